@@ -19,9 +19,82 @@ enum class GitExceptionType {
     Other
 }
 
+/**
+ * Must stay in sync with `ErrorCategory` in the Rust library
+ */
+enum class GitErrorCategory {
+    Other,
+    Auth,
+    Network,
+    Tls,
+    HostKey,
+    NonFastForward,
+    Conflict,
+    RepoState,
+    Io,
+    InvalidInput,
+    Cancelled
+}
+
+/**
+ * Must stay in sync with `Provider` in the Rust library
+ */
+enum class GitProvider {
+    Unknown,
+    GitHub,
+    GitLab,
+    Bitbucket,
+    AzureDevOps,
+    CodeCommit,
+    Gitea,
+    Forgejo,
+    Codeberg,
+    Sourcehut
+}
+
+/**
+ * How the user can fix a failed clone/push, depending on the hosting provider
+ */
+@Keep
+data class GitRemediation(
+    val providerCode: Int,
+    val providerName: String,
+    /**
+     * Kind of credential the provider expects, e.g. "Personal Access Token" or "SSH key"
+     */
+    val credential: String,
+    /**
+     * Page where the credential can be created
+     */
+    val tokenUrl: String?,
+    val scopes: List<String>
+) {
+    val provider: GitProvider
+        get() = GitProvider.entries.getOrElse(providerCode) { GitProvider.Unknown }
+}
+
+/**
+ * Details of the last failed native call, see [takeLastErrorLib]
+ */
+@Keep
+data class GitError(
+    val categoryCode: Int,
+    val message: String,
+    /**
+     * libgit2 error class and code (`git_error_t`, `git_error_code`)
+     */
+    val klass: Int,
+    val code: Int,
+    val remediation: GitRemediation? = null
+) {
+    val category: GitErrorCategory
+        get() = GitErrorCategory.entries.getOrElse(categoryCode) { GitErrorCategory.Other }
+}
+
 class GitException(
     val type: GitExceptionType,
-    message: String?
+    message: String?,
+    val error: GitError? = null
 ) : Exception(getMessage(type, message)) {
 
     companion object {
@@ -34,15 +107,509 @@ class GitException(
     }
 
     constructor(message: String) : this(GitExceptionType.Other, message)
+    constructor(message: String, error: GitError?) : this(GitExceptionType.Other, message, error)
     constructor(type: GitExceptionType) : this(type, null)
 }
 
+/**
+ * Must stay in sync with `IssueKind` in the Rust library
+ */
+enum class GitIntegrityIssueKind {
+    UnreadableObject,
+    BrokenRef,
+    CorruptIndex,
+    DanglingHead
+}
+
+@Keep
+data class GitIntegrityIssue(
+    val kindCode: Int,
+    /**
+     * Object id, ref name, "index" or "HEAD"
+     */
+    val target: String,
+    val detail: String
+) {
+    val kind: GitIntegrityIssueKind
+        get() = GitIntegrityIssueKind.entries.getOrElse(kindCode) { GitIntegrityIssueKind.UnreadableObject }
+}
+
+@Keep
+data class GitContributor(
+    val name: String,
+    val email: String,
+    val commits: Int
+)
+
+/**
+ * Timestamps are in milliseconds
+ */
+@Keep
+data class GitFileHistory(
+    /**
+     * First commit that added the file, renames are followed
+     */
+    val created: Long,
+    /**
+     * Last commit that modified the file
+     */
+    val modified: Long,
+    val lastAuthor: String,
+    val lastAuthorEmail: String
+)
+
+@Keep
+data class GitBlobSize(
+    val path: String,
+    val bytes: Long
+)
+
+/**
+ * What the repository holds; file counts and sizes are taken from HEAD
+ */
+@Keep
+data class GitRepoStats(
+    val commitCount: Int,
+    /**
+     * Most active first
+     */
+    val contributors: List<GitContributor>,
+    val markdownNotes: Int,
+    val textNotes: Int,
+    val otherFiles: Int,
+    val totalBlobBytes: Long,
+    /**
+     * Largest first
+     */
+    val largestBlobs: List<GitBlobSize>,
+    val gitDirBytes: Long,
+    val looseObjects: Int,
+    val packedObjects: Int,
+    val assetBytes: Long
+)
+
+@Keep
+data class GitMaintenanceReport(
+    val objectsPacked: Int,
+    val packsRemoved: Int,
+    val objectsPruned: Int,
+    val bytesBefore: Long,
+    val bytesAfter: Long
+) {
+    val bytesReclaimed: Long
+        get() = (bytesBefore - bytesAfter).coerceAtLeast(0)
+}
+
+/**
+ * Mirrors `NetworkSettings` in the Rust library
+ */
+data class GitNetworkSettings(
+    val connectTimeoutMs: Int = 7000,
+    val timeoutMs: Int = 7000,
+    /**
+     * `http://[user:password@]host:port`, null to use `http.proxy` or the `https_proxy` environment variable
+     */
+    val proxy: String? = null,
+    /**
+     * Retries of a dropped connection, timeout or failed request, 0 to disable
+     */
+    val maxRetries: Int = 2,
+    /**
+     * Delay before the first retry, doubled for each further attempt
+     */
+    val retryDelayMs: Int = 1000
+)
+
+/**
+ * Must stay in sync with `Phase` in the Rust library
+ */
+enum class GitProgressPhase {
+    Receiving,
+    Resolving,
+    Packing,
+    Uploading,
+    Checkout,
+    Merging,
+
+    /**
+     * Waiting before a failed connection is retried, cancelling stops the retries
+     */
+    Retrying
+}
+
+data class GitProgress(
+    val phase: GitProgressPhase,
+    val percent: Int,
+    /**
+     * Bytes transferred so far, 0 for phases without network traffic
+     */
+    val bytes: Long
+)
+
+/**
+ * Must stay in sync with `SignatureStatus` in the Rust library
+ */
+enum class SignatureStatus {
+    Unsigned,
+    Valid,
+    Invalid,
+    UntrustedKey,
+    Unsupported
+}
+
 @Keep
 data class GitLogEntry(
     val hash: String,
     val message: String,
     val author: String,
-    val date: String
+    val date: String,
+    val signatureStatusCode: Int = 0,
+    /**
+     * Principal from the allowed signers, or the key fingerprint for untrusted keys,
+     * see [GitManager.setAllowedSignersFile]
+     */
+    val signer: String? = null,
+    /**
+     * Only filled in with [GitLogQuery.includeFiles]
+     */
+    val files: List<GitChangedFile> = emptyList()
+) {
+    val signatureStatus: SignatureStatus
+        get() = SignatureStatus.entries.getOrElse(signatureStatusCode) { SignatureStatus.Unsupported }
+}
+
+/**
+ * Must stay in sync with `FileStatus` in the Rust library
+ */
+enum class GitFileStatus {
+    Added,
+    Modified,
+    Deleted,
+    Renamed
+}
+
+/**
+ * Must stay in sync with `FileState` in the Rust library
+ */
+enum class GitFileState {
+    New,
+    Modified,
+    Deleted,
+    Renamed,
+    Conflicted,
+    Ignored
+}
+
+/**
+ * A path with uncommitted changes, staged or in the working tree
+ */
+@Keep
+data class GitPathStatus(
+    val path: String,
+    /**
+     * Path before a rename
+     */
+    val oldPath: String?,
+    /**
+     * -1 when nothing is staged
+     */
+    val indexStateCode: Int,
+    /**
+     * -1 when the working tree matches the index
+     */
+    val worktreeStateCode: Int
+) {
+    /**
+     * Staged change compared to HEAD
+     */
+    val indexState: GitFileState?
+        get() = GitFileState.entries.getOrNull(indexStateCode)
+
+    /**
+     * Change in the working tree compared to the index
+     */
+    val worktreeState: GitFileState?
+        get() = GitFileState.entries.getOrNull(worktreeStateCode)
+}
+
+/**
+ * Must stay in sync with `Operation` in the Rust library
+ */
+enum class GitOperation {
+    None,
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+    Bisect,
+    ApplyMailbox
+}
+
+/**
+ * Must stay in sync with `Recovery` in the Rust library
+ */
+enum class GitRecovery {
+    /**
+     * Go back to where the operation started, uncommitted changes are saved in a snapshot
+     */
+    Abort,
+
+    /**
+     * Stage the working tree as the resolution and finish the operation
+     */
+    Continue,
+
+    /**
+     * End the operation but leave the working tree as it is, as uncommitted changes
+     */
+    KeepWorkingTree
+}
+
+/**
+ * An operation left unfinished, e.g. by another git client, and the files it affects
+ */
+@Keep
+data class GitRepoState(
+    val operationCode: Int,
+    val files: List<GitPathStatus>
+) {
+    val operation: GitOperation
+        get() = GitOperation.entries.getOrElse(operationCode) { GitOperation.None }
+
+    val conflicts: List<GitPathStatus>
+        get() = files.filter { it.indexState == GitFileState.Conflicted }
+}
+
+/**
+ * Must stay in sync with `JournalOp` in the Rust library
+ */
+enum class GitJournalOperation {
+    Commit,
+    Pull,
+    Push,
+    Sync
+}
+
+/**
+ * Must stay in sync with `ReplayOutcome` in the Rust library
+ */
+enum class GitReplayOutcome {
+    /**
+     * Nothing was left half done
+     */
+    Discarded,
+
+    /**
+     * The remaining steps were run
+     */
+    Resumed,
+
+    /**
+     * The repository is back where the operation started
+     */
+    RolledBack
+}
+
+/**
+ * An operation of the app that was interrupted, e.g. because the process died, and
+ * what replaying it does, see [GitManager.getPendingJournal]
+ */
+@Keep
+data class GitJournalReplay(
+    val operationCode: Int,
+    val outcomeCode: Int
+) {
+    val operation: GitJournalOperation?
+        get() = GitJournalOperation.entries.getOrNull(operationCode)
+
+    val outcome: GitReplayOutcome?
+        get() = GitReplayOutcome.entries.getOrNull(outcomeCode)
+}
+
+/**
+ * A file changed by a commit, compared to its first parent
+ */
+@Keep
+data class GitChangedFile(
+    val path: String,
+    /**
+     * Path before a rename
+     */
+    val oldPath: String?,
+    val statusCode: Int,
+    /**
+     * Lines added and removed, 0 for binary files
+     */
+    val additions: Int,
+    val deletions: Int
+) {
+    val status: GitFileStatus
+        get() = GitFileStatus.entries.getOrElse(statusCode) { GitFileStatus.Modified }
+}
+
+@Keep
+data class GitPerson(
+    val name: String,
+    val email: String,
+    val timeMs: Long,
+    /**
+     * Offset of the timezone from UTC
+     */
+    val offsetMinutes: Int
+)
+
+/**
+ * The uncommitted changes saved before a sync, pull, cleanup or checkout overwrote them
+ */
+@Keep
+data class GitSnapshot(
+    /**
+     * Time the snapshot was taken in milliseconds, identifies the snapshot
+     */
+    val id: Long,
+    val hash: String,
+    /**
+     * Operation that was about to run, e.g. `sync`
+     */
+    val reason: String,
+    /**
+     * Commit HEAD pointed to, kept reachable by the snapshot
+     */
+    val head: String?,
+    val files: List<GitChangedFile>
+)
+
+@Keep
+data class GitStashEntry(
+    /**
+     * Position in the stash list, 0 is the newest
+     */
+    val index: Int,
+    val hash: String,
+    val message: String,
+    val timeMs: Long,
+    val files: List<GitChangedFile>
+)
+
+@Keep
+data class GitReflogEntry(
+    /**
+     * Position in the reflog, 0 is the newest
+     */
+    val index: Int,
+    /**
+     * All zeros when the ref was created
+     */
+    val oldId: String,
+    val newId: String,
+    val message: String,
+    val timeMs: Long,
+)
+
+@Keep
+data class GitHistoryMatch(
+    /**
+     * Commit that has the line, pass it to [GitManager.checkoutPath] to restore the file
+     */
+    val hash: String,
+    val timeMs: Long,
+    val path: String,
+    /**
+     * Starting at 1
+     */
+    val lineNumber: Int,
+    val line: String
+)
+
+@Keep
+data class GitSearchResult(
+    val path: String,
+    /**
+     * Higher is better, only meaningful compared to the other results
+     */
+    val score: Double,
+    /**
+     * Line of the first match, starting at 1
+     */
+    val line: Int,
+    /**
+     * Text around the first match on a single line
+     */
+    val snippet: String
+)
+
+/**
+ * A line of the commit graph from the row that holds it to the next row
+ */
+@Keep
+data class GitGraphEdge(
+    val from: Int,
+    val to: Int
+)
+
+/**
+ * One commit of the graph, in the same order as the log
+ */
+@Keep
+data class GitGraphRow(
+    val hash: String,
+    val parents: List<String>,
+    /**
+     * Lane of the commit
+     */
+    val column: Int,
+    /**
+     * Lines towards the parents
+     */
+    val edges: List<GitGraphEdge>
+)
+
+@Keep
+data class GitCommitDetails(
+    val hash: String,
+    val parents: List<String>,
+    val message: String,
+    val author: GitPerson,
+    val committer: GitPerson,
+    val files: List<GitChangedFile>
+)
+
+/**
+ * Which commits [GitManager.queryGitLog] returns, all filters must match
+ */
+data class GitLogQuery(
+    val limit: Int = 20,
+    /**
+     * Hash of the last entry of the previous page, the log continues after it. Fails when the
+     * commit is no longer in the log, e.g. after a sync dropped it
+     */
+    val after: String? = null,
+    /**
+     * Number of matching commits to skip
+     */
+    val offset: Int = 0,
+    /**
+     * Case insensitive part of the author name or email
+     */
+    val author: String? = null,
+    /**
+     * Commit time range in milliseconds, both ends included
+     */
+    val sinceMs: Long? = null,
+    val untilMs: Long? = null,
+    /**
+     * File or folder the commit has to change
+     */
+    val path: String? = null,
+    /**
+     * Case insensitive part of the commit message
+     */
+    val message: String? = null,
+    val noMerges: Boolean = false,
+    /**
+     * Include the changed files of each commit
+     */
+    val includeFiles: Boolean = false
 )
 
 class GitManager {
@@ -83,6 +650,15 @@ class GitManager {
         }
     }
 
+    /**
+     * Build an exception from the details of the last native error,
+     * falling back to the raw return code when there are none
+     */
+    private fun nativeException(res: Int, message: (String) -> String): GitException {
+        val error = takeLastErrorLib()
+        return GitException(message(error?.message ?: res.toString()), error)
+    }
+
 
     suspend fun createRepo(repoPath: String): Result<Unit> = safelyAccessLibGit2 {
         Log.d(TAG, "create repo: $repoPath")
@@ -91,7 +667,7 @@ class GitManager {
 
         val res = createRepoLib(repoPath)
         if (res < 0) {
-            throw GitException(uiHelper.getString(R.string.error_create_repo, res.toString()))
+            throw nativeException(res) { uiHelper.getString(R.string.error_create_repo, it) }
         }
         isRepoInitialized = true
     }
@@ -103,19 +679,33 @@ class GitManager {
 
         val res = openRepoLib(repoPath)
         if (res < 0) {
-            throw GitException(uiHelper.getString(R.string.error_open_repo, res))
+            throw nativeException(res) { uiHelper.getString(R.string.error_open_repo, it) }
         }
         isRepoInitialized = true
     }
 
-    private var actualCb: ((Int) -> Boolean)? = null
+    private var actualCb: ((GitProgress) -> Boolean)? = null
 
     /**
-     * This function is called from native code
+     * This function is called from native code, returning false cancels the operation
      */
     @Keep
-    fun progressCb(progress: Int): Boolean {
-        return actualCb?.invoke(progress) != false
+    fun progressCb(phase: Int, progress: Int, bytes: Long): Boolean {
+        val cb = actualCb ?: return true
+        val progressPhase = GitProgressPhase.entries.getOrNull(phase) ?: return true
+        return cb(GitProgress(progressPhase, progress, bytes))
+    }
+
+    private inline fun <T> withProgress(
+        progressCallback: ((GitProgress) -> Boolean)?,
+        block: () -> T
+    ): T {
+        actualCb = progressCallback
+        try {
+            return block()
+        } finally {
+            actualCb = null
+        }
     }
 
     suspend fun cloneRepo(
@@ -128,19 +718,19 @@ class GitManager {
 
         if (isRepoInitialized) throw GitException(GitExceptionType.RepoAlreadyInit)
 
-        actualCb = progressCallback
-
-        val res = cloneRepoLib(
-            repoPath = repoPath,
-            remoteUrl = repoUrl,
-            cred = cred,
-            progressCallback = this
-        )
-
-        actualCb = null
+        val res = withProgress({
+            it.phase != GitProgressPhase.Receiving || progressCallback(it.percent)
+        }) {
+            cloneRepoLib(
+                repoPath = repoPath,
+                remoteUrl = repoUrl,
+                cred = cred,
+                progressCallback = this
+            )
+        }
 
         if (res < 0) {
-            throw GitException(uiHelper.getString(R.string.error_clone_repo, res))
+            throw nativeException(res) { uiHelper.getString(R.string.error_clone_repo, it) }
         }
 
         isRepoInitialized = true
@@ -158,11 +748,96 @@ class GitManager {
         if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
         val res = isChangeLib()
         if (res < 0) {
-            throw GitException("Failed to check changes: $res")
+            throw nativeException(res) { "Failed to check changes: $it" }
         }
         res == 1
     }
 
+    /**
+     * Every path with uncommitted changes, below [folder] if given, sorted by path.
+     * Ignored files are only listed with [includeIgnored].
+     */
+    suspend fun getStatus(
+        folder: String? = null,
+        includeIgnored: Boolean = false
+    ): Result<List<GitPathStatus>> = safelyAccessLibGit2 {
+        Log.d(TAG, "getStatus: ${folder ?: "/"}")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val statuses = mutableListOf<GitPathStatus>()
+
+        val res = getStatusLib(statuses, folder, includeIgnored)
+
+        if (res < 0) {
+            throw nativeException(res) { "getStatusLib error $it" }
+        }
+        statuses
+    }
+
+    /**
+     * The operation left unfinished in the repository, if any, and the changed files.
+     * Opening a repository never resolves it, see [recoverRepo]. The operations of the app
+     * itself are finished or undone with [replayJournal], see [getPendingJournal].
+     */
+    suspend fun getRepoState(): Result<GitRepoState> = safelyAccessLibGit2 {
+        Log.d(TAG, "getRepoState")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        getRepoStateLib() ?: throw nativeException(-1) { "getRepoStateLib error $it" }
+    }
+
+    /**
+     * The operation of the app that was interrupted and what [replayJournal] would do with it,
+     * null if there is none. Opening a repository leaves it alone.
+     */
+    suspend fun getPendingJournal(): Result<GitJournalReplay?> = safelyAccessLibGit2 {
+        Log.d(TAG, "getPendingJournal")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        getPendingJournalLib() ?: takeLastErrorLib()?.let {
+            throw GitException("getPendingJournalLib error ${it.message}", it)
+        }
+    }
+
+    /**
+     * Finish or roll back the interrupted operation of the app, returns what was done.
+     * A roll back fails when the branch moved on since, see [discardJournal].
+     */
+    suspend fun replayJournal(): Result<GitJournalReplay?> = safelyAccessLibGit2 {
+        Log.d(TAG, "replayJournal")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        replayJournalLib() ?: takeLastErrorLib()?.let {
+            throw GitException("replayJournalLib error ${it.message}", it)
+        }
+    }
+
+    /**
+     * Forget the interrupted operation of the app and keep the repository as it is
+     */
+    suspend fun discardJournal(): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "discardJournal")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val res = discardJournalLib()
+        if (res < 0) {
+            throw nativeException(res) { "discardJournalLib error $it" }
+        }
+    }
+
+    /**
+     * Resolve the unfinished operation as chosen by the user, returns the new state.
+     * A rebase continued with [GitRecovery.Continue] may stop again at the next conflict.
+     */
+    suspend fun recoverRepo(recovery: GitRecovery, author: GitAuthor): Result<GitRepoState> =
+        safelyAccessLibGit2 {
+            Log.d(TAG, "recoverRepo: $recovery")
+            if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+            recoverRepoLib(recovery.ordinal, author.name, author.email)
+                ?: throw nativeException(-1) { "recoverRepoLib error $it" }
+        }
+
     suspend fun commitAll(author: GitAuthor, message: String): Result<Unit> = safelyAccessLibGit2 {
         Log.d(TAG, "commit all: ${author.name}")
         if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
@@ -170,7 +845,7 @@ class GitManager {
         var res = isChangeLib()
 
         if (res < 0) {
-            throw GitException(uiHelper.getString(R.string.error_commit_file_change, res))
+            throw nativeException(res) { uiHelper.getString(R.string.error_commit_file_change, it) }
         }
 
         if (res == 0) {
@@ -181,28 +856,163 @@ class GitManager {
 
         res = commitAllLib(author.name, author.email, message)
         if (res < 0) {
-            throw GitException(uiHelper.getString(R.string.error_commit_repo, res.toString()))
+            throw nativeException(res) { uiHelper.getString(R.string.error_commit_repo, it) }
+        }
+
+    }
+
+    /**
+     * Commit after each burst of edits instead of after every edit: changes reported with
+     * [notifyFileChanged] are committed once none arrived for [quietPeriodMs]. With [amend]
+     * the previous auto-commit is amended while it is not pushed. Calling it again applies
+     * the new settings.
+     */
+    suspend fun startAutoCommit(
+        author: GitAuthor,
+        quietPeriodMs: Long = 5000,
+        amend: Boolean = true
+    ): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "startAutoCommit: ${quietPeriodMs}ms, amend=$amend")
+
+        startAutoCommitLib(quietPeriodMs, amend, author.name, author.email)
+    }
+
+    /**
+     * Commit the pending changes and stop committing automatically
+     */
+    suspend fun stopAutoCommit(): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "stopAutoCommit")
+
+        val res = stopAutoCommitLib()
+        if (res < 0) {
+            throw nativeException(res) { "stopAutoCommitLib error $it" }
         }
+    }
+
+    /**
+     * Report an edited, created or deleted note to the auto-commit scheduler, [path] is
+     * relative to the repository root. Only reported files are auto-committed, a folder
+     * stands for all files below it, e.g. after it was moved.
+     * Does not wait for other git operations.
+     */
+    fun notifyFileChanged(path: String) {
+        if (!isLibInitialized) return
 
+        notifyFileChangedLib(path)
+    }
+
+    /**
+     * Commit the pending changes without waiting for the quiet period, e.g. when the app
+     * goes to the background. Push and sync do this on their own.
+     */
+    suspend fun flushAutoCommit(): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "flushAutoCommit")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val res = flushAutoCommitLib()
+        if (res < 0) {
+            throw nativeException(res) { "flushAutoCommitLib error $it" }
+        }
     }
 
-    suspend fun sync(cred: Cred?): Result<Unit> = safelyAccessLibGit2 {
+    /**
+     * Reset the branch to the remote and bring back the uncommitted changes. When they
+     * conflict with the remote ones it fails with [GitErrorCategory.Conflict], the branch is
+     * synced then and the changes are kept in the stash list, see [listStashes].
+     */
+    suspend fun sync(
+        cred: Cred?,
+        progressCallback: ((GitProgress) -> Boolean)? = null
+    ): Result<Unit> = safelyAccessLibGit2 {
         Log.d(TAG, "sync: $cred")
         if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
 
-        val res = syncLib(cred)
+        val res = withProgress(progressCallback) { syncLib(cred, this) }
+        if (res < 0) {
+            throw nativeException(res) { uiHelper.getString(R.string.error_sync_repo, it) }
+        }
+    }
+
+    /**
+     * Sign new commits with the given OpenSSH private key (SSH signature, like `gpg.format=ssh`).
+     * Pass null to disable signing.
+     */
+    suspend fun setSigningKey(privateKey: String?): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "setSigningKey: enabled=${privateKey != null}")
+
+        val res = setSigningKeyLib(privateKey)
+        if (res < 0) {
+            throw nativeException(res) { "Can't set signing key: $it" }
+        }
+    }
+
+    /**
+     * File with the trusted signing keys in the `ssh-keygen` allowed signers format, kept
+     * outside of the repository. Pass null to use `gpg.ssh.allowedSignersFile` of the local
+     * repository config. Files in the working tree are never trusted.
+     */
+    suspend fun setAllowedSignersFile(path: String?): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "setAllowedSignersFile: $path")
+
+        setAllowedSignersFileLib(path)
+    }
+
+    /**
+     * Timeouts, proxy and retries used by clone, pull, push and sync.
+     */
+    suspend fun setNetworkSettings(settings: GitNetworkSettings): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "setNetworkSettings: $settings")
+
+        val res = setNetworkSettingsLib(
+            connectTimeoutMs = settings.connectTimeoutMs,
+            timeoutMs = settings.timeoutMs,
+            proxy = settings.proxy,
+            maxRetries = settings.maxRetries,
+            retryDelayMs = settings.retryDelayMs
+        )
+        if (res < 0) {
+            throw nativeException(res) { "Can't set network settings: $it" }
+        }
+    }
+
+    /**
+     * How many snapshots of uncommitted changes are kept, see [listSnapshots]. At least one
+     * has to be kept, the newest one is never pruned.
+     */
+    suspend fun setSnapshotRetention(
+        maxCount: Int = 50,
+        maxAgeSecs: Long = 30L * 24 * 60 * 60
+    ): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "setSnapshotRetention: $maxCount, ${maxAgeSecs}s")
+
+        val res = setSnapshotRetentionLib(maxCount, maxAgeSecs)
         if (res < 0) {
-            throw GitException(uiHelper.getString(R.string.error_sync_repo, res.toString()))
+            throw nativeException(res) { "Can't set snapshot retention: $it" }
         }
     }
 
-    suspend fun checkoutPath(path: String): Result<Unit> = safelyAccessLibGit2 {
-        Log.d(TAG, "checkout path: $path")
+    /**
+     * Before each push, collapse consecutive unpushed commits of the same author made within
+     * [windowSecs] of each other into one commit. Commits already on the remote-tracking branch
+     * are never rewritten, and nothing is squashed while the branch diverged from the remote, as
+     * the push would be rejected. Pass null to disable it.
+     */
+    suspend fun setSquashBeforePush(windowSecs: Long?): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "setSquashBeforePush: ${windowSecs}s")
+
+        setSquashWindowLib(windowSecs ?: 0)
+    }
+
+    /**
+     * Restore [path] from HEAD, or from [commit] to bring back an older version
+     */
+    suspend fun checkoutPath(path: String, commit: String? = null): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "checkout path: $path at ${commit ?: "HEAD"}")
         if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
 
-        val res = checkoutPathLib(path)
+        val res = checkoutPathLib(path, commit)
         if (res < 0) {
-            throw GitException("Checkout path failed: $res")
+            throw nativeException(res) { "Checkout path failed: $it" }
         }
     }
 
@@ -213,69 +1023,106 @@ class GitManager {
         currentSignatureLib()
     }.getOrNull()?.let { GitAuthor(name = it.first, email = it.second) }
 
-    suspend fun push(cred: Cred?): Result<Unit> = safelyAccessLibGit2 {
+    suspend fun push(
+        cred: Cred?,
+        progressCallback: ((GitProgress) -> Boolean)? = null
+    ): Result<Unit> = safelyAccessLibGit2 {
         Log.d(TAG, "push: $cred")
         if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
-        val res = pushLib(cred)
+        val res = withProgress(progressCallback) { pushLib(cred, this) }
 
         if (res < 0) {
             Log.d(TAG, "push: $res")
             
+            val error = takeLastErrorLib()
+
             // If push failed due to non-fast-forward, try sync instead
-            if (res == -11) { // GIT_ENONFASTFORWARD
+            if (error?.category == GitErrorCategory.NonFastForward || res == -11) { // GIT_ENONFASTFORWARD
                 Log.d(TAG, "push failed with non-fast-forward, attempting sync")
-                val syncRes = syncLib(cred)
+                val syncRes = withProgress(progressCallback) { syncLib(cred, this) }
                 if (syncRes < 0) {
-                    val msg = uiHelper.getString(R.string.error_sync_repo, syncRes.toString())
-                    Log.d(TAG, "sync: $msg")
-                    throw Exception(msg)
+                    val e = nativeException(syncRes) { uiHelper.getString(R.string.error_sync_repo, it) }
+                    Log.d(TAG, "sync: ${e.message}")
+                    throw e
                 }
                 // Sync succeeded, return success
                 return@safelyAccessLibGit2
             }
             
-            val msg = uiHelper.getString(R.string.error_push_repo, res.toString())
+            val msg = uiHelper.getString(R.string.error_push_repo, error?.message ?: res.toString())
             Log.d(TAG, "push: $msg")
-            throw Exception(uiHelper.getString(R.string.error_push_repo, res.toString()))
+            throw GitException(msg, error)
         }
 
     }
 
-    suspend fun pull(cred: Cred?, author: GitAuthor): Result<Unit> = safelyAccessLibGit2 {
+    suspend fun pull(
+        cred: Cred?,
+        author: GitAuthor,
+        progressCallback: ((GitProgress) -> Boolean)? = null
+    ): Result<Unit> = safelyAccessLibGit2 {
         Log.d(TAG, "pull: $cred")
         if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
 
-        val res = pullLib(cred, author.name, author.email)
+        val res = withProgress(progressCallback) { pullLib(cred, author.name, author.email, this) }
 
         if (res < 0) {
             Log.d(TAG, "pull: $res")
             
+            val error = takeLastErrorLib()
+
             // If pull failed due to unmerged files, try sync instead
             if (res == -3) { // GIT_EUNMERGED
                 Log.d(TAG, "pull failed with unmerged files, attempting sync")
-                val syncRes = syncLib(cred)
+                val syncRes = withProgress(progressCallback) { syncLib(cred, this) }
                 if (syncRes < 0) {
-                    val msg = uiHelper.getString(R.string.error_sync_repo, syncRes.toString())
-                    Log.d(TAG, "sync: $msg")
-                    throw Exception(msg)
+                    val e = nativeException(syncRes) { uiHelper.getString(R.string.error_sync_repo, it) }
+                    Log.d(TAG, "sync: ${e.message}")
+                    throw e
                 }
                 // Sync succeeded, return success
                 return@safelyAccessLibGit2
             }
             
-            throw Exception(uiHelper.getString(R.string.error_pull_repo, res.toString()))
+            throw GitException(
+                uiHelper.getString(R.string.error_pull_repo, error?.message ?: res.toString()),
+                error
+            )
         }
     }
 
-    suspend fun getTimestamps(): Result<HashMap<String, Long>> = safelyAccessLibGit2 {
-        Log.d(TAG, "getTimestamps")
+    /**
+     * @param countRenames whether renaming or moving a file without changing it counts as a modification
+     */
+    suspend fun getTimestamps(
+        countRenames: Boolean = true
+    ): Result<HashMap<String, Long>> = safelyAccessLibGit2 {
+        Log.d(TAG, "getTimestamps: countRenames=$countRenames")
 
         val h: HashMap<String, Long> = HashMap()
 
-        val res = getTimestampsLib(h)
+        val res = getTimestampsLib(h, countRenames)
+
+        if (res < 0) {
+            throw nativeException(res) { "getTimestampsLib error $it" }
+        }
+        h
+    }
+
+    /**
+     * @param countRenames see [getTimestamps]
+     */
+    suspend fun getFileHistory(
+        countRenames: Boolean = true
+    ): Result<HashMap<String, GitFileHistory>> = safelyAccessLibGit2 {
+        Log.d(TAG, "getFileHistory: countRenames=$countRenames")
+
+        val h: HashMap<String, GitFileHistory> = HashMap()
+
+        val res = getFileHistoryLib(h, countRenames)
 
         if (res < 0) {
-            throw Exception("getTimestampsLib error $res")
+            throw nativeException(res) { "getFileHistoryLib error $it" }
         }
         h
     }
@@ -288,11 +1135,343 @@ class GitManager {
         val res = getGitLogLib(log, limit)
 
         if (res < 0) {
-            throw Exception("getGitLogLib error $res")
+            throw nativeException(res) { "getGitLogLib error $it" }
         }
         log
     }
 
+    suspend fun queryGitLog(query: GitLogQuery): Result<List<GitLogEntry>> = safelyAccessLibGit2 {
+        Log.d(TAG, "queryGitLog: $query")
+
+        val log = mutableListOf<GitLogEntry>()
+
+        val res = queryGitLogLib(
+            log = log,
+            limit = query.limit,
+            after = query.after,
+            offset = query.offset,
+            author = query.author,
+            sinceMs = query.sinceMs ?: Long.MIN_VALUE,
+            untilMs = query.untilMs ?: Long.MAX_VALUE,
+            path = query.path,
+            message = query.message,
+            noMerges = query.noMerges,
+            includeFiles = query.includeFiles
+        )
+
+        if (res < 0) {
+            throw nativeException(res) { "queryGitLogLib error $it" }
+        }
+        log
+    }
+
+    /**
+     * Lanes for the log entries `offset until offset + limit`, to draw the branches and merges
+     */
+    suspend fun getGitGraph(
+        offset: Int = 0,
+        limit: Int = 20
+    ): Result<List<GitGraphRow>> = safelyAccessLibGit2 {
+        Log.d(TAG, "getGitGraph: offset=$offset, limit=$limit")
+
+        val rows = mutableListOf<GitGraphRow>()
+
+        val res = getGitGraphLib(rows, offset, limit)
+
+        if (res < 0) {
+            throw nativeException(res) { "getGitGraphLib error $it" }
+        }
+        rows
+    }
+
+    /**
+     * Full text search over the notes, best match first.
+     *
+     * Words match whole words, `word*` matches words starting with `word` and `"some words"`
+     * matches the words next to each other. [folder] limits the search to the notes below it.
+     * The index is updated after commits and pulls, call [updateSearchIndex] after saving a note.
+     */
+    suspend fun search(
+        query: String,
+        folder: String? = null,
+        limit: Int = 50
+    ): Result<List<GitSearchResult>> = safelyAccessLibGit2 {
+        Log.d(TAG, "search: $query in ${folder ?: "/"}")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val results = mutableListOf<GitSearchResult>()
+
+        val res = searchLib(results, query, folder, limit)
+
+        if (res < 0) {
+            throw nativeException(res) { "searchLib error $it" }
+        }
+        results
+    }
+
+    /**
+     * Find [pattern] in the older versions of the notes, including deleted ones, newest first.
+     *
+     * [path] limits the search to a file or the notes below a folder. Each line is reported
+     * once, for the newest commit that has it.
+     */
+    suspend fun searchHistory(
+        pattern: String,
+        ignoreCase: Boolean = true,
+        path: String? = null,
+        sinceMs: Long? = null,
+        untilMs: Long? = null,
+        limit: Int = 100
+    ): Result<List<GitHistoryMatch>> = safelyAccessLibGit2 {
+        Log.d(TAG, "searchHistory: $pattern in ${path ?: "/"}")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val matches = mutableListOf<GitHistoryMatch>()
+
+        val res = searchHistoryLib(
+            matches,
+            pattern,
+            ignoreCase,
+            path,
+            sinceMs = sinceMs ?: Long.MIN_VALUE,
+            untilMs = untilMs ?: Long.MAX_VALUE,
+            limit
+        )
+
+        if (res < 0) {
+            throw nativeException(res) { "searchHistoryLib error $it" }
+        }
+        matches
+    }
+
+    /**
+     * Re-index the notes changed since the last update, returns the number of files looked at
+     */
+    suspend fun updateSearchIndex(): Result<Int> = safelyAccessLibGit2 {
+        Log.d(TAG, "updateSearchIndex")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val res = updateSearchIndexLib()
+
+        if (res < 0) {
+            throw nativeException(res) { "updateSearchIndexLib error $it" }
+        }
+        res
+    }
+
+    suspend fun getCommitDetails(hash: String): Result<GitCommitDetails> = safelyAccessLibGit2 {
+        Log.d(TAG, "getCommitDetails: $hash")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        getCommitDetailsLib(hash) ?: throw nativeException(-1) { "getCommitDetailsLib error $it" }
+    }
+
+    /**
+     * Snapshots taken before an operation overwrote uncommitted changes, newest first
+     */
+    suspend fun listSnapshots(): Result<List<GitSnapshot>> = safelyAccessLibGit2 {
+        Log.d(TAG, "listSnapshots")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val snapshots = mutableListOf<GitSnapshot>()
+
+        val res = listSnapshotsLib(snapshots)
+
+        if (res < 0) {
+            throw nativeException(res) { "listSnapshotsLib error $it" }
+        }
+        snapshots
+    }
+
+    /**
+     * The changes saved in a snapshot as a unified diff
+     */
+    suspend fun showSnapshot(id: Long): Result<String> = safelyAccessLibGit2 {
+        Log.d(TAG, "showSnapshot: $id")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        showSnapshotLib(id) ?: throw nativeException(-1) { "showSnapshotLib error $it" }
+    }
+
+    /**
+     * Write the files of a snapshot back as uncommitted changes. The current changes are
+     * saved in a new snapshot first.
+     */
+    suspend fun restoreSnapshot(id: Long): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "restoreSnapshot: $id")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val res = restoreSnapshotLib(id)
+
+        if (res < 0) {
+            throw nativeException(res) { "restoreSnapshotLib error $it" }
+        }
+    }
+
+    suspend fun deleteSnapshot(id: Long): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "deleteSnapshot: $id")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val res = deleteSnapshotLib(id)
+
+        if (res < 0) {
+            throw nativeException(res) { "deleteSnapshotLib error $it" }
+        }
+    }
+
+    /**
+     * Changes put aside with `git stash` or by [sync], newest first
+     */
+    suspend fun listStashes(): Result<List<GitStashEntry>> = safelyAccessLibGit2 {
+        Log.d(TAG, "listStashes")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val stashes = mutableListOf<GitStashEntry>()
+
+        val res = listStashesLib(stashes)
+
+        if (res < 0) {
+            throw nativeException(res) { "listStashesLib error $it" }
+        }
+        stashes
+    }
+
+    /**
+     * The changes of a stash as a unified diff
+     */
+    suspend fun showStash(index: Int): Result<String> = safelyAccessLibGit2 {
+        Log.d(TAG, "showStash: $index")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        showStashLib(index) ?: throw nativeException(-1) { "showStashLib error $it" }
+    }
+
+    /**
+     * Bring back the changes of a stash, returns the files left with conflict markers.
+     *
+     * With [pop] the stash is dropped unless there were conflicts. Fails without changing
+     * anything when a file with uncommitted changes would be overwritten.
+     */
+    suspend fun applyStash(index: Int, pop: Boolean = false): Result<List<String>> =
+        safelyAccessLibGit2 {
+            Log.d(TAG, "applyStash: $index, pop=$pop")
+            if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+            val conflicts = mutableListOf<String>()
+
+            val res = applyStashLib(conflicts, index, pop)
+
+            if (res < 0) {
+                throw nativeException(res) { "applyStashLib error $it" }
+            }
+            conflicts
+        }
+
+    suspend fun dropStash(index: Int): Result<Unit> = safelyAccessLibGit2 {
+        Log.d(TAG, "dropStash: $index")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val res = dropStashLib(index)
+
+        if (res < 0) {
+            throw nativeException(res) { "dropStashLib error $it" }
+        }
+    }
+
+    /**
+     * Moves of a local branch, or of HEAD when [branch] is null, newest first
+     */
+    suspend fun getReflog(branch: String? = null): Result<List<GitReflogEntry>> =
+        safelyAccessLibGit2 {
+            Log.d(TAG, "getReflog: $branch")
+            if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+            val entries = mutableListOf<GitReflogEntry>()
+
+            val res = getReflogLib(entries, branch)
+
+            if (res < 0) {
+                throw nativeException(res) { "getReflogLib error $it" }
+            }
+            entries
+        }
+
+    /**
+     * Move a branch, or HEAD when [branch] is null, back to where the reflog entry
+     * at [index] left it. The current branch is reset with its working tree, other
+     * branches are only moved, after saving a snapshot either way.
+     */
+    suspend fun resetToReflog(branch: String? = null, index: Int): Result<Unit> =
+        safelyAccessLibGit2 {
+            Log.d(TAG, "resetToReflog: $branch@{$index}")
+            if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+            val res = resetToReflogLib(branch, index)
+
+            if (res < 0) {
+                throw nativeException(res) { "resetToReflogLib error $it" }
+            }
+        }
+
+
+    /**
+     * Check objects, refs, HEAD and the index, without changing anything.
+     */
+    suspend fun verifyRepo(): Result<List<GitIntegrityIssue>> = safelyAccessLibGit2 {
+        Log.d(TAG, "verifyRepo")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val issues = mutableListOf<GitIntegrityIssue>()
+        val res = verifyRepoLib(issues)
+        if (res < 0) {
+            throw nativeException(res) { "verifyRepoLib error $it" }
+        }
+        issues
+    }
+
+    /**
+     * Fix what [verifyRepo] reports, restoring damaged objects from the remote.
+     * Uncommitted changes in the working tree are kept, local branches that can't be
+     * restored are left as they are and reported.
+     *
+     * @return the issues that could not be fixed
+     */
+    suspend fun repairRepo(cred: Cred?): Result<List<GitIntegrityIssue>> = safelyAccessLibGit2 {
+        Log.d(TAG, "repairRepo")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val remaining = mutableListOf<GitIntegrityIssue>()
+        val res = repairRepoLib(cred, remaining)
+        if (res < 0) {
+            throw nativeException(res) { "repairRepoLib error $it" }
+        }
+        remaining
+    }
+
+    /**
+     * Pack loose objects and prune unreachable objects older than [gracePeriodDays].
+     * Skipped while there are fewer than [minLooseObjects] loose objects, so it is cheap
+     * enough to call after each sync.
+     */
+    suspend fun runMaintenance(
+        gracePeriodDays: Int = 14,
+        minLooseObjects: Int = 0
+    ): Result<GitMaintenanceReport> = safelyAccessLibGit2 {
+        Log.d(TAG, "runMaintenance: gracePeriodDays=$gracePeriodDays, minLooseObjects=$minLooseObjects")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        val report = runMaintenanceLib(gracePeriodDays * 24L * 3600L, minLooseObjects)
+            ?: throw nativeException(-1) { "Maintenance failed: $it" }
+        Log.d(TAG, "runMaintenance: $report")
+        report
+    }
+
+    suspend fun repoStats(assetDir: String): Result<GitRepoStats> = safelyAccessLibGit2 {
+        Log.d(TAG, "repoStats: assetDir=$assetDir")
+        if (!isRepoInitialized) throw GitException(GitExceptionType.RepoNotInit)
+
+        repoStatsLib(assetDir) ?: throw nativeException(-1) { "repoStatsLib error $it" }
+    }
 
     fun closeRepoWithoutLock() {
         if (isRepoInitialized) closeRepoLib()
@@ -311,6 +1490,11 @@ class GitManager {
 
 }
 
+/**
+ * Returns and clears the details of the last error reported by a native call
+ */
+private external fun takeLastErrorLib(): GitError?
+
 private external fun initLib(
     homePath: String = MyApp.appModule.context.filesDir.toPath().toString()
 ): Int
@@ -330,11 +1514,15 @@ private external fun cloneRepoLib(
 private external fun lastCommitLib(): String?
 
 private external fun commitAllLib(name: String, email: String, message: String): Int
-private external fun checkoutPathLib(path: String): Int
+private external fun checkoutPathLib(path: String, commit: String?): Int
+private external fun startAutoCommitLib(quietPeriodMs: Long, amend: Boolean, name: String, email: String)
+private external fun stopAutoCommitLib(): Int
+private external fun notifyFileChangedLib(path: String)
+private external fun flushAutoCommitLib(): Int
 private external fun currentSignatureLib(): Pair<String, String>?
-private external fun pushLib(cred: Cred?): Int
-private external fun pullLib(cred: Cred?, name: String, email: String): Int
-private external fun syncLib(cred: Cred?): Int
+private external fun pushLib(cred: Cred?, progressCallback: GitManager): Int
+private external fun pullLib(cred: Cred?, name: String, email: String, progressCallback: GitManager): Int
+private external fun syncLib(cred: Cred?, progressCallback: GitManager): Int
 
 private external fun freeLib()
 
@@ -342,9 +1530,81 @@ private external fun freeLib()
 private external fun closeRepoLib()
 
 private external fun isChangeLib(): Int
+private external fun getRepoStateLib(): GitRepoState?
+private external fun getPendingJournalLib(): GitJournalReplay?
+private external fun replayJournalLib(): GitJournalReplay?
+private external fun discardJournalLib(): Int
+private external fun recoverRepoLib(recovery: Int, name: String, email: String): GitRepoState?
+private external fun getStatusLib(
+    statuses: MutableList<GitPathStatus>,
+    folder: String?,
+    includeIgnored: Boolean
+): Int
 
-private external fun getTimestampsLib(timestamps: HashMap<String, Long>): Int
+private external fun getTimestampsLib(timestamps: HashMap<String, Long>, countRenames: Boolean): Int
+private external fun getFileHistoryLib(
+    files: HashMap<String, GitFileHistory>,
+    countRenames: Boolean
+): Int
 
 private external fun getGitLogLib(log: MutableList<GitLogEntry>, limit: Int): Int
+private external fun queryGitLogLib(
+    log: MutableList<GitLogEntry>,
+    limit: Int,
+    after: String?,
+    offset: Int,
+    author: String?,
+    sinceMs: Long,
+    untilMs: Long,
+    path: String?,
+    message: String?,
+    noMerges: Boolean,
+    includeFiles: Boolean
+): Int
+private external fun getCommitDetailsLib(hash: String): GitCommitDetails?
+private external fun listSnapshotsLib(snapshots: MutableList<GitSnapshot>): Int
+private external fun showSnapshotLib(id: Long): String?
+private external fun restoreSnapshotLib(id: Long): Int
+private external fun deleteSnapshotLib(id: Long): Int
+private external fun listStashesLib(stashes: MutableList<GitStashEntry>): Int
+private external fun showStashLib(index: Int): String?
+private external fun applyStashLib(conflicts: MutableList<String>, index: Int, pop: Boolean): Int
+private external fun dropStashLib(index: Int): Int
+private external fun getReflogLib(entries: MutableList<GitReflogEntry>, refname: String?): Int
+private external fun resetToReflogLib(refname: String?, index: Int): Int
+private external fun searchLib(
+    results: MutableList<GitSearchResult>,
+    query: String,
+    folder: String?,
+    limit: Int
+): Int
+private external fun updateSearchIndexLib(): Int
+private external fun searchHistoryLib(
+    matches: MutableList<GitHistoryMatch>,
+    pattern: String,
+    ignoreCase: Boolean,
+    path: String?,
+    sinceMs: Long,
+    untilMs: Long,
+    limit: Int
+): Int
+private external fun getGitGraphLib(rows: MutableList<GitGraphRow>, offset: Int, limit: Int): Int
+
+private external fun verifyRepoLib(issues: MutableList<GitIntegrityIssue>): Int
+private external fun repairRepoLib(cred: Cred?, remaining: MutableList<GitIntegrityIssue>): Int
+private external fun runMaintenanceLib(gracePeriodSecs: Long, minLooseObjects: Int): GitMaintenanceReport?
+private external fun repoStatsLib(assetDir: String): GitRepoStats?
 
 external fun generateSshKeysLib(): Pair<String, String>
+
+private external fun setSigningKeyLib(privateKey: String?): Int
+private external fun setAllowedSignersFileLib(path: String?)
+private external fun setSnapshotRetentionLib(maxCount: Int, maxAgeSecs: Long): Int
+private external fun setSquashWindowLib(windowSecs: Long)
+private external fun setNetworkSettingsLib(
+    connectTimeoutMs: Int,
+    timeoutMs: Int,
+    proxy: String?,
+    maxRetries: Int,
+    retryDelayMs: Int
+): Int
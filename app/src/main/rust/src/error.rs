@@ -0,0 +1,333 @@
+use std::{
+    fmt::Display,
+    sync::{LazyLock, Mutex},
+};
+
+use git2::{ErrorClass, ErrorCode};
+use jni::sys::jint;
+
+use crate::libgit2::provider::Remediation;
+
+/// Generic libgit2 error code (`GIT_ERROR`), used for errors that don't come from libgit2
+const GIT_ERROR: jint = -1;
+
+#[derive(Debug)]
+pub enum Error {
+    Git2 {
+        error: git2::Error,
+        msg: String,
+    },
+    /// Failed clone/fetch/push, with advice for the hosting provider
+    Remote {
+        error: git2::Error,
+        msg: String,
+        remediation: Remediation,
+    },
+    Io {
+        error: std::io::Error,
+        msg: String,
+    },
+    /// Error detected by this library rather than by libgit2
+    Custom {
+        category: ErrorCategory,
+        msg: String,
+    },
+}
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Other = 0,
+    Auth = 1,
+    Network = 2,
+    Tls = 3,
+    HostKey = 4,
+    NonFastForward = 5,
+    Conflict = 6,
+    RepoState = 7,
+    Io = 8,
+    InvalidInput = 9,
+    /// The progress callback asked to stop
+    Cancelled = 10,
+}
+
+impl From<git2::Error> for Error {
+    fn from(value: git2::Error) -> Self {
+        Self::git2(value, "")
+    }
+}
+
+impl Error {
+    pub fn git2(error: git2::Error, msg: &str) -> Self {
+        Self::Git2 {
+            error,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn remote(error: git2::Error, msg: &str, remediation: Remediation) -> Self {
+        Self::Remote {
+            error,
+            msg: msg.into(),
+            remediation,
+        }
+    }
+
+    pub fn io(error: std::io::Error, msg: &str) -> Self {
+        Self::Io {
+            error,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn invalid_input(msg: &str) -> Self {
+        Self::Custom {
+            category: ErrorCategory::InvalidInput,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn repo_state(msg: &str) -> Self {
+        Self::Custom {
+            category: ErrorCategory::RepoState,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn conflict(msg: &str) -> Self {
+        Self::Custom {
+            category: ErrorCategory::Conflict,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn other(msg: &str) -> Self {
+        Self::Custom {
+            category: ErrorCategory::Other,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn auth(msg: &str) -> Self {
+        Self::Custom {
+            category: ErrorCategory::Auth,
+            msg: msg.into(),
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Git2 { error, .. } | Error::Remote { error, .. } => {
+                ErrorCategory::from_git2(error)
+            }
+            Error::Io { .. } => ErrorCategory::Io,
+            Error::Custom { category, .. } => *category,
+        }
+    }
+
+    /// libgit2 error class and code, `(0, -1)` for errors not raised by libgit2
+    pub fn git2_class_code(&self) -> (i32, i32) {
+        match self {
+            Error::Git2 { error, .. } | Error::Remote { error, .. } => {
+                (error.raw_class() as i32, error.raw_code())
+            }
+            _ => (0, GIT_ERROR),
+        }
+    }
+}
+
+impl ErrorCategory {
+    fn from_git2(error: &git2::Error) -> Self {
+        match (error.code(), error.class()) {
+            (ErrorCode::User, _) => Self::Cancelled,
+            (ErrorCode::Auth, _) => Self::Auth,
+            (ErrorCode::Certificate, ErrorClass::Ssh) => Self::HostKey,
+            (ErrorCode::Certificate, _) | (_, ErrorClass::Ssl) => Self::Tls,
+            (ErrorCode::NotFastForward, _) => Self::NonFastForward,
+            (ErrorCode::Conflict | ErrorCode::MergeConflict | ErrorCode::Unmerged, _) => {
+                Self::Conflict
+            }
+            (
+                ErrorCode::Locked
+                | ErrorCode::BareRepo
+                | ErrorCode::UnbornBranch
+                | ErrorCode::Uncommitted
+                | ErrorCode::IndexDirty
+                | ErrorCode::Modified
+                | ErrorCode::Owner,
+                _,
+            ) => Self::RepoState,
+            (ErrorCode::Timeout, _) => Self::Network,
+            (ErrorCode::InvalidSpec | ErrorCode::Invalid | ErrorCode::Ambiguous, _) => {
+                Self::InvalidInput
+            }
+            (_, ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssh) => Self::Network,
+            (_, ErrorClass::Os | ErrorClass::Filesystem) => Self::Io,
+            (_, ErrorClass::Merge | ErrorClass::Checkout) => Self::Conflict,
+            (_, ErrorClass::Invalid) => Self::InvalidInput,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<Error> for jint {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Git2 { error, .. } | Error::Remote { error, .. } => error.raw_code(),
+            Error::Io { .. } | Error::Custom { .. } => GIT_ERROR,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Git2 { error, msg } | Error::Remote { error, msg, .. } => {
+                write!(f, "{msg}: {error}")
+            }
+            Error::Io { error, msg } => {
+                write!(f, "{msg}: {error}")
+            }
+            Error::Custom { msg, .. } => {
+                write!(f, "{msg}")
+            }
+        }
+    }
+}
+
+/// What the Kotlin side gets to see of the last failed call
+#[derive(Debug, Clone)]
+pub struct ErrorInfo {
+    pub category: ErrorCategory,
+    pub message: String,
+    pub class: i32,
+    pub code: i32,
+    pub remediation: Option<Remediation>,
+}
+
+impl From<&Error> for ErrorInfo {
+    fn from(value: &Error) -> Self {
+        let message = match value {
+            Error::Git2 { error, msg } | Error::Remote { error, msg, .. } if msg.is_empty() => {
+                error.message().to_string()
+            }
+            Error::Git2 { error, msg } | Error::Remote { error, msg, .. } => {
+                format!("{msg}: {}", error.message())
+            }
+            other => other.to_string(),
+        };
+        let (class, code) = value.git2_class_code();
+        let remediation = match value {
+            Error::Remote { remediation, .. } => Some(remediation.clone()),
+            _ => None,
+        };
+
+        Self {
+            category: value.category(),
+            message,
+            class,
+            code,
+            remediation,
+        }
+    }
+}
+
+static LAST_ERROR: LazyLock<Mutex<Option<ErrorInfo>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Remember an error so the Kotlin side can fetch the details after a negative return code.
+pub fn set_last_error(error: &Error) {
+    LAST_ERROR
+        .lock()
+        .expect("last error lock")
+        .replace(error.into());
+}
+
+/// Forget the error of an earlier call, once a call got past the library
+pub fn clear_last_error() {
+    LAST_ERROR.lock().expect("last error lock").take();
+}
+
+pub fn take_last_error() -> Option<ErrorInfo> {
+    LAST_ERROR.lock().expect("last error lock").take()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn category(code: ErrorCode, class: ErrorClass) -> ErrorCategory {
+        Error::git2(git2::Error::new(code, class, "test"), "").category()
+    }
+
+    #[test]
+    fn git2_categories() {
+        assert_eq!(
+            category(ErrorCode::Auth, ErrorClass::Http),
+            ErrorCategory::Auth
+        );
+        assert_eq!(
+            category(ErrorCode::Certificate, ErrorClass::Ssh),
+            ErrorCategory::HostKey
+        );
+        assert_eq!(
+            category(ErrorCode::Certificate, ErrorClass::Http),
+            ErrorCategory::Tls
+        );
+        assert_eq!(
+            category(ErrorCode::GenericError, ErrorClass::Ssl),
+            ErrorCategory::Tls
+        );
+        assert_eq!(
+            category(ErrorCode::NotFastForward, ErrorClass::Reference),
+            ErrorCategory::NonFastForward
+        );
+        assert_eq!(
+            category(ErrorCode::Unmerged, ErrorClass::Merge),
+            ErrorCategory::Conflict
+        );
+        assert_eq!(
+            category(ErrorCode::Locked, ErrorClass::Index),
+            ErrorCategory::RepoState
+        );
+        assert_eq!(
+            category(ErrorCode::GenericError, ErrorClass::Net),
+            ErrorCategory::Network
+        );
+        assert_eq!(
+            category(ErrorCode::GenericError, ErrorClass::Os),
+            ErrorCategory::Io
+        );
+        assert_eq!(
+            category(ErrorCode::InvalidSpec, ErrorClass::Reference),
+            ErrorCategory::InvalidInput
+        );
+        assert_eq!(
+            category(ErrorCode::User, ErrorClass::Callback),
+            ErrorCategory::Cancelled
+        );
+        assert_eq!(
+            category(ErrorCode::GenericError, ErrorClass::None),
+            ErrorCategory::Other
+        );
+    }
+
+    #[test]
+    fn last_error() {
+        let git2_error = git2::Error::new(ErrorCode::Auth, ErrorClass::Http, "too many redirects");
+        let (class, code) = (git2_error.raw_class() as i32, git2_error.raw_code());
+        set_last_error(&Error::git2(git2_error, "push"));
+
+        let info = take_last_error().expect("last error");
+        assert_eq!(info.category, ErrorCategory::Auth);
+        assert_eq!(info.message, "push: too many redirects");
+        assert_eq!((info.class, info.code), (class, code));
+        assert!(take_last_error().is_none());
+
+        set_last_error(&Error::other("stale"));
+        clear_last_error();
+        assert!(take_last_error().is_none());
+
+        let error = Error::invalid_input("bad key");
+        assert_eq!(jint::from(error), GIT_ERROR);
+    }
+}
@@ -1,11 +1,14 @@
+use std::collections::HashMap;
 use std::fmt::{Debug, Display};
+use std::time::Duration;
 
 use anyhow::anyhow;
 use jni::JNIEnv;
 use jni::objects::{JClass, JObject, JString, JValue};
-use jni::sys::{jboolean, jint, jobject, jstring};
+use jni::sys::{jboolean, jint, jlong, jobject, jstring};
 
 use crate::callback::ProgressCB;
+pub use crate::error::{Error, ErrorCategory};
 use crate::key_gen::gen_keys;
 use crate::utils::install_panic_hook;
 
@@ -13,53 +16,17 @@ use crate::utils::install_panic_hook;
 extern crate log;
 #[macro_use]
 mod utils;
+mod error;
 mod key_gen;
 pub mod libgit2;
 mod mime_types;
+mod search;
 
 #[cfg(test)]
 mod test;
 
 const OK: jint = 0;
 
-#[derive(Debug)]
-pub enum Error {
-    Git2 { error: git2::Error, msg: String },
-}
-
-impl From<git2::Error> for Error {
-    fn from(value: git2::Error) -> Self {
-        Self::git2(value, "")
-    }
-}
-
-impl Error {
-    fn git2(error: git2::Error, msg: &str) -> Self {
-        Self::Git2 {
-            error,
-            msg: msg.into(),
-        }
-    }
-}
-
-impl From<Error> for jint {
-    fn from(value: Error) -> Self {
-        match value {
-            Error::Git2 { error, .. } => error.raw_code(),
-        }
-    }
-}
-
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Error::Git2 { error, msg } => {
-                write!(f, "{msg}: {error}")
-            }
-        }
-    }
-}
-
 #[unsafe(no_mangle)]
 pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_initLib<'local>(
     mut env: JNIEnv<'local>,
@@ -89,6 +56,105 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_ini
     OK
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_takeLastErrorLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jobject {
+    let Some(error) = error::take_last_error() else {
+        return std::ptr::null_mut();
+    };
+
+    match take_last_error_jni(&mut env, error) {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            error!("take_last_error_jni: {e}");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn take_last_error_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    error: error::ErrorInfo,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_message = env.new_string(&error.message)?;
+
+    let j_remediation = match &error.remediation {
+        Some(remediation) => remediation_jni(env, remediation)?,
+        None => JObject::null(),
+    };
+
+    let obj = env.new_object(
+        "io/github/christianjann/gittasks/manager/GitError",
+        "(ILjava/lang/String;IILio/github/christianjann/gittasks/manager/GitRemediation;)V",
+        &[
+            JValue::Int(error.category as jint),
+            JValue::Object(&j_message),
+            JValue::Int(error.class),
+            JValue::Int(error.code),
+            JValue::Object(&j_remediation),
+        ],
+    )?;
+
+    Ok(obj)
+}
+
+/// Log a failure to pass a result to the Kotlin side and keep it for `takeLastErrorLib`
+fn jni_error(name: &str, e: impl Display) {
+    error!("{name}: {e}");
+    error::set_last_error(&Error::other(&format!("{name}: {e}")));
+}
+
+fn remediation_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    remediation: &libgit2::provider::Remediation,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_provider_name = env.new_string(remediation.provider.name())?;
+    let j_credential = env.new_string(remediation.credential)?;
+    let j_token_url = match &remediation.token_url {
+        Some(url) => JObject::from(env.new_string(url)?),
+        None => JObject::null(),
+    };
+    let j_scopes = string_list_jni(env, remediation.scopes.iter())?;
+
+    let obj = env.new_object(
+        "io/github/christianjann/gittasks/manager/GitRemediation",
+        "(ILjava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/util/List;)V",
+        &[
+            JValue::Int(remediation.provider as jint),
+            JValue::Object(&j_provider_name),
+            JValue::Object(&j_credential),
+            JValue::Object(&j_token_url),
+            JValue::Object(&j_scopes),
+        ],
+    )?;
+
+    Ok(obj)
+}
+
+fn string_list_jni<'local, S: AsRef<str>>(
+    env: &mut JNIEnv<'local>,
+    items: impl Iterator<Item = S>,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let list = env.new_object("java/util/ArrayList", "()V", &[])?;
+
+    for item in items {
+        let j_item = env.new_string(item.as_ref())?;
+        env.call_method(
+            &list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_item)],
+        )?;
+        env.delete_local_ref(j_item)?;
+    }
+
+    Ok(list)
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_createRepoLib<
     'local,
@@ -233,6 +299,8 @@ impl Cred {
 mod callback {
     use jni::{JNIEnv, objects::JObject};
 
+    use crate::libgit2::{Phase, Progress};
+
     pub struct ProgressCB<'a, 'b> {
         env: &'b mut JNIEnv<'a>,
         callback_class: JObject<'a>,
@@ -245,12 +313,19 @@ mod callback {
                 callback_class,
             }
         }
-        pub fn progress(&mut self, progress: i32) -> bool {
+    }
+
+    impl Progress for ProgressCB<'_, '_> {
+        fn report(&mut self, phase: Phase, percent: i32, bytes: usize) -> bool {
+            if self.callback_class.is_null() {
+                return true;
+            }
+
             match self.env.call_method(
                 &self.callback_class,
                 "progressCb",
-                "(I)Z",
-                &[progress.into()],
+                "(IIJ)Z",
+                &[(phase as i32).into(), percent.into(), (bytes as i64).into()],
             ) {
                 Ok(res) => res.z().unwrap(),
                 Err(e) => {
@@ -283,10 +358,10 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_clo
         }
     };
 
-    let cb = ProgressCB::new(&mut env, progress_callback);
+    let mut cb = ProgressCB::new(&mut env, progress_callback);
 
     unwrap_or_log!(
-        libgit2::clone_repo(&repo_path, &remote_url, cred, cb),
+        libgit2::clone_repo(&repo_path, &remote_url, cred, &mut cb),
         "clone_repo"
     );
 
@@ -332,10 +407,15 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_che
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     path: JString<'local>,
+    commit: JString<'local>,
 ) -> jint {
     let path: String = env.get_string(&path).unwrap().into();
+    let commit = optional_string(&mut env, &commit);
 
-    unwrap_or_log!(libgit2::checkout_path(&path), "checkout_path");
+    unwrap_or_log!(
+        libgit2::checkout_path(&path, commit.as_deref()),
+        "checkout_path"
+    );
 
     OK
 }
@@ -372,9 +452,11 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_pus
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     cred: JString<'local>,
+    progress_callback: JObject<'local>,
 ) -> jint {
     let cred = Cred::from_jni(&mut env, &cred).unwrap();
-    unwrap_or_log!(libgit2::push(cred), "push");
+    let mut cb = ProgressCB::new(&mut env, progress_callback);
+    unwrap_or_log!(libgit2::push(cred, &mut cb), "push");
     OK
 }
 
@@ -385,6 +467,7 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_pul
     cred: JString<'local>,
     name: JString<'local>,
     email: JString<'local>,
+    progress_callback: JObject<'local>,
 ) -> jint {
     let cred = Cred::from_jni(&mut env, &cred).unwrap();
     let name = env.get_string(&name).unwrap().to_str().unwrap().to_string();
@@ -394,7 +477,8 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_pul
         .to_str()
         .unwrap()
         .to_string();
-    unwrap_or_log!(libgit2::pull(cred, &name, &email), "pull");
+    let mut cb = ProgressCB::new(&mut env, progress_callback);
+    unwrap_or_log!(libgit2::pull(cred, &name, &email, &mut cb), "pull");
     OK
 }
 
@@ -403,9 +487,11 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_syn
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     cred: JString<'local>,
+    progress_callback: JObject<'local>,
 ) -> jint {
     let cred = Cred::from_jni(&mut env, &cred).unwrap();
-    unwrap_or_log!(libgit2::sync(cred), "sync");
+    let mut cb = ProgressCB::new(&mut env, progress_callback);
+    unwrap_or_log!(libgit2::sync(cred, &mut cb), "sync");
     OK
 }
 
@@ -443,6 +529,66 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_isC
     is_change as jint
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getStatusLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    folder: JString<'local>,
+    include_ignored: jboolean,
+) -> jint {
+    let folder = optional_string(&mut env, &folder);
+
+    let statuses = unwrap_or_log!(
+        libgit2::status(folder.as_deref(), include_ignored != 0),
+        "status"
+    );
+
+    if let Err(e) = status_jni(&mut env, &j_list, &statuses) {
+        jni_error("status_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn status_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    statuses: &[libgit2::PathStatus],
+) -> Result<(), Box<dyn std::error::Error>> {
+    // -1 for no change, see `GitPathStatus`
+    let state = |state: Option<libgit2::FileState>| state.map_or(-1, |state| state as jint);
+
+    for status in statuses {
+        let j_path = env.new_string(&status.path)?;
+        let j_old_path = match &status.old_path {
+            Some(old_path) => JObject::from(env.new_string(old_path)?),
+            None => JObject::null(),
+        };
+        let j_status = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitPathStatus",
+            "(Ljava/lang/String;Ljava/lang/String;II)V",
+            &[
+                JValue::Object(&j_path),
+                JValue::Object(&j_old_path),
+                JValue::Int(state(status.index)),
+                JValue::Int(state(status.worktree)),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_status)],
+        )?;
+    }
+
+    Ok(())
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getTimestampsLib<
     'local,
@@ -450,11 +596,15 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_get
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     j_map: JObject<'local>,
+    count_renames: jboolean,
 ) -> jint {
-    let timestamps = unwrap_or_log!(libgit2::get_timestamps(), "get_timestamps");
+    let timestamps = unwrap_or_log!(
+        libgit2::get_timestamps(count_renames != 0),
+        "get_timestamps"
+    );
 
     if let Err(e) = get_timestamps_jni(&mut env, &j_map, timestamps.iter()) {
-        error!("get_timestamps_jni: {e}");
+        jni_error("get_timestamps_jni", e);
         return -1;
     }
 
@@ -501,6 +651,58 @@ fn get_timestamps_jni<'local, 'a>(
     Ok(())
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getFileHistoryLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_map: JObject<'local>,
+    count_renames: jboolean,
+) -> jint {
+    let files = unwrap_or_log!(
+        libgit2::get_file_history(count_renames != 0),
+        "get_file_history"
+    );
+
+    if let Err(e) = file_history_jni(&mut env, &j_map, &files) {
+        jni_error("file_history_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn file_history_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_map: &JObject<'local>,
+    files: &HashMap<String, libgit2::FileHistory>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (path, history) in files {
+        let j_path = env.new_string(path)?;
+        let j_author = env.new_string(&history.last_author)?;
+        let j_email = env.new_string(&history.last_author_email)?;
+        let j_history = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitFileHistory",
+            "(JJLjava/lang/String;Ljava/lang/String;)V",
+            &[
+                JValue::Long(history.created),
+                JValue::Long(history.modified),
+                JValue::Object(&j_author),
+                JValue::Object(&j_email),
+            ],
+        )?;
+        env.call_method(
+            j_map,
+            "put",
+            "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+            &[JValue::Object(&j_path), JValue::Object(&j_history)],
+        )?;
+    }
+
+    Ok(())
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_generateSshKeysLib<
     'local,
@@ -511,7 +713,7 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_gen
     let keys = match gen_keys() {
         Ok(keys) => keys,
         Err(e) => {
-            error!("can't gen keys: {e}");
+            jni_error("gen_keys", e);
             return std::ptr::null_mut();
         }
     };
@@ -533,96 +735,1253 @@ pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_gen
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_io_github_christianjann_gittasks_manager_MimeTypeManagerKt_extensionTypeLib<
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_setSigningKeyLib<
     'local,
 >(
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
-    extension: JString<'local>,
+    private_key: JString<'local>,
 ) -> jint {
-    let extension: String = env.get_string(&extension).unwrap().into();
+    let private_key = optional_string(&mut env, &private_key);
 
-    match mime_types::extension_type(extension.as_str()) {
-        Some(ext_type) => ext_type as jint,
-        None => 0,
-    }
+    unwrap_or_log!(
+        libgit2::set_signing_key(private_key.as_deref()),
+        "set_signing_key"
+    );
+
+    OK
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_io_github_christianjann_gittasks_manager_MimeTypeManagerKt_isExtensionSupported<
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_setAllowedSignersFileLib<
     'local,
 >(
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
-    extension: JString<'local>,
-) -> jboolean {
-    let extension: String = env.get_string(&extension).unwrap().into();
+    path: JString<'local>,
+) {
+    let path = optional_string(&mut env, &path);
 
-    mime_types::is_extension_supported(extension.as_str()).into()
+    libgit2::set_allowed_signers_file(path.as_deref());
 }
 
 #[unsafe(no_mangle)]
-pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getGitLogLib<
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_setNetworkSettingsLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    connect_timeout_ms: jint,
+    timeout_ms: jint,
+    proxy: JString<'local>,
+    max_retries: jint,
+    retry_delay_ms: jint,
+) -> jint {
+    let proxy = optional_string(&mut env, &proxy);
+
+    let settings = libgit2::NetworkSettings {
+        connect_timeout_ms,
+        timeout_ms,
+        proxy,
+        max_retries: max_retries.max(0) as u32,
+        retry_delay_ms: retry_delay_ms.max(0) as u64,
+    };
+
+    unwrap_or_log!(
+        libgit2::set_network_settings(settings),
+        "set_network_settings"
+    );
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_verifyRepoLib<
     'local,
 >(
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     j_list: JObject<'local>,
-    limit: jint,
 ) -> jint {
-    let log_entries = unwrap_or_log!(libgit2::get_git_log(limit as usize), "get_git_log");
+    let issues = unwrap_or_log!(libgit2::verify_repo(), "verify_repo");
 
-    if let Err(e) = get_git_log_jni(&mut env, &j_list, log_entries) {
-        error!("get_git_log_jni: {e}");
+    if let Err(e) = integrity_issues_jni(&mut env, &j_list, issues) {
+        jni_error("integrity_issues_jni", e);
         return -1;
     }
 
     OK
 }
 
-fn get_git_log_jni<'local>(
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_repairRepoLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    cred: JString<'local>,
+    j_list: JObject<'local>,
+) -> jint {
+    let cred = Cred::from_jni(&mut env, &cred).unwrap();
+    let remaining = unwrap_or_log!(libgit2::repair_repo(cred), "repair_repo");
+
+    if let Err(e) = integrity_issues_jni(&mut env, &j_list, remaining) {
+        jni_error("integrity_issues_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn integrity_issues_jni<'local>(
     env: &mut JNIEnv<'local>,
     j_list: &JObject<'local>,
-    entries: Vec<libgit2::GitLogEntry>,
+    issues: Vec<libgit2::Issue>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let list_class = env.get_object_class(j_list)?;
-    let add_method = env.get_method_id(&list_class, "add", "(Ljava/lang/Object;)Z")?;
+    for issue in issues {
+        let j_target = env.new_string(&issue.target)?;
+        let j_detail = env.new_string(&issue.detail)?;
+
+        let j_issue = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitIntegrityIssue",
+            "(ILjava/lang/String;Ljava/lang/String;)V",
+            &[
+                JValue::Int(issue.kind as jint),
+                JValue::Object(&j_target),
+                JValue::Object(&j_detail),
+            ],
+        )?;
+
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_issue)],
+        )?;
+    }
 
-    let entry_class = env.find_class("io/github/christianjann/gittasks/manager/GitLogEntry")?;
-    let entry_ctor = env.get_method_id(
-        &entry_class,
-        "<init>",
-        "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)V",
-    )?;
+    Ok(())
+}
 
-    for entry in entries {
-        let j_hash = env.new_string(&entry.hash)?;
-        let j_message = env.new_string(&entry.message)?;
-        let j_author = env.new_string(&entry.author)?;
-        let j_date = env.new_string(&entry.date)?;
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_runMaintenanceLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    grace_period_secs: jlong,
+    min_loose_objects: jint,
+) -> jobject {
+    let report = unwrap_or_log!(
+        libgit2::run_maintenance(
+            Duration::from_secs(grace_period_secs.max(0) as u64),
+            min_loose_objects.max(0) as usize,
+        ),
+        "run_maintenance",
+        std::ptr::null_mut()
+    );
 
-        let j_entry = unsafe {
-            env.new_object_unchecked(
-                &entry_class,
-                entry_ctor,
-                &[
-                    JValue::Object(&JObject::from(j_hash)).as_jni(),
-                    JValue::Object(&JObject::from(j_message)).as_jni(),
-                    JValue::Object(&JObject::from(j_author)).as_jni(),
-                    JValue::Object(&JObject::from(j_date)).as_jni(),
-                ],
-            )?
-        };
+    let res = env.new_object(
+        "io/github/christianjann/gittasks/manager/GitMaintenanceReport",
+        "(IIIJJ)V",
+        &[
+            JValue::Int(report.objects_packed as jint),
+            JValue::Int(report.packs_removed as jint),
+            JValue::Int(report.objects_pruned as jint),
+            JValue::Long(report.bytes_before as jlong),
+            JValue::Long(report.bytes_after as jlong),
+        ],
+    );
 
-        unsafe {
-            env.call_method_unchecked(
-                j_list,
-                add_method,
-                jni::signature::ReturnType::Primitive(jni::signature::Primitive::Boolean),
-                &[JValue::Object(&j_entry).as_jni()],
-            )?;
+    match res {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            jni_error("run_maintenance_jni", e);
+            std::ptr::null_mut()
         }
     }
+}
 
-    Ok(())
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_repoStatsLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    asset_dir: JString<'local>,
+) -> jobject {
+    let asset_dir: String = env.get_string(&asset_dir).unwrap().into();
+
+    let stats = unwrap_or_log!(
+        libgit2::repo_stats(&asset_dir),
+        "repo_stats",
+        std::ptr::null_mut()
+    );
+
+    match repo_stats_jni(&mut env, &stats) {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            jni_error("repo_stats_jni", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn repo_stats_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    stats: &libgit2::RepoStats,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_contributors = env.new_object("java/util/ArrayList", "()V", &[])?;
+    for contributor in &stats.contributors {
+        let j_name = env.new_string(&contributor.name)?;
+        let j_email = env.new_string(&contributor.email)?;
+        let j_contributor = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitContributor",
+            "(Ljava/lang/String;Ljava/lang/String;I)V",
+            &[
+                JValue::Object(&j_name),
+                JValue::Object(&j_email),
+                JValue::Int(contributor.commits as jint),
+            ],
+        )?;
+        env.call_method(
+            &j_contributors,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_contributor)],
+        )?;
+    }
+
+    let j_largest = env.new_object("java/util/ArrayList", "()V", &[])?;
+    for blob in &stats.largest_blobs {
+        let j_path = env.new_string(&blob.path)?;
+        let j_blob = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitBlobSize",
+            "(Ljava/lang/String;J)V",
+            &[JValue::Object(&j_path), JValue::Long(blob.bytes as jlong)],
+        )?;
+        env.call_method(
+            &j_largest,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_blob)],
+        )?;
+    }
+
+    let obj = env.new_object(
+        "io/github/christianjann/gittasks/manager/GitRepoStats",
+        "(ILjava/util/List;IIIJLjava/util/List;JIIJ)V",
+        &[
+            JValue::Int(stats.commit_count as jint),
+            JValue::Object(&j_contributors),
+            JValue::Int(stats.markdown_notes as jint),
+            JValue::Int(stats.text_notes as jint),
+            JValue::Int(stats.other_files as jint),
+            JValue::Long(stats.total_blob_bytes as jlong),
+            JValue::Object(&j_largest),
+            JValue::Long(stats.git_dir_bytes as jlong),
+            JValue::Int(stats.loose_objects as jint),
+            JValue::Int(stats.packed_objects as jint),
+            JValue::Long(stats.asset_bytes as jlong),
+        ],
+    )?;
+
+    Ok(obj)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_updateSearchIndexLib(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jint {
+    let changed = unwrap_or_log!(libgit2::update_search_index(), "update_search_index");
+
+    changed as jint
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_searchLib<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    query: JString<'local>,
+    folder: JString<'local>,
+    limit: jint,
+) -> jint {
+    let query: String = env.get_string(&query).unwrap().into();
+    let folder = optional_string(&mut env, &folder);
+
+    let results = unwrap_or_log!(
+        libgit2::search(&query, folder.as_deref(), limit.max(0) as usize),
+        "search"
+    );
+
+    if let Err(e) = search_jni(&mut env, &j_list, &results) {
+        jni_error("search_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn search_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    results: &[search::SearchResult],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for result in results {
+        let j_path = env.new_string(&result.path)?;
+        let j_snippet = env.new_string(&result.snippet)?;
+        let j_result = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitSearchResult",
+            "(Ljava/lang/String;DILjava/lang/String;)V",
+            &[
+                JValue::Object(&j_path),
+                JValue::Double(result.score),
+                JValue::Int(result.line as jint),
+                JValue::Object(&j_snippet),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_result)],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_searchHistoryLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    pattern: JString<'local>,
+    ignore_case: jboolean,
+    path: JString<'local>,
+    since: jlong,
+    until: jlong,
+    limit: jint,
+) -> jint {
+    let query = libgit2::HistoryQuery {
+        pattern: env.get_string(&pattern).unwrap().into(),
+        ignore_case: ignore_case != 0,
+        path: optional_string(&mut env, &path),
+        // The Kotlin side passes the extremes for an open range
+        since: (since != jlong::MIN).then_some(since),
+        until: (until != jlong::MAX).then_some(until),
+        limit: limit.max(0) as usize,
+    };
+
+    let matches = unwrap_or_log!(libgit2::search_history(&query), "search_history");
+
+    if let Err(e) = search_history_jni(&mut env, &j_list, &matches) {
+        jni_error("search_history_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn search_history_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    matches: &[libgit2::HistoryMatch],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for found in matches {
+        let j_hash = env.new_string(&found.hash)?;
+        let j_path = env.new_string(&found.path)?;
+        let j_line = env.new_string(&found.line)?;
+        let j_match = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitHistoryMatch",
+            "(Ljava/lang/String;JLjava/lang/String;ILjava/lang/String;)V",
+            &[
+                JValue::Object(&j_hash),
+                JValue::Long(found.time),
+                JValue::Object(&j_path),
+                JValue::Int(found.line_number as jint),
+                JValue::Object(&j_line),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_match)],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_MimeTypeManagerKt_extensionTypeLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    extension: JString<'local>,
+) -> jint {
+    let extension: String = env.get_string(&extension).unwrap().into();
+
+    match mime_types::extension_type(extension.as_str()) {
+        Some(ext_type) => ext_type as jint,
+        None => 0,
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_MimeTypeManagerKt_isExtensionSupported<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    extension: JString<'local>,
+) -> jboolean {
+    let extension: String = env.get_string(&extension).unwrap().into();
+
+    mime_types::is_extension_supported(extension.as_str()).into()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getGitLogLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    limit: jint,
+) -> jint {
+    let log_entries = unwrap_or_log!(libgit2::get_git_log(limit as usize), "get_git_log");
+
+    if let Err(e) = get_git_log_jni(&mut env, &j_list, log_entries) {
+        jni_error("get_git_log_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_queryGitLogLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    limit: jint,
+    after: JString<'local>,
+    offset: jint,
+    author: JString<'local>,
+    since: jlong,
+    until: jlong,
+    path: JString<'local>,
+    message: JString<'local>,
+    no_merges: jboolean,
+    include_files: jboolean,
+) -> jint {
+    let query = libgit2::LogQuery {
+        limit: limit.max(0) as usize,
+        after: optional_string(&mut env, &after),
+        offset: offset.max(0) as usize,
+        author: optional_string(&mut env, &author),
+        // The Kotlin side passes the extremes for an open range
+        since: (since != jlong::MIN).then_some(since),
+        until: (until != jlong::MAX).then_some(until),
+        path: optional_string(&mut env, &path),
+        message: optional_string(&mut env, &message),
+        no_merges: no_merges != 0,
+        include_files: include_files != 0,
+    };
+
+    let log_entries = unwrap_or_log!(libgit2::query_git_log(&query), "query_git_log");
+
+    if let Err(e) = get_git_log_jni(&mut env, &j_list, log_entries) {
+        jni_error("get_git_log_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+/// A position in a list passed by the Kotlin side
+fn index_arg(index: jint) -> Result<usize, Error> {
+    usize::try_from(index).map_err(|_| Error::invalid_input(&format!("negative index {index}")))
+}
+
+fn optional_string(env: &mut JNIEnv, s: &JString) -> Option<String> {
+    if s.is_null() {
+        None
+    } else {
+        Some(env.get_string(s).unwrap().into())
+    }
+}
+
+fn get_git_log_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    entries: Vec<libgit2::GitLogEntry>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let list_class = env.get_object_class(j_list)?;
+    let add_method = env.get_method_id(&list_class, "add", "(Ljava/lang/Object;)Z")?;
+
+    let entry_class = env.find_class("io/github/christianjann/gittasks/manager/GitLogEntry")?;
+    let entry_ctor = env.get_method_id(
+        &entry_class,
+        "<init>",
+        "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;ILjava/lang/String;Ljava/util/List;)V",
+    )?;
+
+    for entry in entries {
+        let j_hash = env.new_string(&entry.hash)?;
+        let j_message = env.new_string(&entry.message)?;
+        let j_author = env.new_string(&entry.author)?;
+        let j_date = env.new_string(&entry.date)?;
+        let j_signer = match &entry.signer {
+            Some(signer) => JObject::from(env.new_string(signer)?),
+            None => JObject::null(),
+        };
+        let j_files = changed_files_jni(env, &entry.files)?;
+
+        let j_entry = unsafe {
+            env.new_object_unchecked(
+                &entry_class,
+                entry_ctor,
+                &[
+                    JValue::Object(&JObject::from(j_hash)).as_jni(),
+                    JValue::Object(&JObject::from(j_message)).as_jni(),
+                    JValue::Object(&JObject::from(j_author)).as_jni(),
+                    JValue::Object(&JObject::from(j_date)).as_jni(),
+                    JValue::Int(entry.signature_status as jint).as_jni(),
+                    JValue::Object(&j_signer).as_jni(),
+                    JValue::Object(&j_files).as_jni(),
+                ],
+            )?
+        };
+
+        unsafe {
+            env.call_method_unchecked(
+                j_list,
+                add_method,
+                jni::signature::ReturnType::Primitive(jni::signature::Primitive::Boolean),
+                &[JValue::Object(&j_entry).as_jni()],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getGitGraphLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    offset: jint,
+    limit: jint,
+) -> jint {
+    let rows = unwrap_or_log!(
+        libgit2::get_git_graph(offset.max(0) as usize, limit.max(0) as usize),
+        "get_git_graph"
+    );
+
+    if let Err(e) = git_graph_jni(&mut env, &j_list, &rows) {
+        jni_error("git_graph_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn git_graph_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    rows: &[libgit2::GraphRow],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for row in rows {
+        let j_parents = env.new_object("java/util/ArrayList", "()V", &[])?;
+        for parent in &row.parents {
+            let j_parent = env.new_string(parent)?;
+            env.call_method(
+                &j_parents,
+                "add",
+                "(Ljava/lang/Object;)Z",
+                &[JValue::Object(&j_parent)],
+            )?;
+        }
+
+        let j_edges = env.new_object("java/util/ArrayList", "()V", &[])?;
+        for edge in &row.edges {
+            let j_edge = env.new_object(
+                "io/github/christianjann/gittasks/manager/GitGraphEdge",
+                "(II)V",
+                &[JValue::Int(edge.from as jint), JValue::Int(edge.to as jint)],
+            )?;
+            env.call_method(
+                &j_edges,
+                "add",
+                "(Ljava/lang/Object;)Z",
+                &[JValue::Object(&j_edge)],
+            )?;
+        }
+
+        let j_hash = env.new_string(&row.hash)?;
+        let j_row = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitGraphRow",
+            "(Ljava/lang/String;Ljava/util/List;ILjava/util/List;)V",
+            &[
+                JValue::Object(&j_hash),
+                JValue::Object(&j_parents),
+                JValue::Int(row.column as jint),
+                JValue::Object(&j_edges),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_row)],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn changed_files_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    files: &[libgit2::ChangedFile],
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_files = env.new_object("java/util/ArrayList", "()V", &[])?;
+
+    for file in files {
+        let j_path = env.new_string(&file.path)?;
+        let j_old_path = match &file.old_path {
+            Some(old_path) => JObject::from(env.new_string(old_path)?),
+            None => JObject::null(),
+        };
+        let j_file = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitChangedFile",
+            "(Ljava/lang/String;Ljava/lang/String;III)V",
+            &[
+                JValue::Object(&j_path),
+                JValue::Object(&j_old_path),
+                JValue::Int(file.status as jint),
+                JValue::Int(file.additions as jint),
+                JValue::Int(file.deletions as jint),
+            ],
+        )?;
+        env.call_method(
+            &j_files,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_file)],
+        )?;
+    }
+
+    Ok(j_files)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getCommitDetailsLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    hash: JString<'local>,
+) -> jobject {
+    let hash: String = env.get_string(&hash).unwrap().into();
+
+    let details = unwrap_or_log!(
+        libgit2::get_commit_details(&hash),
+        "get_commit_details",
+        std::ptr::null_mut()
+    );
+
+    match commit_details_jni(&mut env, &details) {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            jni_error("commit_details_jni", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn commit_details_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    details: &libgit2::CommitDetails,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_parents = env.new_object("java/util/ArrayList", "()V", &[])?;
+    for parent in &details.parents {
+        let j_parent = env.new_string(parent)?;
+        env.call_method(
+            &j_parents,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_parent)],
+        )?;
+    }
+
+    let j_author = person_jni(env, &details.author)?;
+    let j_committer = person_jni(env, &details.committer)?;
+    let j_files = changed_files_jni(env, &details.files)?;
+    let j_hash = env.new_string(&details.hash)?;
+    let j_message = env.new_string(&details.message)?;
+
+    let obj = env.new_object(
+        "io/github/christianjann/gittasks/manager/GitCommitDetails",
+        "(Ljava/lang/String;Ljava/util/List;Ljava/lang/String;Lio/github/christianjann/gittasks/manager/GitPerson;Lio/github/christianjann/gittasks/manager/GitPerson;Ljava/util/List;)V",
+        &[
+            JValue::Object(&j_hash),
+            JValue::Object(&j_parents),
+            JValue::Object(&j_message),
+            JValue::Object(&j_author),
+            JValue::Object(&j_committer),
+            JValue::Object(&j_files),
+        ],
+    )?;
+
+    Ok(obj)
+}
+
+fn person_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    person: &libgit2::Person,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_name = env.new_string(&person.name)?;
+    let j_email = env.new_string(&person.email)?;
+
+    Ok(env.new_object(
+        "io/github/christianjann/gittasks/manager/GitPerson",
+        "(Ljava/lang/String;Ljava/lang/String;JI)V",
+        &[
+            JValue::Object(&j_name),
+            JValue::Object(&j_email),
+            JValue::Long(person.time),
+            JValue::Int(person.offset_minutes),
+        ],
+    )?)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_listStashesLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+) -> jint {
+    let stashes = unwrap_or_log!(libgit2::list_stashes(), "list_stashes");
+
+    if let Err(e) = stashes_jni(&mut env, &j_list, &stashes) {
+        jni_error("stashes_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn stashes_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    stashes: &[libgit2::StashEntry],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for stash in stashes {
+        let j_hash = env.new_string(&stash.hash)?;
+        let j_message = env.new_string(&stash.message)?;
+        let j_files = changed_files_jni(env, &stash.files)?;
+        let j_stash = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitStashEntry",
+            "(ILjava/lang/String;Ljava/lang/String;JLjava/util/List;)V",
+            &[
+                JValue::Int(stash.index as jint),
+                JValue::Object(&j_hash),
+                JValue::Object(&j_message),
+                JValue::Long(stash.time),
+                JValue::Object(&j_files),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_stash)],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_showStashLib<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    index: jint,
+) -> jstring {
+    let diff = unwrap_or_log!(
+        index_arg(index).and_then(libgit2::show_stash),
+        "show_stash",
+        std::ptr::null_mut()
+    );
+
+    env.new_string(diff)
+        .expect("Couldn't create Java string!")
+        .into_raw()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_applyStashLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_conflicts: JObject<'local>,
+    index: jint,
+    pop: jboolean,
+) -> jint {
+    let conflicts = unwrap_or_log!(
+        index_arg(index).and_then(|index| libgit2::apply_stash(index, pop != 0)),
+        "apply_stash"
+    );
+
+    for conflict in conflicts {
+        let res = env.new_string(conflict).and_then(|j_path| {
+            env.call_method(
+                &j_conflicts,
+                "add",
+                "(Ljava/lang/Object;)Z",
+                &[JValue::Object(&j_path)],
+            )
+        });
+        if let Err(e) = res {
+            jni_error("apply_stash", e);
+            return -1;
+        }
+    }
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_dropStashLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    index: jint,
+) -> jint {
+    unwrap_or_log!(index_arg(index).and_then(libgit2::drop_stash), "drop_stash");
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_listSnapshotsLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+) -> jint {
+    let snapshots = unwrap_or_log!(libgit2::list_snapshots(), "list_snapshots");
+
+    if let Err(e) = snapshots_jni(&mut env, &j_list, &snapshots) {
+        jni_error("snapshots_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn snapshots_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    snapshots: &[libgit2::Snapshot],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for snapshot in snapshots {
+        let j_hash = env.new_string(&snapshot.hash)?;
+        let j_reason = env.new_string(&snapshot.reason)?;
+        let j_head = match &snapshot.head {
+            Some(head) => JObject::from(env.new_string(head)?),
+            None => JObject::null(),
+        };
+        let j_files = changed_files_jni(env, &snapshot.files)?;
+        let j_snapshot = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitSnapshot",
+            "(JLjava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/util/List;)V",
+            &[
+                JValue::Long(snapshot.id),
+                JValue::Object(&j_hash),
+                JValue::Object(&j_reason),
+                JValue::Object(&j_head),
+                JValue::Object(&j_files),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_snapshot)],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_showSnapshotLib<
+    'local,
+>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    id: jlong,
+) -> jstring {
+    let diff = unwrap_or_log!(
+        libgit2::show_snapshot(id),
+        "show_snapshot",
+        std::ptr::null_mut()
+    );
+
+    env.new_string(diff)
+        .expect("Couldn't create Java string!")
+        .into_raw()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_restoreSnapshotLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    id: jlong,
+) -> jint {
+    unwrap_or_log!(libgit2::restore_snapshot(id), "restore_snapshot");
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_deleteSnapshotLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    id: jlong,
+) -> jint {
+    unwrap_or_log!(libgit2::delete_snapshot(id), "delete_snapshot");
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_setSnapshotRetentionLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    max_count: jint,
+    max_age_secs: jlong,
+) -> jint {
+    let retention = usize::try_from(max_count)
+        .ok()
+        .zip(u64::try_from(max_age_secs).ok())
+        .map(|(max_count, max_age_secs)| libgit2::Retention {
+            max_count,
+            max_age: Duration::from_secs(max_age_secs),
+        })
+        .ok_or_else(|| Error::invalid_input("negative snapshot retention"));
+
+    unwrap_or_log!(
+        retention.and_then(libgit2::set_snapshot_retention),
+        "set_snapshot_retention"
+    );
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getRepoStateLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jobject {
+    let state = unwrap_or_log!(libgit2::repo_state(), "repo_state", std::ptr::null_mut());
+
+    match repo_state_jni(&mut env, &state) {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            jni_error("repo_state_jni", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_recoverRepoLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    recovery: jint,
+    name: JString<'local>,
+    email: JString<'local>,
+) -> jobject {
+    let name: String = env.get_string(&name).unwrap().into();
+    let email: String = env.get_string(&email).unwrap().into();
+
+    let recovery = unwrap_or_log!(
+        libgit2::Recovery::from_code(recovery)
+            .ok_or_else(|| Error::invalid_input(&format!("unknown recovery {recovery}"))),
+        "recover",
+        std::ptr::null_mut()
+    );
+
+    let state = unwrap_or_log!(
+        libgit2::recover(recovery, &name, &email),
+        "recover",
+        std::ptr::null_mut()
+    );
+
+    match repo_state_jni(&mut env, &state) {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            jni_error("repo_state_jni", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn repo_state_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    state: &libgit2::RepoState,
+) -> Result<JObject<'local>, Box<dyn std::error::Error>> {
+    let j_files = env.new_object("java/util/ArrayList", "()V", &[])?;
+    status_jni(env, &j_files, &state.files)?;
+
+    Ok(env.new_object(
+        "io/github/christianjann/gittasks/manager/GitRepoState",
+        "(ILjava/util/List;)V",
+        &[
+            JValue::Int(state.operation as jint),
+            JValue::Object(&j_files),
+        ],
+    )?)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getReflogLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_list: JObject<'local>,
+    j_name: JString<'local>,
+) -> jint {
+    let name = optional_string(&mut env, &j_name);
+
+    let entries = unwrap_or_log!(libgit2::get_reflog(name.as_deref()), "get_reflog");
+
+    if let Err(e) = reflog_jni(&mut env, &j_list, &entries) {
+        jni_error("reflog_jni", e);
+        return -1;
+    }
+
+    OK
+}
+
+fn reflog_jni<'local>(
+    env: &mut JNIEnv<'local>,
+    j_list: &JObject<'local>,
+    entries: &[libgit2::ReflogEntry],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for entry in entries {
+        let j_old_id = env.new_string(&entry.old_id)?;
+        let j_new_id = env.new_string(&entry.new_id)?;
+        let j_message = env.new_string(&entry.message)?;
+        let j_entry = env.new_object(
+            "io/github/christianjann/gittasks/manager/GitReflogEntry",
+            "(ILjava/lang/String;Ljava/lang/String;Ljava/lang/String;J)V",
+            &[
+                JValue::Int(entry.index as jint),
+                JValue::Object(&j_old_id),
+                JValue::Object(&j_new_id),
+                JValue::Object(&j_message),
+                JValue::Long(entry.time),
+            ],
+        )?;
+        env.call_method(
+            j_list,
+            "add",
+            "(Ljava/lang/Object;)Z",
+            &[JValue::Object(&j_entry)],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_resetToReflogLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    j_name: JString<'local>,
+    index: jint,
+) -> jint {
+    let name = optional_string(&mut env, &j_name);
+
+    unwrap_or_log!(
+        index_arg(index).and_then(|index| libgit2::reset_to_reflog(name.as_deref(), index)),
+        "reset_to_reflog"
+    );
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_startAutoCommitLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    quiet_period_ms: jlong,
+    amend: jboolean,
+    name: JString<'local>,
+    email: JString<'local>,
+) {
+    let name: String = env.get_string(&name).unwrap().into();
+    let email: String = env.get_string(&email).unwrap().into();
+
+    libgit2::start_auto_commit(libgit2::AutoCommitSettings {
+        quiet_period: Duration::from_millis(quiet_period_ms.max(0) as u64),
+        amend: amend != 0,
+        name,
+        email,
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_stopAutoCommitLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jint {
+    unwrap_or_log!(libgit2::stop_auto_commit(), "stop_auto_commit");
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_notifyFileChangedLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    path: JString<'local>,
+) {
+    let path: String = env.get_string(&path).unwrap().into();
+
+    libgit2::notify_file_changed(&path);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_flushAutoCommitLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jint {
+    unwrap_or_log!(libgit2::flush_auto_commit(), "flush_auto_commit");
+
+    OK
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_setSquashWindowLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    window_secs: jlong,
+) {
+    libgit2::set_squash_window((window_secs > 0).then(|| Duration::from_secs(window_secs as u64)));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_getPendingJournalLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jobject {
+    let pending = unwrap_or_log!(
+        libgit2::pending_journal(),
+        "pending_journal",
+        std::ptr::null_mut()
+    );
+
+    journal_replay_jni(&mut env, pending)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_replayJournalLib<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jobject {
+    let replay = unwrap_or_log!(
+        libgit2::replay_journal(),
+        "replay_journal",
+        std::ptr::null_mut()
+    );
+
+    journal_replay_jni(&mut env, replay)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn Java_io_github_christianjann_gittasks_manager_GitManagerKt_discardJournalLib<
+    'local,
+>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+) -> jint {
+    unwrap_or_log!(libgit2::discard_journal(), "discard_journal");
+
+    OK
+}
+
+fn journal_replay_jni(env: &mut JNIEnv, replay: Option<libgit2::Replay>) -> jobject {
+    let Some(replay) = replay else {
+        return std::ptr::null_mut();
+    };
+
+    let res = env.new_object(
+        "io/github/christianjann/gittasks/manager/GitJournalReplay",
+        "(II)V",
+        &[
+            JValue::Int(replay.operation as jint),
+            JValue::Int(replay.outcome as jint),
+        ],
+    );
+
+    match res {
+        Ok(obj) => obj.into_raw(),
+        Err(e) => {
+            jni_error("journal_replay_jni", e);
+            std::ptr::null_mut()
+        }
+    }
 }
@@ -0,0 +1,368 @@
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    sync::{Condvar, LazyLock, Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+
+use git2::{Commit, ErrorCode, Index, IndexAddOption, Oid, Repository, ResetType, Signature, Tree};
+
+use super::{REMOTE, signing};
+use crate::Error;
+
+/// Summary of the commits made by the scheduler, only those are amended
+pub const MESSAGE: &str = "Auto-commit";
+
+/// Source of the current time, replaced by a fake clock in tests
+pub trait Clock: Send {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoCommitSettings {
+    /// Time without any change before the pending changes are committed
+    pub quiet_period: Duration,
+    /// Amend the previous auto-commit as long as it is not pushed, instead of adding one
+    pub amend: bool,
+    pub name: String,
+    pub email: String,
+}
+
+/// Collects changed paths until no change arrived for the quiet period
+pub struct Scheduler<C: Clock = SystemClock> {
+    clock: C,
+    quiet_period: Duration,
+    pending: BTreeSet<String>,
+    last_change: Option<Instant>,
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C, quiet_period: Duration) -> Self {
+        Self {
+            clock,
+            quiet_period,
+            pending: BTreeSet::new(),
+            last_change: None,
+        }
+    }
+
+    /// `path` changed, which restarts the quiet period
+    pub fn notify(&mut self, path: &str) {
+        self.pending.insert(path.to_string());
+        self.last_change = Some(self.clock.now());
+    }
+
+    /// Pending `paths` whose commit failed, they are due after another quiet period
+    pub fn requeue(&mut self, paths: &[String]) {
+        for path in paths {
+            self.notify(path);
+        }
+    }
+
+    /// When the pending changes are due, `None` without pending changes
+    pub fn deadline(&self) -> Option<Instant> {
+        self.last_change
+            .map(|last_change| last_change + self.quiet_period)
+    }
+
+    /// The pending paths, if the quiet period has passed since the last change
+    pub fn take_due(&mut self) -> Option<Vec<String>> {
+        let deadline = self.deadline()?;
+        if self.clock.now() < deadline {
+            return None;
+        }
+
+        Some(self.take_all())
+    }
+
+    /// The pending paths, without waiting for the quiet period
+    pub fn take_all(&mut self) -> Vec<String> {
+        self.last_change = None;
+        std::mem::take(&mut self.pending).into_iter().collect()
+    }
+}
+
+struct Worker {
+    settings: AutoCommitSettings,
+    scheduler: Scheduler,
+}
+
+static WORKER: LazyLock<Mutex<Option<Worker>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Wakes the worker thread when a change arrives or the settings change
+static WAKE: Condvar = Condvar::new();
+
+static SPAWN: Once = Once::new();
+
+/// Commits the changed paths of a batch with the given settings, while no lock of
+/// this module is held
+pub type CommitFn = fn(&AutoCommitSettings, &[String]) -> Result<(), Error>;
+
+/// Start committing changes once they settle, or apply new `settings`.
+/// Pending changes are kept.
+pub fn start(settings: AutoCommitSettings, commit: CommitFn) {
+    info!(
+        "Auto-commit after {}ms, amend: {}",
+        settings.quiet_period.as_millis(),
+        settings.amend
+    );
+
+    let mut worker = WORKER.lock().expect("auto-commit lock");
+    match worker.as_mut() {
+        Some(worker) => {
+            worker.scheduler.quiet_period = settings.quiet_period;
+            worker.settings = settings;
+        }
+        None => {
+            *worker = Some(Worker {
+                scheduler: Scheduler::new(SystemClock, settings.quiet_period),
+                settings,
+            });
+        }
+    }
+    drop(worker);
+
+    SPAWN.call_once(|| {
+        thread::spawn(move || run(commit));
+    });
+    WAKE.notify_all();
+}
+
+/// Stop the scheduler, returning the changes that are still pending
+pub fn stop() -> Option<(Vec<String>, AutoCommitSettings)> {
+    let worker = WORKER.lock().expect("auto-commit lock").take()?;
+    WAKE.notify_all();
+
+    let mut scheduler = worker.scheduler;
+    let paths = scheduler.take_all();
+
+    (!paths.is_empty()).then_some((paths, worker.settings))
+}
+
+/// The commit of `paths` failed, keep them pending for the next deadline or flush.
+/// Ignored while the scheduler is stopped.
+pub fn requeue(paths: &[String]) {
+    if let Some(worker) = WORKER.lock().expect("auto-commit lock").as_mut() {
+        worker.scheduler.requeue(paths);
+        WAKE.notify_all();
+    }
+}
+
+/// Ignored while the scheduler is stopped
+pub fn notify(path: &str) {
+    if let Some(worker) = WORKER.lock().expect("auto-commit lock").as_mut() {
+        worker.scheduler.notify(path);
+        WAKE.notify_all();
+    }
+}
+
+/// The pending changes, to commit them right away, e.g. before a sync
+pub fn take_pending() -> Option<(Vec<String>, AutoCommitSettings)> {
+    let mut worker = WORKER.lock().expect("auto-commit lock");
+    let worker = worker.as_mut()?;
+
+    let paths = worker.scheduler.take_all();
+
+    (!paths.is_empty()).then(|| (paths, worker.settings.clone()))
+}
+
+fn run(commit: CommitFn) {
+    let mut worker = WORKER.lock().expect("auto-commit lock");
+
+    loop {
+        let Some(state) = worker.as_mut() else {
+            worker = WAKE.wait(worker).expect("auto-commit lock");
+            continue;
+        };
+
+        if let Some(paths) = state.scheduler.take_due() {
+            let settings = state.settings.clone();
+            drop(worker);
+
+            debug!("Auto-commit of {paths:?}");
+            let result = commit(&settings, &paths);
+
+            worker = WORKER.lock().expect("auto-commit lock");
+            if let Err(e) = result {
+                warn!("Auto-commit failed, retrying later: {e}");
+                if let Some(state) = worker.as_mut() {
+                    state.scheduler.requeue(&paths);
+                }
+            }
+            continue;
+        }
+
+        worker = match state.scheduler.deadline() {
+            Some(deadline) => {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                WAKE.wait_timeout(worker, timeout)
+                    .expect("auto-commit lock")
+                    .0
+            }
+            None => WAKE.wait(worker).expect("auto-commit lock"),
+        };
+    }
+}
+
+/// Commit the changes of `paths`, relative to the working tree, as an auto-commit.
+/// Other changes are left alone. Returns `None` when there was nothing to commit.
+///
+/// With [`AutoCommitSettings::amend`] an auto-commit at HEAD that is not on the
+/// remote yet is replaced instead, or dropped if the changes were undone.
+pub(super) fn commit(
+    repo: &Repository,
+    settings: &AutoCommitSettings,
+    paths: &[String],
+) -> Result<Option<Oid>, Error> {
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| Error::git2(e, "head"))?;
+
+    let mut index = repo.index().map_err(|e| Error::git2(e, "index"))?;
+    stage(repo, &mut index, paths)?;
+    index.write().map_err(|e| Error::git2(e, "write"))?;
+
+    let tree = index
+        .write_tree()
+        .and_then(|oid| repo.find_tree(oid))
+        .map_err(|e| Error::git2(e, "write_tree"))?;
+
+    if tree.id() == head.tree_id() {
+        return Ok(None);
+    }
+
+    let sig = Signature::now(&settings.name, &settings.email)
+        .map_err(|e| Error::git2(e, "Signature::now"))?;
+
+    if !(settings.amend && is_amendable(repo, &head)?) {
+        let message = message(repo, Some(&head), &tree)?;
+        let oid = signing::commit(repo, &sig, &sig, &message, &tree, &[&head])
+            .map_err(|e| Error::git2(e, "commit"))?;
+
+        info!("Auto-committed {oid}");
+        return Ok(Some(oid));
+    }
+
+    let parent = head.parent(0).ok();
+    if let Some(parent) = &parent
+        && parent.tree_id() == tree.id()
+    {
+        repo.reset(parent.as_object(), ResetType::Soft, None)
+            .map_err(|e| Error::git2(e, "reset"))?;
+
+        info!("Dropped auto-commit {}, its changes were undone", head.id());
+        return Ok(None);
+    }
+
+    let message = message(repo, parent.as_ref(), &tree)?;
+    let oid = signing::amend(repo, &head, &sig, &sig, &message, &tree)
+        .map_err(|e| Error::git2(e, "amend"))?;
+
+    info!("Amended auto-commit {} as {oid}", head.id());
+    Ok(Some(oid))
+}
+
+/// Add the files of `paths` to the index, or remove those that were deleted.
+/// A directory stands for all files below it, e.g. after it was moved.
+/// Ignored files are only staged when they are tracked already.
+fn stage(repo: &Repository, index: &mut Index, paths: &[String]) -> Result<(), Error> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::repo_state("auto-commit: bare repository"))?;
+
+    for path in paths.iter().map(Path::new) {
+        let full_path = workdir.join(path);
+        if full_path.is_dir() {
+            index
+                .update_all([path], None)
+                .map_err(|e| Error::git2(e, "update_all"))?;
+            index
+                .add_all([path], IndexAddOption::DEFAULT, None)
+                .map_err(|e| Error::git2(e, "add_all"))?;
+            continue;
+        }
+
+        if !full_path.is_file() {
+            index
+                .remove_path(path)
+                .map_err(|e| Error::git2(e, "remove_path"))?;
+            index
+                .remove_dir(path, 0)
+                .map_err(|e| Error::git2(e, "remove_dir"))?;
+            continue;
+        }
+
+        let ignored = repo
+            .is_path_ignored(path)
+            .map_err(|e| Error::git2(e, "is_path_ignored"))?;
+        if ignored && index.get_path(path, 0).is_none() {
+            continue;
+        }
+
+        index
+            .add_path(path)
+            .map_err(|e| Error::git2(e, "add_path"))?;
+    }
+
+    Ok(())
+}
+
+/// Whether `head` is an auto-commit that was not pushed yet
+fn is_amendable(repo: &Repository, head: &Commit) -> Result<bool, Error> {
+    if head.summary() != Some(MESSAGE) || head.parent_count() > 1 {
+        return Ok(false);
+    }
+
+    let branch = repo.head().map_err(|e| Error::git2(e, "head"))?;
+    let Some(branch) = branch.shorthand().filter(|_| branch.is_branch()) else {
+        return Ok(false);
+    };
+
+    let upstream = match repo.find_reference(&format!("refs/remotes/{REMOTE}/{branch}")) {
+        Ok(upstream) => upstream
+            .peel_to_commit()
+            .map_err(|e| Error::git2(e, "upstream"))?
+            .id(),
+        // Never pushed
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(true),
+        Err(e) => return Err(Error::git2(e, "upstream")),
+    };
+
+    let pushed = upstream == head.id()
+        || repo
+            .graph_descendant_of(upstream, head.id())
+            .map_err(|e| Error::git2(e, "graph_descendant_of"))?;
+
+    Ok(!pushed)
+}
+
+/// [`MESSAGE`] with the paths changed since `base`
+fn message(repo: &Repository, base: Option<&Commit>, tree: &Tree) -> Result<String, Error> {
+    let base_tree = match base {
+        Some(base) => Some(base.tree().map_err(|e| Error::git2(e, "tree"))?),
+        None => None,
+    };
+
+    let diff = repo
+        .diff_tree_to_tree(base_tree.as_ref(), Some(tree), None)
+        .map_err(|e| Error::git2(e, "diff_tree_to_tree"))?;
+
+    let mut message = format!("{MESSAGE}\n");
+    for delta in diff.deltas() {
+        if let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) {
+            message.push_str(&format!("\n{}", path.to_string_lossy()));
+        }
+    }
+
+    Ok(message)
+}
@@ -0,0 +1,605 @@
+use std::{collections::HashSet, path::Path};
+
+use chrono::{DateTime, Local};
+use git2::{
+    Commit, Delta, Diff, DiffFindOptions, DiffFormat, DiffOptions, Oid, Patch, Repository, Revwalk,
+    Sort, Time,
+};
+
+use super::signing::{self, AllowedSigners, SignatureStatus};
+use crate::{Error, mime_types::is_extension_supported};
+
+#[derive(Debug)]
+pub struct GitLogEntry {
+    pub hash: String,
+    pub message: String,
+    pub author: String,
+    pub date: String,
+    pub signature_status: SignatureStatus,
+    pub signer: Option<String>,
+    /// Only filled in with [`LogQuery::include_files`]
+    pub files: Vec<ChangedFile>,
+}
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Added = 0,
+    Modified = 1,
+    Deleted = 2,
+    Renamed = 3,
+}
+
+/// A file changed by a commit, compared to its first parent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub path: String,
+    /// Path before a rename
+    pub old_path: Option<String>,
+    pub status: FileStatus,
+    /// Lines added and removed, 0 for binary files
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Person {
+    pub name: String,
+    pub email: String,
+    /// Milliseconds since the epoch
+    pub time: i64,
+    /// Offset of the timezone from UTC, in minutes
+    pub offset_minutes: i32,
+}
+
+/// What [`search`] looks for in the older versions of the notes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryQuery {
+    /// Text to find in a line
+    pub pattern: String,
+    pub ignore_case: bool,
+    /// Only look at files below this folder, or this file
+    pub path: Option<String>,
+    /// Commit time range in milliseconds, both ends included
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub limit: usize,
+}
+
+/// A line of a note as it was in `hash`, `checkout_path` with that commit restores it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryMatch {
+    pub hash: String,
+    /// Commit time in milliseconds
+    pub time: i64,
+    pub path: String,
+    /// Starting at 1
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// A line of the commit graph between a row and the next one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+}
+
+/// One commit of the graph, in the same order as the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphRow {
+    pub hash: String,
+    pub parents: Vec<String>,
+    /// Lane of the commit
+    pub column: usize,
+    /// Lines from this row to the next one, towards the parents
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitDetails {
+    pub hash: String,
+    pub parents: Vec<String>,
+    pub message: String,
+    pub author: Person,
+    pub committer: Person,
+    pub files: Vec<ChangedFile>,
+}
+
+/// Which commits [`query`] returns, all filters must match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogQuery {
+    pub limit: usize,
+    /// Hash of the last entry of the previous page, the log continues after it
+    pub after: Option<String>,
+    /// Number of matching commits to skip
+    pub offset: usize,
+    /// Case insensitive part of the author name or email
+    pub author: Option<String>,
+    /// Commit time range in milliseconds, both ends included
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// File or folder the commit has to change, compared to its first parent
+    pub path: Option<String>,
+    /// Case insensitive part of the commit message
+    pub message: Option<String>,
+    pub no_merges: bool,
+    /// Fill in [`GitLogEntry::files`]
+    pub include_files: bool,
+}
+
+impl Default for LogQuery {
+    fn default() -> Self {
+        Self {
+            limit: 20,
+            after: None,
+            offset: 0,
+            author: None,
+            since: None,
+            until: None,
+            path: None,
+            message: None,
+            no_merges: false,
+            include_files: false,
+        }
+    }
+}
+
+pub fn query(repo: &Repository, query: &LogQuery) -> Result<Vec<GitLogEntry>, Error> {
+    // Empty repositories have no commits yet
+    if repo.head().is_err() {
+        debug!("Repository has no commits yet, returning empty log");
+        return Ok(Vec::new());
+    }
+
+    let after = match &query.after {
+        Some(after) => Some(parse_oid(after)?),
+        None => None,
+    };
+
+    let revwalk = walk_head(repo)?;
+
+    let allowed_signers = AllowedSigners::load(repo);
+    let author = query.author.as_ref().map(|author| author.to_lowercase());
+    let message = query.message.as_ref().map(|message| message.to_lowercase());
+
+    // Walk the same order as the first page, so that no commit is skipped or repeated
+    let mut past_cursor = after.is_none();
+    let mut to_skip = query.offset;
+    let mut entries = Vec::new();
+
+    for oid in revwalk {
+        if entries.len() >= query.limit {
+            break;
+        }
+
+        let oid = oid?;
+        if !past_cursor {
+            past_cursor = Some(oid) == after;
+            continue;
+        }
+
+        let commit = repo.find_commit(oid)?;
+
+        if !matches(repo, &commit, query, author.as_deref(), message.as_deref())? {
+            continue;
+        }
+
+        if to_skip > 0 {
+            to_skip -= 1;
+            continue;
+        }
+
+        let mut entry = log_entry(repo, &commit, &allowed_signers);
+        if query.include_files {
+            entry.files = changed_files(repo, &commit)?;
+        }
+        entries.push(entry);
+    }
+
+    // E.g. the commit was dropped by a sync, an empty page would end the log
+    if !past_cursor {
+        return Err(Error::invalid_input("unknown cursor"));
+    }
+
+    Ok(entries)
+}
+
+/// `author` and `message` are the lowercase filters of `query`
+fn matches(
+    repo: &Repository,
+    commit: &Commit,
+    query: &LogQuery,
+    author: Option<&str>,
+    message: Option<&str>,
+) -> Result<bool, Error> {
+    if query.no_merges && commit.parent_count() > 1 {
+        return Ok(false);
+    }
+
+    let time = commit.time().seconds() * 1000;
+    if query.since.is_some_and(|since| time < since)
+        || query.until.is_some_and(|until| time > until)
+    {
+        return Ok(false);
+    }
+
+    if let Some(author) = author {
+        let signature = commit.author();
+        let name = signature.name().unwrap_or_default().to_lowercase();
+        let email = signature.email().unwrap_or_default().to_lowercase();
+        if !name.contains(author) && !email.contains(author) {
+            return Ok(false);
+        }
+    }
+
+    if let Some(message) = message
+        && !commit
+            .message()
+            .unwrap_or_default()
+            .to_lowercase()
+            .contains(message)
+    {
+        return Ok(false);
+    }
+
+    if let Some(path) = &query.path {
+        return touches(repo, commit, path);
+    }
+
+    Ok(true)
+}
+
+/// Children before parents, newest first otherwise, like `git log --graph`
+fn walk_head(repo: &Repository) -> Result<Revwalk<'_>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    Ok(revwalk)
+}
+
+/// Lanes of the commits `offset..offset + limit` of the log. The commits
+/// before the window are walked too, so that pages fit together.
+pub fn graph(repo: &Repository, offset: usize, limit: usize) -> Result<Vec<GraphRow>, Error> {
+    if repo.head().is_err() {
+        return Ok(Vec::new());
+    }
+
+    // The commit each lane waits for
+    let mut lanes: Vec<Option<Oid>> = Vec::new();
+    // Lines leaving the previous row: the lane they end in and their column
+    let mut sources: Vec<(usize, usize)> = Vec::new();
+    let mut rows: Vec<GraphRow> = Vec::new();
+
+    for (index, oid) in walk_head(repo)?.enumerate() {
+        if index >= offset.saturating_add(limit) {
+            break;
+        }
+
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+
+        let column = match lanes.iter().position(|lane| *lane == Some(oid)) {
+            Some(column) => column,
+            None => free_lane(&mut lanes),
+        };
+
+        if let Some(previous) = rows.last_mut() {
+            previous.edges = edges(&sources, |lane| {
+                if lanes[lane] == Some(oid) {
+                    column
+                } else {
+                    lane
+                }
+            });
+        }
+
+        // All lanes waiting for this commit end here
+        for lane in lanes.iter_mut().filter(|lane| **lane == Some(oid)) {
+            *lane = None;
+        }
+
+        let mut next_sources: Vec<(usize, usize)> = lanes
+            .iter()
+            .enumerate()
+            .filter(|(_, lane)| lane.is_some())
+            .map(|(lane, _)| (lane, lane))
+            .collect();
+
+        for (i, parent) in commit.parent_ids().enumerate() {
+            let lane = match lanes.iter().position(|lane| *lane == Some(parent)) {
+                Some(lane) => lane,
+                // The first parent continues the lane of the commit
+                None if i == 0 => {
+                    lanes[column] = Some(parent);
+                    column
+                }
+                None => {
+                    let lane = free_lane(&mut lanes);
+                    lanes[lane] = Some(parent);
+                    lane
+                }
+            };
+            next_sources.push((lane, column));
+        }
+
+        while lanes.last() == Some(&None) {
+            lanes.pop();
+        }
+        sources = next_sources;
+
+        if index >= offset {
+            rows.push(GraphRow {
+                hash: oid.to_string(),
+                parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+                column,
+                edges: Vec::new(),
+            });
+        }
+    }
+
+    // Parents below the window keep their lane
+    if let Some(last) = rows.last_mut() {
+        last.edges = edges(&sources, |lane| lane);
+    }
+
+    Ok(rows)
+}
+
+fn free_lane(lanes: &mut Vec<Option<Oid>>) -> usize {
+    match lanes.iter().position(Option::is_none) {
+        Some(lane) => lane,
+        None => {
+            lanes.push(None);
+            lanes.len() - 1
+        }
+    }
+}
+
+fn edges(sources: &[(usize, usize)], target: impl Fn(usize) -> usize) -> Vec<GraphEdge> {
+    let mut edges: Vec<GraphEdge> = sources
+        .iter()
+        .map(|&(lane, from)| GraphEdge {
+            from,
+            to: target(lane),
+        })
+        .collect();
+
+    edges.sort_by_key(|edge| (edge.from, edge.to));
+    edges.dedup();
+    edges
+}
+
+/// Search the versions of the notes committed in the range of `query`, newest first.
+///
+/// Each version of a file is searched once, in the commit that introduced it.
+/// A line that stayed the same over several versions is only reported for the
+/// newest one, which is the best one to restore.
+pub fn search(repo: &Repository, query: &HistoryQuery) -> Result<Vec<HistoryMatch>, Error> {
+    if repo.head().is_err() || query.pattern.is_empty() || query.limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let pattern = if query.ignore_case {
+        query.pattern.to_lowercase()
+    } else {
+        query.pattern.clone()
+    };
+
+    let mut searched_blobs = HashSet::new();
+    let mut seen_lines = HashSet::new();
+    let mut matches = Vec::new();
+
+    for oid in walk_head(repo)? {
+        let commit = repo.find_commit(oid?)?;
+
+        let time = commit.time().seconds() * 1000;
+        if query.until.is_some_and(|until| time > until) {
+            continue;
+        }
+        // Commits are only roughly sorted by time, skip rather than stop
+        if query.since.is_some_and(|since| time < since) {
+            continue;
+        }
+
+        let parent_tree = first_parent_tree(&commit)?;
+        let mut options = DiffOptions::new();
+        if let Some(path) = &query.path {
+            options.pathspec(path.trim_end_matches('/'));
+        }
+        let diff = repo.diff_tree_to_tree(
+            parent_tree.as_ref(),
+            Some(&commit.tree()?),
+            Some(&mut options),
+        )?;
+
+        for delta in diff.deltas() {
+            if delta.status() == Delta::Deleted {
+                continue;
+            }
+
+            let file = delta.new_file();
+            let Some(path) = file.path().and_then(|path| path.to_str()) else {
+                continue;
+            };
+            let supported = Path::new(path)
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(is_extension_supported);
+
+            if !supported || !searched_blobs.insert(file.id()) {
+                continue;
+            }
+
+            let blob = repo.find_blob(file.id())?;
+            if blob.is_binary() {
+                continue;
+            }
+
+            let content = String::from_utf8_lossy(blob.content());
+            for (index, line) in content.lines().enumerate() {
+                let found = if query.ignore_case {
+                    line.to_lowercase().contains(&pattern)
+                } else {
+                    line.contains(&pattern)
+                };
+
+                if !found || !seen_lines.insert((path.to_string(), line.to_string())) {
+                    continue;
+                }
+
+                matches.push(HistoryMatch {
+                    hash: commit.id().to_string(),
+                    time,
+                    path: path.to_string(),
+                    line_number: index + 1,
+                    line: line.to_string(),
+                });
+
+                if matches.len() >= query.limit {
+                    return Ok(matches);
+                }
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+pub fn commit_details(repo: &Repository, hash: &str) -> Result<CommitDetails, Error> {
+    let commit = repo.find_commit(parse_oid(hash)?)?;
+
+    Ok(CommitDetails {
+        hash: commit.id().to_string(),
+        parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+        message: commit.message().unwrap_or("").to_string(),
+        author: person(&commit.author()),
+        committer: person(&commit.committer()),
+        files: changed_files(repo, &commit)?,
+    })
+}
+
+fn parse_oid(hash: &str) -> Result<Oid, Error> {
+    Oid::from_str(hash).map_err(|_| Error::invalid_input(&format!("invalid commit hash: {hash}")))
+}
+
+fn person(signature: &git2::Signature) -> Person {
+    let time: Time = signature.when();
+
+    Person {
+        name: signature.name().unwrap_or("").to_string(),
+        email: signature.email().unwrap_or("").to_string(),
+        time: time.seconds() * 1000,
+        offset_minutes: time.offset_minutes(),
+    }
+}
+
+fn first_parent_tree<'r>(commit: &Commit<'r>) -> Result<Option<git2::Tree<'r>>, Error> {
+    // The initial commit adds everything
+    Ok(match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    })
+}
+
+/// Files `commit` changed compared to its first parent, renames are detected
+fn changed_files(repo: &Repository, commit: &Commit) -> Result<Vec<ChangedFile>, Error> {
+    let parent_tree = first_parent_tree(commit)?;
+
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+    diff_files(diff)
+}
+
+/// Files in `diff` with their line stats, renames are detected
+pub(super) fn diff_files(mut diff: Diff) -> Result<Vec<ChangedFile>, Error> {
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let mut files = Vec::new();
+
+    for (index, delta) in diff.deltas().enumerate() {
+        let path =
+            |file: git2::DiffFile| file.path().map(|path| path.to_string_lossy().to_string());
+
+        let status = match delta.status() {
+            Delta::Added => FileStatus::Added,
+            Delta::Deleted => FileStatus::Deleted,
+            Delta::Renamed => FileStatus::Renamed,
+            _ => FileStatus::Modified,
+        };
+
+        let (additions, deletions) = match Patch::from_diff(&diff, index)? {
+            Some(patch) => {
+                let (_, additions, deletions) = patch.line_stats()?;
+                (additions, deletions)
+            }
+            None => (0, 0),
+        };
+
+        files.push(ChangedFile {
+            path: path(delta.new_file())
+                .or_else(|| path(delta.old_file()))
+                .unwrap_or_default(),
+            old_path: (status == FileStatus::Renamed)
+                .then(|| path(delta.old_file()))
+                .flatten(),
+            status,
+            additions,
+            deletions,
+        });
+    }
+
+    Ok(files)
+}
+
+/// `diff` as a unified diff
+pub(super) fn patch_text(diff: &Diff) -> Result<String, Error> {
+    let mut patch = String::new();
+    diff.print(DiffFormat::Patch, |_delta, _hunk, line| {
+        if let origin @ ('+' | '-' | ' ') = line.origin() {
+            patch.push(origin);
+        }
+        patch.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+
+    Ok(patch)
+}
+
+/// Whether `commit` changed `path` or something below it
+fn touches(repo: &Repository, commit: &Commit, path: &str) -> Result<bool, Error> {
+    let parent_tree = first_parent_tree(commit)?;
+
+    let mut options = DiffOptions::new();
+    options.pathspec(path.trim_end_matches('/'));
+
+    let diff = repo.diff_tree_to_tree(
+        parent_tree.as_ref(),
+        Some(&commit.tree()?),
+        Some(&mut options),
+    )?;
+
+    Ok(diff.deltas().len() > 0)
+}
+
+fn log_entry(repo: &Repository, commit: &Commit, allowed_signers: &AllowedSigners) -> GitLogEntry {
+    // Format date as readable string in local timezone
+    let time = commit.time();
+    let datetime = DateTime::from_timestamp(time.seconds(), 0).unwrap_or(DateTime::UNIX_EPOCH);
+    let date = datetime
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    let signature = signing::verify_commit(repo, commit.id(), allowed_signers);
+
+    GitLogEntry {
+        hash: commit.id().to_string(),
+        message: commit.message().unwrap_or("").to_string(),
+        author: commit.author().name().unwrap_or("").to_string(),
+        date,
+        signature_status: signature.status,
+        signer: signature.signer,
+        files: Vec::new(),
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs,
+    path::Path,
+};
+
+use git2::{ObjectType, Oid, Repository};
+
+use super::REMOTE;
+use crate::Error;
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IssueKind {
+    /// Object that is missing or can't be decompressed
+    UnreadableObject = 0,
+    /// Ref file that can't be parsed, or points to an unreadable commit
+    BrokenRef = 1,
+    /// Index that can't be loaded, or lists blobs that don't exist
+    CorruptIndex = 2,
+    /// HEAD that can't be parsed, or points to a branch or commit that doesn't exist
+    DanglingHead = 3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// Object id, ref name, or the index/HEAD file name
+    pub target: String,
+    pub detail: String,
+}
+
+impl Issue {
+    fn new(kind: IssueKind, target: &str, detail: impl ToString) -> Self {
+        Self {
+            kind,
+            target: target.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// Report everything that keeps git operations from working. Never modifies the repository.
+pub fn verify(repo: &Repository) -> Result<Vec<Issue>, Error> {
+    let mut issues = Vec::new();
+
+    let unreadable = unreadable_objects(repo)?;
+    for (oid, detail) in &unreadable {
+        issues.push(Issue::new(
+            IssueKind::UnreadableObject,
+            &oid.to_string(),
+            detail,
+        ));
+    }
+
+    let refs = ref_names(repo);
+    for name in &refs {
+        if let Err(detail) = check_ref(repo, name) {
+            issues.push(Issue::new(IssueKind::BrokenRef, name, detail));
+        }
+    }
+
+    if let Err(detail) = check_head(repo, &refs) {
+        issues.push(Issue::new(IssueKind::DanglingHead, "HEAD", detail));
+    }
+
+    if let Err(detail) = check_index(repo) {
+        issues.push(Issue::new(IssueKind::CorruptIndex, "index", detail));
+    }
+
+    for issue in &issues {
+        warn!(
+            "Integrity: {:?} {}: {}",
+            issue.kind, issue.target, issue.detail
+        );
+    }
+
+    Ok(issues)
+}
+
+/// Fix the issues found by [`verify`] and return the ones that are left.
+///
+/// Objects are copied from `remote`, a fresh clone of the remote, if given.
+/// Refs are restored from their reflog, or from the remote-tracking branch.
+/// Broken remote-tracking branches that can't be restored are removed, other
+/// refs are left as they are and reported.
+/// The index is rebuilt from HEAD. The working tree is never touched, so
+/// uncommitted changes survive and show up as modified files.
+pub fn repair(repo: &Repository, remote: Option<&Repository>) -> Result<Vec<Issue>, Error> {
+    let issues = verify(repo)?;
+    if issues.is_empty() {
+        return Ok(issues);
+    }
+
+    // Objects first, so the refs pointing to them become valid again
+    if let Some(remote) = remote {
+        for issue in issues
+            .iter()
+            .filter(|issue| issue.kind == IssueKind::UnreadableObject)
+        {
+            restore_object(repo, remote, &issue.target);
+        }
+    }
+
+    let refs = ref_names(repo);
+    for name in &refs {
+        if check_ref(repo, name).is_err() {
+            repair_ref(repo, name);
+        }
+    }
+
+    if check_head(repo, &ref_names(repo)).is_err() {
+        repair_head(repo);
+    }
+
+    if check_index(repo).is_err() {
+        rebuild_index(repo)?;
+    }
+
+    verify(repo)
+}
+
+fn unreadable_objects(repo: &Repository) -> Result<BTreeMap<Oid, String>, Error> {
+    let odb = repo.odb().map_err(|e| Error::git2(e, "odb"))?;
+    let mut unreadable = BTreeMap::new();
+
+    // Corrupt objects, reachable or not
+    odb.foreach(|oid| {
+        if let Err(e) = odb.read(*oid) {
+            unreadable.insert(*oid, e.message().to_string());
+        }
+        true
+    })
+    .map_err(|e| Error::git2(e, "odb foreach"))?;
+
+    // Objects referenced from history that don't exist at all
+    let mut commits: Vec<Oid> = ref_names(repo)
+        .iter()
+        .filter_map(|name| repo.find_reference(name).ok()?.target())
+        .collect();
+    let mut seen = HashSet::new();
+
+    while let Some(oid) = commits.pop() {
+        if !seen.insert(oid) || unreadable.contains_key(&oid) {
+            continue;
+        }
+        let commit = match repo.find_commit(oid) {
+            Ok(commit) => commit,
+            // Refs may point to tags or blobs, only commits have a history
+            Err(_) if odb.exists(oid) => continue,
+            Err(e) => {
+                unreadable.insert(oid, e.message().to_string());
+                continue;
+            }
+        };
+        commits.extend(commit.parent_ids());
+        check_tree(repo, &odb, commit.tree_id(), &mut seen, &mut unreadable);
+    }
+
+    Ok(unreadable)
+}
+
+fn check_tree(
+    repo: &Repository,
+    odb: &git2::Odb,
+    oid: Oid,
+    seen: &mut HashSet<Oid>,
+    unreadable: &mut BTreeMap<Oid, String>,
+) {
+    if !seen.insert(oid) || unreadable.contains_key(&oid) {
+        return;
+    }
+
+    let tree = match repo.find_tree(oid) {
+        Ok(tree) => tree,
+        Err(e) => {
+            unreadable.insert(oid, e.message().to_string());
+            return;
+        }
+    };
+
+    for entry in tree.iter() {
+        match entry.kind() {
+            Some(ObjectType::Tree) => check_tree(repo, odb, entry.id(), seen, unreadable),
+            Some(ObjectType::Blob) if seen.insert(entry.id()) && !odb.exists(entry.id()) => {
+                unreadable.insert(entry.id(), "object not found".to_string());
+            }
+            // Submodules live in another repository
+            _ => {}
+        }
+    }
+}
+
+/// Names of all refs, including loose ref files libgit2 refuses to parse
+fn ref_names(repo: &Repository) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+
+    if let Ok(references) = repo.references() {
+        names.extend(
+            references
+                .flatten()
+                .filter_map(|r| r.name().map(str::to_string)),
+        );
+    }
+
+    collect_loose_refs(repo.path(), Path::new("refs"), &mut names);
+
+    names
+}
+
+fn collect_loose_refs(git_dir: &Path, dir: &Path, names: &mut BTreeSet<String>) {
+    let Ok(entries) = fs::read_dir(git_dir.join(dir)) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = dir.join(entry.file_name());
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => collect_loose_refs(git_dir, &path, names),
+            Ok(_) if path.extension().is_some_and(|ext| ext == "lock") => {}
+            Ok(_) => {
+                if let Some(name) = path.to_str() {
+                    names.insert(name.replace('\\', "/"));
+                }
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+fn check_ref(repo: &Repository, name: &str) -> Result<(), String> {
+    let reference = repo
+        .find_reference(name)
+        .map_err(|e| e.message().to_string())?;
+
+    match reference.target() {
+        Some(oid) => repo
+            .find_object(oid, None)
+            .map(|_| ())
+            .map_err(|e| format!("{oid}: {}", e.message())),
+        // Symbolic refs (e.g. refs/remotes/origin/HEAD) are resolved by their target
+        None => Ok(()),
+    }
+}
+
+fn check_head(repo: &Repository, refs: &BTreeSet<String>) -> Result<(), String> {
+    let head = repo
+        .find_reference("HEAD")
+        .map_err(|e| e.message().to_string())?;
+
+    match (head.symbolic_target(), head.target()) {
+        // A missing branch is fine in a repository without any branch yet
+        (Some(target), _) if !refs.contains(target) && has_branches(refs) => {
+            Err(format!("points to missing branch {target}"))
+        }
+        (Some(_), _) => Ok(()),
+        (None, Some(oid)) => repo
+            .find_commit(oid)
+            .map(|_| ())
+            .map_err(|e| format!("{oid}: {}", e.message())),
+        (None, None) => Err("HEAD has no target".to_string()),
+    }
+}
+
+fn check_index(repo: &Repository) -> Result<(), String> {
+    let mut index = repo.index().map_err(|e| e.message().to_string())?;
+    // The repository caches its index, read what is on disk
+    index.read(true).map_err(|e| e.message().to_string())?;
+    let odb = repo.odb().map_err(|e| e.message().to_string())?;
+
+    match index.iter().find(|entry| !odb.exists(entry.id)) {
+        Some(entry) => Err(format!(
+            "{} lists missing blob {}",
+            String::from_utf8_lossy(&entry.path),
+            entry.id
+        )),
+        None => Ok(()),
+    }
+}
+
+fn has_branches(refs: &BTreeSet<String>) -> bool {
+    refs.iter().any(|name| name.starts_with("refs/heads/"))
+}
+
+fn restore_object(repo: &Repository, remote: &Repository, target: &str) {
+    let Ok(oid) = Oid::from_str(target) else {
+        return;
+    };
+
+    let remote_odb = match remote.odb() {
+        Ok(odb) => odb,
+        Err(e) => {
+            warn!("Can't open remote odb: {e}");
+            return;
+        }
+    };
+
+    let object = match remote_odb.read(oid) {
+        Ok(object) => object,
+        Err(e) => {
+            warn!("Can't restore {oid} from remote: {e}");
+            return;
+        }
+    };
+
+    // A corrupt loose file would make the odb skip the write
+    let hex = oid.to_string();
+    let loose = repo.path().join("objects").join(&hex[..2]).join(&hex[2..]);
+    if loose.exists()
+        && let Err(e) = fs::remove_file(&loose)
+    {
+        warn!("Can't remove corrupt object {oid}: {e}");
+        return;
+    }
+
+    match repo
+        .odb()
+        .and_then(|odb| odb.write(object.kind(), object.data()))
+    {
+        Ok(_) => info!("Restored {oid} from remote"),
+        Err(e) => warn!("Can't write {oid}: {e}"),
+    }
+}
+
+fn repair_ref(repo: &Repository, name: &str) {
+    let restored = match intact_reflog_target(repo, name) {
+        Some(oid) => Some((oid, "repair: restored from reflog")),
+        None => name
+            .strip_prefix("refs/heads/")
+            .and_then(|branch| {
+                repo.refname_to_id(&format!("refs/remotes/{REMOTE}/{branch}"))
+                    .ok()
+            })
+            .filter(|oid| is_intact_commit(repo, *oid))
+            .map(|oid| (oid, "repair: restored from remote-tracking branch")),
+    };
+
+    // The next fetch recreates remote-tracking branches, other refs may be the
+    // only way to reach local commits and are left for the user
+    if restored.is_none() && !name.starts_with("refs/remotes/") {
+        warn!("Can't restore {name}, leaving it as it is");
+        return;
+    }
+
+    // The corrupt loose file shadows the packed ref and blocks updates
+    let loose = repo.path().join(name);
+    if loose.is_file()
+        && let Err(e) = fs::remove_file(&loose)
+    {
+        warn!("Can't remove broken ref {name}: {e}");
+        return;
+    }
+
+    let res = match restored {
+        Some((oid, message)) => repo
+            .reference(name, oid, true, message)
+            .map(|_| info!("Restored {name} to {oid}")),
+        None => match repo.find_reference(name) {
+            Ok(mut reference) => reference.delete(),
+            Err(_) => Ok(()),
+        }
+        .map(|_| warn!("Removed broken ref {name}")),
+    };
+
+    if let Err(e) = res {
+        warn!("Can't repair {name}: {e}");
+    }
+}
+
+/// Newest reflog entry of `name` whose commit and tree can be read
+fn intact_reflog_target(repo: &Repository, name: &str) -> Option<Oid> {
+    let reflog = repo.reflog(name).ok()?;
+    reflog
+        .iter()
+        .map(|entry| entry.id_new())
+        .find(|oid| is_intact_commit(repo, *oid))
+}
+
+fn is_intact_commit(repo: &Repository, oid: Oid) -> bool {
+    !oid.is_zero() && repo.find_commit(oid).and_then(|c| c.tree()).is_ok()
+}
+
+fn repair_head(repo: &Repository) {
+    let branches = ref_names(repo);
+    let branch = ["refs/heads/main", "refs/heads/master"]
+        .into_iter()
+        .map(str::to_string)
+        .find(|name| branches.contains(name))
+        .or_else(|| {
+            branches
+                .iter()
+                .find(|name| name.starts_with("refs/heads/"))
+                .cloned()
+        });
+
+    let Some(branch) = branch else {
+        warn!("Can't repair HEAD: no branch left");
+        return;
+    };
+
+    // set_head refuses to work while HEAD can't be parsed
+    let res = fs::write(repo.path().join("HEAD"), format!("ref: {branch}\n"));
+    match res {
+        Ok(()) => info!("Pointed HEAD to {branch}"),
+        Err(e) => warn!("Can't repair HEAD: {e}"),
+    }
+}
+
+fn rebuild_index(repo: &Repository) -> Result<(), Error> {
+    let index_path = repo.path().join("index");
+    if index_path.exists() {
+        fs::remove_file(&index_path).map_err(|e| Error::io(e, "remove corrupt index"))?;
+    }
+
+    let mut index = repo.index().map_err(|e| Error::git2(e, "index"))?;
+    index.clear().map_err(|e| Error::git2(e, "clear index"))?;
+
+    if let Ok(tree) = repo.head().and_then(|head| head.peel_to_tree()) {
+        index
+            .read_tree(&tree)
+            .map_err(|e| Error::git2(e, "read_tree"))?;
+    }
+
+    index.write().map_err(|e| Error::git2(e, "write index"))?;
+    info!("Rebuilt index from HEAD");
+
+    Ok(())
+}
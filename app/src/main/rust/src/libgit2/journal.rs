@@ -0,0 +1,433 @@
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    thread,
+};
+
+use git2::{Oid, Repository, ResetType};
+
+use super::{snapshot, stash};
+use crate::Error;
+
+/// Inside the git directory, so it is never committed
+const JOURNAL: &str = "gittasks/journal";
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalOp {
+    Commit = 0,
+    Pull = 1,
+    Push = 2,
+    Sync = 3,
+}
+
+/// How far an operation got, each step includes the ones before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Started,
+    /// The remote changes were fetched, only the remote-tracking refs changed
+    Fetched,
+    /// The branch and the working tree are being moved to the fetched commit
+    Updating,
+    /// The branch and the working tree reached their new state
+    Updated,
+}
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// Nothing was left half done
+    Discarded = 0,
+    /// The remaining steps were run
+    Resumed = 1,
+    /// The repository is back where the operation started
+    RolledBack = 2,
+}
+
+/// An operation the app did not finish and what [`replay`] does with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Replay {
+    pub operation: JournalOp,
+    pub outcome: ReplayOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    operation: JournalOp,
+    step: Step,
+    /// Branch HEAD pointed to, `None` when detached
+    branch: Option<String>,
+    head: Option<Oid>,
+    /// Commit the branch is moved to, or merged with
+    target: Option<Oid>,
+    /// Uncommitted changes saved before the operation
+    snapshot: Option<Oid>,
+    stash: Option<Oid>,
+}
+
+/// Write-ahead record of a running operation, removed when it returns, also with an
+/// error. It is only left behind when the process dies, then [`replay`] brings the
+/// repository to a consistent state when it is opened again.
+pub(super) struct Journal {
+    path: PathBuf,
+    entry: Entry,
+}
+
+impl Journal {
+    /// Fails if the journal cannot be written, the operation must not start then
+    pub(super) fn begin(repo: &Repository, operation: JournalOp) -> Result<Self, Error> {
+        let head = repo.head().ok();
+
+        let journal = Self {
+            path: repo.path().join(JOURNAL),
+            entry: Entry {
+                operation,
+                step: Step::Started,
+                branch: head
+                    .as_ref()
+                    .filter(|head| head.is_branch())
+                    .and_then(|head| head.name())
+                    .map(str::to_string),
+                head: head.and_then(|head| head.target()),
+                target: None,
+                snapshot: None,
+                stash: None,
+            },
+        };
+        journal.write()?;
+
+        Ok(journal)
+    }
+
+    pub(super) fn step(&mut self, step: Step) {
+        self.entry.step = step;
+        self.update();
+    }
+
+    /// The working tree is about to change, `snapshot` holds its uncommitted changes
+    pub(super) fn updating(&mut self, target: Oid, snapshot: Option<Oid>) {
+        self.entry.step = Step::Updating;
+        self.entry.target = Some(target);
+        self.entry.snapshot = snapshot;
+        self.update();
+    }
+
+    pub(super) fn stash(&mut self, stash: Oid) {
+        self.entry.stash = Some(stash);
+        self.update();
+    }
+
+    /// The operation goes on without a journal rather than failing halfway
+    fn update(&self) {
+        if let Err(e) = self.write() {
+            warn!("Failed to update the journal: {e}");
+        }
+    }
+
+    /// Replace the journal atomically, a crash leaves the old or the new entry
+    fn write(&self) -> Result<(), Error> {
+        let dir = self.path.parent().expect("journal is in a directory");
+        fs::create_dir_all(dir).map_err(|e| Error::io(e, "create journal dir"))?;
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp).map_err(|e| Error::io(e, "create journal"))?;
+        file.write_all(self.entry.to_string().as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|e| Error::io(e, "write journal"))?;
+
+        fs::rename(&tmp, &self.path).map_err(|e| Error::io(e, "rename journal"))?;
+        File::open(dir)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| Error::io(e, "sync journal dir"))
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        // Kept for the replay, the operation did not return
+        if thread::panicking() {
+            return;
+        }
+
+        match fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove the journal: {e}"),
+        }
+    }
+}
+
+/// The operation left in the journal by a process that died, if any, and what
+/// [`replay`] would do with it. Nothing is changed, an unreadable journal is
+/// ignored and replaced by the next operation.
+pub(super) fn pending(repo: &Repository) -> Result<Option<Replay>, Error> {
+    Ok(read(repo)?.map(|entry| Replay {
+        operation: entry.operation,
+        outcome: outcome(&entry),
+    }))
+}
+
+/// Finish or undo the operation left in the journal by a process that died:
+///
+/// - commit and push only move refs, which git updates atomically, nothing is left to do
+/// - a pull or sync that did not start to update the working tree left it untouched,
+///   except for the changes a sync stashed, which are applied again
+/// - a pull or sync that was updating the branch is rolled back, the uncommitted
+///   changes are restored from the snapshot or the stash taken before
+/// - a sync that reset the branch gets its stashed changes back
+///
+/// A roll back is refused when the branch moved on since, the state found before a
+/// roll back is saved in a snapshot.
+pub(super) fn replay(repo: &mut Repository) -> Result<Option<Replay>, Error> {
+    let Some(entry) = read(repo)? else {
+        discard(repo)?;
+        return Ok(None);
+    };
+
+    info!(
+        "Replaying interrupted {:?} stopped at {:?}",
+        entry.operation, entry.step
+    );
+
+    let outcome = outcome(&entry);
+    match outcome {
+        ReplayOutcome::Discarded => {}
+        ReplayOutcome::Resumed => {
+            if let Some(stash) = entry.stash {
+                pop_stash(repo, stash)?;
+            }
+        }
+        ReplayOutcome::RolledBack => roll_back(repo, &entry)?,
+    }
+
+    discard(repo)?;
+
+    info!("Interrupted {:?} {outcome:?}", entry.operation);
+
+    Ok(Some(Replay {
+        operation: entry.operation,
+        outcome,
+    }))
+}
+
+/// Forget the operation left in the journal, keeping the repository as it is
+pub(super) fn discard(repo: &Repository) -> Result<(), Error> {
+    match fs::remove_file(repo.path().join(JOURNAL)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::io(e, "remove journal")),
+    }
+}
+
+fn read(repo: &Repository) -> Result<Option<Entry>, Error> {
+    let content = match fs::read_to_string(repo.path().join(JOURNAL)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(e, "read journal")),
+    };
+
+    let entry = Entry::parse(&content);
+    if entry.is_none() {
+        warn!("Ignoring unreadable journal: {content:?}");
+    }
+
+    Ok(entry)
+}
+
+fn outcome(entry: &Entry) -> ReplayOutcome {
+    match (entry.operation, entry.step, entry.stash) {
+        (JournalOp::Commit | JournalOp::Push, _, _) => ReplayOutcome::Discarded,
+        (JournalOp::Pull | JournalOp::Sync, Step::Updating, _) => ReplayOutcome::RolledBack,
+        // The stash is all a sync changed before it updates the branch
+        (JournalOp::Sync, Step::Started | Step::Fetched, Some(_)) => ReplayOutcome::RolledBack,
+        (JournalOp::Sync, Step::Updated, Some(_)) => ReplayOutcome::Resumed,
+        (JournalOp::Pull | JournalOp::Sync, _, _) => ReplayOutcome::Discarded,
+    }
+}
+
+fn roll_back(repo: &mut Repository, entry: &Entry) -> Result<(), Error> {
+    if entry.step == Step::Updating
+        && let Some(head) = entry.head
+    {
+        let current = check_head(repo, entry)?;
+        snapshot::save(repo, "rollback", true)?;
+
+        let commit = repo
+            .find_commit(head)
+            .map_err(|e| Error::git2(e, "find journal head"))?;
+        let message = format!("rollback: interrupted {:?}", entry.operation);
+
+        match &entry.branch {
+            Some(branch) => {
+                repo.reference_matching(branch, head, true, current, &message)
+                    .map_err(|e| Error::git2(e, "restore branch"))?;
+            }
+            None => repo
+                .set_head_detached(head)
+                .map_err(|e| Error::git2(e, "set_head_detached"))?,
+        }
+
+        repo.reset(commit.as_object(), ResetType::Hard, None)
+            .map_err(|e| Error::git2(e, "reset"))?;
+
+        repo.cleanup_state()
+            .map_err(|e| Error::git2(e, "cleanup_state"))?;
+
+        if let Some(snapshot) = entry.snapshot {
+            let commit = repo
+                .find_commit(snapshot)
+                .map_err(|e| Error::git2(e, "find journal snapshot"))?;
+            snapshot::restore_commit(repo, &commit)?;
+        }
+    }
+
+    if let Some(stash) = entry.stash {
+        pop_stash(repo, stash)?;
+    }
+
+    Ok(())
+}
+
+/// The commit HEAD points to, if it is still on the branch of the operation at the
+/// commit it started from or at one the operation created. Anything else was done
+/// after the operation died and is not rolled back.
+fn check_head(repo: &Repository, entry: &Entry) -> Result<Oid, Error> {
+    let head = repo.head().map_err(|e| Error::git2(e, "head"))?;
+
+    let on_branch = match &entry.branch {
+        Some(branch) => head.is_branch() && head.name() == Some(branch.as_str()),
+        None => !head.is_branch(),
+    };
+    let current = head.target().expect("HEAD is resolved");
+
+    let expected = on_branch
+        && match current {
+            current if Some(current) == entry.head || Some(current) == entry.target => true,
+            // The merge commit of a pull
+            current => {
+                let commit = repo
+                    .find_commit(current)
+                    .map_err(|e| Error::git2(e, "find head"))?;
+                let parents: Vec<Oid> = commit.parent_ids().collect();
+                parents.len() == 2
+                    && Some(parents[0]) == entry.head
+                    && Some(parents[1]) == entry.target
+            }
+        };
+
+    if !expected {
+        return Err(Error::repo_state(
+            "HEAD moved since the interrupted operation, it is not rolled back",
+        ));
+    }
+
+    Ok(current)
+}
+
+/// Apply and drop the stash made by the operation, if it is still there
+fn pop_stash(repo: &mut Repository, stash: Oid) -> Result<(), Error> {
+    let hash = stash.to_string();
+    let Some(index) = stash::list(repo)?
+        .iter()
+        .position(|entry| entry.hash == hash)
+    else {
+        warn!("Stash {hash} of the journal is gone");
+        return Ok(());
+    };
+
+    // Kept in the stash list otherwise, see `list_stashes`
+    match stash::apply(repo, index, true) {
+        Ok(conflicts) if conflicts.is_empty() => {}
+        Ok(conflicts) => warn!("Stashed changes conflict in {conflicts:?}"),
+        Err(e) => warn!("Failed to apply stash {hash}: {e}"),
+    }
+
+    Ok(())
+}
+
+impl Entry {
+    fn parse(content: &str) -> Option<Self> {
+        let mut operation = None;
+        let mut step = None;
+        let mut entry = Self {
+            operation: JournalOp::Commit,
+            step: Step::Started,
+            branch: None,
+            head: None,
+            target: None,
+            snapshot: None,
+            stash: None,
+        };
+
+        for line in content.lines() {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "operation" => {
+                    operation = Some(match value {
+                        "commit" => JournalOp::Commit,
+                        "pull" => JournalOp::Pull,
+                        "push" => JournalOp::Push,
+                        "sync" => JournalOp::Sync,
+                        _ => return None,
+                    })
+                }
+                "step" => {
+                    step = Some(match value {
+                        "started" => Step::Started,
+                        "fetched" => Step::Fetched,
+                        "updating" => Step::Updating,
+                        "updated" => Step::Updated,
+                        _ => return None,
+                    })
+                }
+                "branch" => entry.branch = Some(value.to_string()),
+                "head" => entry.head = Some(Oid::from_str(value).ok()?),
+                "target" => entry.target = Some(Oid::from_str(value).ok()?),
+                "snapshot" => entry.snapshot = Some(Oid::from_str(value).ok()?),
+                "stash" => entry.stash = Some(Oid::from_str(value).ok()?),
+                _ => return None,
+            }
+        }
+
+        entry.operation = operation?;
+        entry.step = step?;
+
+        Some(entry)
+    }
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operation = match self.operation {
+            JournalOp::Commit => "commit",
+            JournalOp::Pull => "pull",
+            JournalOp::Push => "push",
+            JournalOp::Sync => "sync",
+        };
+        let step = match self.step {
+            Step::Started => "started",
+            Step::Fetched => "fetched",
+            Step::Updating => "updating",
+            Step::Updated => "updated",
+        };
+        writeln!(f, "operation={operation}")?;
+        writeln!(f, "step={step}")?;
+
+        if let Some(branch) = &self.branch {
+            writeln!(f, "branch={branch}")?;
+        }
+        for (key, oid) in [
+            ("head", self.head),
+            ("target", self.target),
+            ("snapshot", self.snapshot),
+            ("stash", self.stash),
+        ] {
+            if let Some(oid) = oid {
+                writeln!(f, "{key}={oid}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
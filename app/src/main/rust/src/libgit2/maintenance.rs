@@ -0,0 +1,466 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use git2::{ObjectType, Oid, PackBuilder, Repository};
+
+use crate::Error;
+
+/// Refs outside of `refs/` that can point to objects worth keeping
+const PSEUDO_REFS: [&str; 4] = ["HEAD", "FETCH_HEAD", "ORIG_HEAD", "MERGE_HEAD"];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MaintenanceReport {
+    /// Loose objects moved into the new pack
+    pub objects_packed: usize,
+    /// Old packs replaced by the new pack
+    pub packs_removed: usize,
+    /// Unreachable objects deleted because they were older than the grace period
+    pub objects_pruned: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl MaintenanceReport {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+struct LooseObject {
+    oid: Oid,
+    path: PathBuf,
+    modified: SystemTime,
+}
+
+struct Pack {
+    /// Path without extension, e.g. `objects/pack/pack-<hash>`
+    base: PathBuf,
+    modified: SystemTime,
+    oids: Vec<Oid>,
+}
+
+/// Number of loose objects, to decide whether [`run`] is worth it
+pub fn loose_object_count(repo: &Repository) -> usize {
+    loose_objects(&repo.path().join("objects")).len()
+}
+
+/// Loose and packed object counts, like `git count-objects -v`
+pub fn object_counts(repo: &Repository) -> (usize, usize) {
+    let objects_dir = repo.path().join("objects");
+
+    let packed = fs::read_dir(objects_dir.join("pack"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+                .filter_map(|path| read_idx(&path))
+                .map(|oids| oids.len())
+                .sum()
+        })
+        .unwrap_or(0);
+
+    (loose_objects(&objects_dir).len(), packed)
+}
+
+/// Poor man's `git gc`: pack everything reachable into one pack, drop the
+/// packs it replaces, and prune unreachable objects older than `grace_period`.
+///
+/// Unreachable objects that are still within the grace period are kept:
+/// loose ones stay where they are, packed ones move to a "cruft" pack that
+/// keeps the age of the pack they came from.
+pub fn run(
+    repo: &Repository,
+    grace_period: Duration,
+    now: SystemTime,
+) -> Result<MaintenanceReport, Error> {
+    let objects_dir = repo.path().join("objects");
+    let pack_dir = objects_dir.join("pack");
+
+    let bytes_before = dir_size(&objects_dir);
+    let loose = loose_objects(&objects_dir);
+    let packs = packs(&pack_dir);
+
+    if loose.is_empty() && packs.is_empty() {
+        info!("Maintenance: nothing to do");
+        return Ok(MaintenanceReport {
+            bytes_before,
+            bytes_after: bytes_before,
+            ..Default::default()
+        });
+    }
+
+    let reachable = reachable_objects(repo)?;
+    let is_expired = |modified: SystemTime| {
+        now.duration_since(modified)
+            .is_ok_and(|age| age >= grace_period)
+    };
+
+    let mut report = MaintenanceReport {
+        bytes_before,
+        ..Default::default()
+    };
+
+    // Packs written by this run; may share the name of an old pack with the same content
+    let mut written = Vec::new();
+
+    if !reachable.is_empty() {
+        let mut builder = repo
+            .packbuilder()
+            .map_err(|e| Error::git2(e, "packbuilder"))?;
+        for oid in &reachable {
+            builder
+                .insert_object(*oid, None)
+                .map_err(|e| Error::git2(e, "insert_object"))?;
+        }
+        written.push(write_pack(&mut builder, &pack_dir, None)?);
+    }
+
+    // Unreachable packed objects still in their grace period, with the age of their youngest pack
+    let mut cruft = HashSet::new();
+    let mut cruft_modified = None;
+    for pack in &packs {
+        if is_expired(pack.modified) {
+            continue;
+        }
+        let young: Vec<Oid> = pack
+            .oids
+            .iter()
+            .filter(|oid| !reachable.contains(oid))
+            .copied()
+            .collect();
+        if !young.is_empty() {
+            cruft.extend(young);
+            cruft_modified = cruft_modified.max(Some(pack.modified));
+        }
+    }
+
+    if let Some(modified) = cruft_modified {
+        let mut builder = repo
+            .packbuilder()
+            .map_err(|e| Error::git2(e, "packbuilder"))?;
+        for oid in &cruft {
+            builder
+                .insert_object(*oid, None)
+                .map_err(|e| Error::git2(e, "insert_object"))?;
+        }
+        written.push(write_pack(&mut builder, &pack_dir, Some(modified))?);
+    }
+
+    // A multi-pack-index listing the removed packs breaks other git clients,
+    // without one they read the packs directly
+    if packs.iter().any(|pack| !written.contains(&pack.base)) {
+        remove_multi_pack_index(&pack_dir);
+    }
+
+    // Everything the old packs held is now in the new pack, the cruft pack, or expired
+    for pack in &packs {
+        if written.contains(&pack.base) {
+            continue;
+        }
+        report.objects_pruned += pack
+            .oids
+            .iter()
+            .filter(|oid| !reachable.contains(oid) && !cruft.contains(oid))
+            .count();
+        remove_pack(&pack.base);
+        report.packs_removed += 1;
+    }
+
+    for object in &loose {
+        let remove = if reachable.contains(&object.oid) {
+            report.objects_packed += 1;
+            true
+        } else if is_expired(object.modified) {
+            report.objects_pruned += 1;
+            true
+        } else {
+            false
+        };
+
+        if remove && let Err(e) = fs::remove_file(&object.path) {
+            warn!("Failed to remove {}: {e}", object.path.display());
+        }
+    }
+    remove_empty_fanout_dirs(&objects_dir);
+
+    // The open odb still knows the removed packs
+    repo.odb()
+        .and_then(|odb| odb.refresh())
+        .map_err(|e| Error::git2(e, "odb refresh"))?;
+
+    report.bytes_after = dir_size(&objects_dir);
+    info!("Maintenance: {report:?}");
+
+    Ok(report)
+}
+
+/// Write the pack and return its base path, optionally backdating it
+fn write_pack(
+    builder: &mut PackBuilder,
+    pack_dir: &Path,
+    modified: Option<SystemTime>,
+) -> Result<PathBuf, Error> {
+    builder
+        .write(pack_dir, 0)
+        .map_err(|e| Error::git2(e, "write pack"))?;
+
+    let name = builder
+        .name()
+        .ok_or_else(|| Error::repo_state("written pack has no name"))?;
+    let base = pack_dir.join(format!("pack-{name}"));
+
+    if let Some(modified) = modified {
+        for ext in ["pack", "idx"] {
+            // Packs are read-only, but the owner may still set their times
+            let res = fs::File::open(base.with_extension(ext))
+                .and_then(|file| file.set_modified(modified));
+            if let Err(e) = res {
+                warn!("Failed to backdate cruft pack: {e}");
+            }
+        }
+    }
+
+    Ok(base)
+}
+
+/// Everything reachable from refs, their reflogs and the index.
+///
+/// Fails on unreadable objects: packing around a hole would lose history,
+/// the repository needs a repair first.
+fn reachable_objects(repo: &Repository) -> Result<HashSet<Oid>, Error> {
+    let mut pending = Vec::new();
+
+    let references = repo
+        .references()
+        .map_err(|e| Error::git2(e, "references"))?;
+    let mut names: Vec<String> = references
+        .flatten()
+        .filter_map(|r| r.name().map(str::to_string))
+        .collect();
+    names.extend(PSEUDO_REFS.iter().map(|name| name.to_string()));
+
+    let odb = repo.odb().map_err(|e| Error::git2(e, "odb"))?;
+    for name in &names {
+        if let Ok(oid) = repo.refname_to_id(name) {
+            pending.push(oid);
+        }
+        // Old reflog entries may point to objects that are gone already
+        if let Ok(reflog) = repo.reflog(name) {
+            pending.extend(
+                reflog
+                    .iter()
+                    .flat_map(|entry| [entry.id_old(), entry.id_new()])
+                    .filter(|oid| !oid.is_zero() && odb.exists(*oid)),
+            );
+        }
+    }
+
+    if let Ok(index) = repo.index() {
+        pending.extend(index.iter().map(|entry| entry.id));
+    }
+
+    let mut reachable = HashSet::new();
+    while let Some(oid) = pending.pop() {
+        if oid.is_zero() || !reachable.insert(oid) {
+            continue;
+        }
+
+        let object = repo.find_object(oid, None).map_err(|e| {
+            Error::repo_state(&format!(
+                "object {oid} is unreadable, repair the repository first: {}",
+                e.message()
+            ))
+        })?;
+
+        match object.kind() {
+            Some(ObjectType::Commit) => {
+                let commit = object.peel_to_commit()?;
+                pending.push(commit.tree_id());
+                pending.extend(commit.parent_ids());
+            }
+            Some(ObjectType::Tree) => {
+                let tree = object.peel_to_tree()?;
+                pending.extend(
+                    tree.iter()
+                        // Submodule commits live in another repository
+                        .filter(|entry| entry.kind() != Some(ObjectType::Commit))
+                        .map(|entry| entry.id()),
+                );
+            }
+            Some(ObjectType::Tag) => {
+                if let Some(tag) = object.as_tag() {
+                    pending.push(tag.target_id());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(reachable)
+}
+
+fn loose_objects(objects_dir: &Path) -> Vec<LooseObject> {
+    let mut objects = Vec::new();
+
+    let Ok(dirs) = fs::read_dir(objects_dir) else {
+        return objects;
+    };
+
+    for dir in dirs.flatten() {
+        let prefix = dir.file_name().to_string_lossy().to_string();
+        if prefix.len() != 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+
+        let Ok(files) = fs::read_dir(dir.path()) else {
+            continue;
+        };
+
+        for file in files.flatten() {
+            let name = file.file_name().to_string_lossy().to_string();
+            let Ok(oid) = Oid::from_str(&format!("{prefix}{name}")) else {
+                // Temporary files of interrupted writes
+                continue;
+            };
+            let modified = file
+                .metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+
+            objects.push(LooseObject {
+                oid,
+                path: file.path(),
+                modified,
+            });
+        }
+    }
+
+    objects
+}
+
+/// Packs with a readable index, except the ones protected by a `.keep` file
+fn packs(pack_dir: &Path) -> Vec<Pack> {
+    let Ok(entries) = fs::read_dir(pack_dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pack"))
+        .filter(|path| !path.with_extension("keep").exists())
+        .filter_map(|path| {
+            let oids = read_idx(&path.with_extension("idx"))?;
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some(Pack {
+                base: path.with_extension(""),
+                modified,
+                oids,
+            })
+        })
+        .collect()
+}
+
+/// Object ids listed in a version 2 pack index
+fn read_idx(path: &Path) -> Option<Vec<Oid>> {
+    let data = fs::read(path).ok()?;
+
+    // magic, version, 256 fan-out entries
+    let header = 8 + 256 * 4;
+    if data.len() < header || data[..8] != [0xff, b't', b'O', b'c', 0, 0, 0, 2] {
+        warn!("Unsupported pack index {}", path.display());
+        return None;
+    }
+
+    let count = u32::from_be_bytes(data[header - 4..header].try_into().ok()?) as usize;
+    let names = data.get(header..header + count * 20)?;
+
+    names
+        .chunks_exact(20)
+        .map(|bytes| Oid::from_bytes(bytes).ok())
+        .collect()
+}
+
+fn remove_pack(base: &Path) {
+    for ext in ["pack", "idx", "rev", "bitmap", "mtimes"] {
+        let path = base.with_extension(ext);
+        if path.exists()
+            && let Err(e) = fs::remove_file(&path)
+        {
+            warn!("Failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+/// Remove `multi-pack-index` with its bitmap and reverse index, and the chain of
+/// an incremental one
+fn remove_multi_pack_index(pack_dir: &Path) {
+    let Ok(entries) = fs::read_dir(pack_dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with("multi-pack-index")
+        {
+            continue;
+        }
+
+        let res = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match res {
+            Ok(()) => debug!("Removed {}", path.display()),
+            Err(e) => warn!("Failed to remove {}: {e}", path.display()),
+        }
+    }
+}
+
+fn remove_empty_fanout_dirs(objects_dir: &Path) {
+    let Ok(dirs) = fs::read_dir(objects_dir) else {
+        return;
+    };
+
+    for dir in dirs.flatten() {
+        if dir.file_name().len() == 2 {
+            // Fails for directories that still hold objects
+            let _ = fs::remove_dir(dir.path());
+        }
+    }
+}
+
+/// Space used on disk; every loose object takes at least one block
+pub fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|m| disk_usage(&m)).unwrap_or(0),
+        })
+        .sum()
+}
+
+#[cfg(unix)]
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
@@ -1,9 +1,12 @@
 use git2::Repository;
 
+use super::progress::Reporter;
+
 fn fast_forward(
     repo: &Repository,
     lb: &mut git2::Reference,
     rc: &git2::AnnotatedCommit,
+    reporter: &Reporter,
 ) -> Result<(), git2::Error> {
     let name = match lb.name() {
         Some(s) => s.to_string(),
@@ -12,12 +15,13 @@ fn fast_forward(
     let msg = format!("Fast-Forward: Setting {} to id: {}", name, rc.id());
     lb.set_target(rc.id(), &msg)?;
     repo.set_head(&name)?;
+    let mut checkout = git2::build::CheckoutBuilder::default();
+    reporter.checkout(&mut checkout);
     repo.checkout_head(Some(
-        git2::build::CheckoutBuilder::default()
-            // For some reason the force is required to make the working directory actually get updated
-            // I suspect we should be adding some logic to handle dirty working directory states
-            // but this is just an example so maybe not.
-            .force(),
+        // For some reason the force is required to make the working directory actually get updated
+        // I suspect we should be adding some logic to handle dirty working directory states
+        // but this is just an example so maybe not.
+        checkout.force(),
     ))?;
     Ok(())
 }
@@ -28,6 +32,7 @@ fn normal_merge(
     remote: &git2::AnnotatedCommit,
     name: &str,
     email: &str,
+    reporter: &Reporter,
 ) -> Result<(), git2::Error> {
     // Set up the merge state
     let mut merge_options = git2::MergeOptions::new();
@@ -238,8 +243,8 @@ fn normal_merge(
     let local_commit = repo.find_commit(local.id())?;
     let remote_commit = repo.find_commit(remote.id())?;
     // Do our merge commit and set current branch head to that commit.
-    let _merge_commit = repo.commit(
-        Some("HEAD"),
+    let _merge_commit = super::signing::commit(
+        repo,
         &sig,
         &sig,
         &msg,
@@ -248,6 +253,7 @@ fn normal_merge(
     )?;
     // Set working tree to match head.
     let mut checkout_opts = git2::build::CheckoutBuilder::new();
+    reporter.checkout(&mut checkout_opts);
     checkout_opts.force();
     repo.checkout_head(Some(&mut checkout_opts))?;
     Ok(())
@@ -259,6 +265,7 @@ pub fn do_merge<'a>(
     fetch_commit: git2::AnnotatedCommit<'a>,
     name: &str,
     email: &str,
+    reporter: &Reporter,
 ) -> Result<(), git2::Error> {
     // 1. do a merge analysis
     let analysis = repo.merge_analysis(&[&fetch_commit])?;
@@ -269,7 +276,7 @@ pub fn do_merge<'a>(
         let refname = format!("refs/heads/{remote_branch}");
         match repo.find_reference(&refname) {
             Ok(mut r) => {
-                fast_forward(repo, &mut r, &fetch_commit)?;
+                fast_forward(repo, &mut r, &fetch_commit, reporter)?;
             }
             Err(_) => {
                 // The branch doesn't exist so just set the reference to the
@@ -282,8 +289,10 @@ pub fn do_merge<'a>(
                     &format!("Setting {} to {}", remote_branch, fetch_commit.id()),
                 )?;
                 repo.set_head(&refname)?;
+                let mut checkout = git2::build::CheckoutBuilder::default();
+                reporter.checkout(&mut checkout);
                 repo.checkout_head(Some(
-                    git2::build::CheckoutBuilder::default()
+                    checkout
                         .allow_conflicts(true)
                         .conflict_style_merge(true)
                         .force(),
@@ -293,7 +302,7 @@ pub fn do_merge<'a>(
     } else if analysis.0.is_normal() {
         // do a normal merge
         let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-        normal_merge(repo, &head_commit, &fetch_commit, name, email)?;
+        normal_merge(repo, &head_commit, &fetch_commit, name, email, reporter)?;
     } else {
         // Nothing to do...
     }
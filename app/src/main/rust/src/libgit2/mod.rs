@@ -6,16 +6,56 @@ use std::{
     sync::{LazyLock, Mutex, OnceLock},
 };
 
-use chrono::{DateTime, Local};
-
 use git2::{
-    CertificateCheckStatus, FetchOptions, IndexAddOption, Progress, PushOptions, RemoteCallbacks,
-    Repository, Signature, StatusOptions, TreeWalkMode, TreeWalkResult,
+    CertificateCheckStatus, FetchOptions, IndexAddOption, PushOptions, RemoteCallbacks, Repository,
+    Signature, StatusOptions, TreeWalkMode, TreeWalkResult, build::CheckoutBuilder,
 };
 
-use crate::{Cred, Error, ProgressCB, mime_types::is_extension_supported};
+use crate::{
+    Cred, Error,
+    search::{self, SearchResult},
+};
+use journal::{Journal, Step};
+use progress::Reporter;
 
+pub use autocommit::AutoCommitSettings;
+pub use history::{
+    ChangedFile, CommitDetails, FileStatus, GitLogEntry, GraphEdge, GraphRow, HistoryMatch,
+    HistoryQuery, LogQuery, Person,
+};
+pub use integrity::{Issue, IssueKind};
+pub use journal::{JournalOp, Replay, ReplayOutcome};
+pub use maintenance::MaintenanceReport;
+pub use network::{NetworkSettings, set_network_settings};
+pub use progress::{NoProgress, Phase, Progress};
+pub use recovery::{Operation, Recovery, RepoState};
+pub use reflog::ReflogEntry;
+pub use signing::{SignatureStatus, is_signing_enabled, set_allowed_signers_file, set_signing_key};
+pub use snapshot::{Retention, Snapshot, set_snapshot_retention};
+pub use squash::set_squash_window;
+pub use stash::StashEntry;
+pub use stats::{BlobSize, Contributor, RepoStats};
+pub use status::{FileState, PathStatus};
+pub use timestamps::FileHistory;
+
+mod autocommit;
+mod history;
+mod integrity;
+mod journal;
+mod maintenance;
 mod merge;
+mod network;
+mod progress;
+pub mod provider;
+mod recovery;
+mod reflog;
+mod signing;
+mod snapshot;
+mod squash;
+mod stash;
+mod stats;
+mod status;
+mod timestamps;
 
 #[cfg(test)]
 mod test;
@@ -24,6 +64,9 @@ const REMOTE: &str = "origin";
 
 static REPO: LazyLock<Mutex<Option<Repository>>> = LazyLock::new(|| Mutex::new(None));
 
+/// Search index of the open repository, loaded on the first search
+static SEARCH_INDEX: LazyLock<Mutex<Option<search::Index>>> = LazyLock::new(|| Mutex::new(None));
+
 // https://github.com/libgit2/libgit2/pull/7056
 static HOME_PATH: OnceLock<String> = OnceLock::new();
 
@@ -108,8 +151,17 @@ fn cleanup_repo_state(repo: &Repository) -> Result<(), Error> {
         cleaned_something = true;
     }
 
+    // Both resets below throw away uncommitted changes
+    let can_reset = match snapshot::save(repo, "cleanup", false) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Failed to save a snapshot, not resetting: {e}");
+            false
+        }
+    };
+
     // Check if index has conflicts
-    if let Ok(index) = repo.index() {
+    if can_reset && let Ok(index) = repo.index() {
         if index.has_conflicts() {
             warn!("Index has conflicts, resetting to HEAD...");
             if let (Ok(_head), Ok(head_commit)) = (
@@ -126,7 +178,7 @@ fn cleanup_repo_state(repo: &Repository) -> Result<(), Error> {
     // Check for uncommitted changes and reset if needed
     let mut status_opts = StatusOptions::new();
     status_opts.include_untracked(false);
-    if let Ok(statuses) = repo.statuses(Some(&mut status_opts)) {
+    if can_reset && let Ok(statuses) = repo.statuses(Some(&mut status_opts)) {
         if statuses.iter().any(|s| s.status() != git2::Status::CURRENT) {
             warn!("Repository has uncommitted changes, resetting to HEAD...");
             if let (Ok(_head), Ok(head_commit)) = (
@@ -178,16 +230,7 @@ pub fn init_lib(home_path: String) {
         }
     }
 
-    unsafe {
-        libgit2_sys::git_libgit2_opts(
-            libgit2_sys::GIT_OPT_SET_SERVER_CONNECT_TIMEOUT as std::ffi::c_int,
-            7000,
-        );
-        libgit2_sys::git_libgit2_opts(
-            libgit2_sys::GIT_OPT_SET_SERVER_TIMEOUT as std::ffi::c_int,
-            7000,
-        );
-    };
+    network::apply_timeouts(&network::network_settings());
 }
 
 pub fn create_repo(repo_path: &str) -> Result<(), Error> {
@@ -197,6 +240,7 @@ pub fn create_repo(repo_path: &str) -> Result<(), Error> {
     create_initial_welcome_commit(&repo)?;
 
     REPO.lock().unwrap().replace(repo);
+    SEARCH_INDEX.lock().expect("search index lock").take();
 
     Ok(())
 }
@@ -220,7 +264,7 @@ You can delete this file or edit it as you like!
 
     std::fs::write(&welcome_path, welcome_content).map_err(|e| {
         error!("Failed to write welcome.md file: {}", e);
-        git2::Error::from_str(&format!("IO error: {}", e))
+        Error::io(e, "write welcome.md")
     })?;
 
     // Add welcome.md to index
@@ -270,13 +314,25 @@ You can delete this file or edit it as you like!
 pub fn open_repo(repo_path: &str) -> Result<(), Error> {
     let repo = Repository::open(repo_path).map_err(|e| Error::git2(e, "Repository::open"))?;
 
-    // Automatically clean up any broken repository state when opening
-    if let Err(e) = cleanup_repo_state(&repo) {
-        warn!("Failed to cleanup repository state on open: {}", e);
-        // Continue anyway - the repo is still opened
+    // Left behind when the app died during an operation, the caller decides
+    // with `pending_journal` and `replay_journal`
+    match journal::pending(&repo) {
+        Ok(Some(pending)) => warn!("Repository has an interrupted {pending:?}"),
+        Ok(None) => {}
+        Err(e) => warn!("Failed to read the journal: {e}"),
+    }
+
+    // Only reported, the changes may come from another app on shared storage.
+    // The caller decides with `repo_state` and `recover`.
+    if repo.state() != git2::RepositoryState::Clean {
+        warn!(
+            "Repository has an interrupted operation: {:?}",
+            repo.state()
+        );
     }
 
     REPO.lock().unwrap().replace(repo);
+    SEARCH_INDEX.lock().expect("search index lock").take();
 
     Ok(())
 }
@@ -291,10 +347,7 @@ fn current_branch(repo: &Repository) -> Result<String, Error> {
     }
 
     // Detached HEAD or not a branch
-    Err(Error::git2(
-        git2::Error::from_str("unable to determine default branch"),
-        "",
-    ))
+    Err(Error::repo_state("unable to determine default branch"))
 }
 
 fn credential_helper(cred: &Cred) -> Result<git2::Cred, git2::Error> {
@@ -320,9 +373,10 @@ pub fn clone_repo(
     repo_path: &str,
     remote_url: &str,
     cred: Option<Cred>,
-    mut cb: ProgressCB,
+    progress: &mut dyn Progress,
 ) -> Result<(), Error> {
     apply_ssh_workaround(true);
+    let reporter = Reporter::new(progress);
     let mut callbacks = RemoteCallbacks::new();
 
     callbacks.certificate_check(|_cert, _| Ok(CertificateCheckStatus::CertificateOk));
@@ -332,79 +386,32 @@ pub fn clone_repo(
             .credentials(move |_url, _username_from_url, _allowed_types| credential_helper(&cred));
     }
 
-    callbacks.transfer_progress(|stats: Progress| {
-        let progress = stats.indexed_objects() as f32 / stats.total_objects() as f32 * 100.;
-
-        cb.progress(progress as i32)
-    });
+    reporter.remote_callbacks(&mut callbacks);
 
     let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    fetch_options
+        .remote_callbacks(callbacks)
+        .proxy_options(network::proxy_options());
+
+    let mut checkout = CheckoutBuilder::new();
+    reporter.checkout(&mut checkout);
 
     let mut builder = git2::build::RepoBuilder::new();
+    builder.fetch_options(fetch_options).with_checkout(checkout);
 
-    let repo = match builder
-        .fetch_options(fetch_options)
-        .clone(remote_url, std::path::Path::new(&repo_path))
-    {
+    let repo = match network::retry("clone", Some(&reporter), || {
+        builder.clone(remote_url, std::path::Path::new(&repo_path))
+    }) {
         Ok(repo) => repo,
-        Err(e) => {
-            // Provide specific guidance for authentication errors on common Git hosting services
-            if remote_url.starts_with("http://") || remote_url.starts_with("https://") {
-                if remote_url.contains("github.com") {
-                    error!(
-                        "GitHub clone failed - ensure you're using a Personal Access Token, not your password"
-                    );
-                    return Err(Error::git2(
-                        e,
-                        "GitHub clone failed - check credentials (use Personal Access Token)",
-                    ));
-                } else if remote_url.contains("bitbucket.org") {
-                    error!(
-                        "Bitbucket clone failed - ensure you're using an App Password, not your password"
-                    );
-                    return Err(Error::git2(
-                        e,
-                        "Bitbucket clone failed - check credentials (use App Password)",
-                    ));
-                } else if remote_url.contains("gitlab.com") || remote_url.contains("gitlab.") {
-                    error!(
-                        "GitLab clone failed - ensure you're using a Personal Access Token, not your password"
-                    );
-                    return Err(Error::git2(
-                        e,
-                        "GitLab clone failed - check credentials (use Personal Access Token)",
-                    ));
-                } else if remote_url.contains("azure.com")
-                    || remote_url.contains("visualstudio.com")
-                {
-                    error!(
-                        "Azure DevOps clone failed - ensure you're using a Personal Access Token"
-                    );
-                    return Err(Error::git2(
-                        e,
-                        "Azure DevOps clone failed - check credentials (use Personal Access Token)",
-                    ));
-                } else if remote_url.contains("codecommit.") {
-                    error!("AWS CodeCommit clone failed - check IAM permissions and credentials");
-                    return Err(Error::git2(
-                        e,
-                        "AWS CodeCommit clone failed - check IAM permissions",
-                    ));
-                } else {
-                    error!("HTTP clone failed - check credentials for {}", remote_url);
-                    return Err(Error::git2(e, "HTTP clone failed - check credentials"));
-                }
-            } else {
-                return Err(Error::git2(e, "clone"));
-            }
-        }
+        Err(e) if reporter.is_cancelled() => return Err(Error::git2(e, "clone")),
+        Err(e) => return Err(provider::remote_error(e, remote_url, "clone")),
     };
 
     // Handle empty repositories or missing main/master branch
     setup_repository_after_clone(&repo)?;
 
     REPO.lock().unwrap().replace(repo);
+    SEARCH_INDEX.lock().expect("search index lock").take();
 
     Ok(())
 }
@@ -437,7 +444,7 @@ fn create_initial_commit_and_branch(repo: &Repository) -> Result<(), Error> {
     let gitkeep_path = repo.path().parent().unwrap().join(".gitkeep");
     fs::write(&gitkeep_path, "").map_err(|e| {
         error!("Failed to write .gitkeep file: {}", e);
-        git2::Error::from_str(&format!("IO error: {}", e))
+        Error::io(e, "write .gitkeep")
     })?;
 
     // Add .gitkeep to index
@@ -528,12 +535,11 @@ pub fn commit_all(name: &str, email: &str, message: &str) -> Result<(), Error> {
     // Repository should have at least initial commit
     if repo.head().is_err() {
         warn!("Repository has no commits - this should not happen for properly initialized repos");
-        return Err(Error::git2(
-            git2::Error::from_str("Repository has no commits"),
-            "commit",
-        ));
+        return Err(Error::repo_state("commit: Repository has no commits"));
     }
 
+    let _journal = Journal::begin(repo, JournalOp::Commit)?;
+
     let mut index = repo.index().map_err(|e| Error::git2(e, "index"))?;
 
     index
@@ -559,27 +565,83 @@ pub fn commit_all(name: &str, email: &str, message: &str) -> Result<(), Error> {
 
     // Create commit
     match parent_commit {
-        Some(ref parent) => repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[parent]),
-        None => repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[]),
+        Some(ref parent) => signing::commit(repo, &sig, &sig, message, &tree, &[parent]),
+        None => signing::commit(repo, &sig, &sig, message, &tree, &[]),
+    }
+    .map_err(|e| Error::git2(e, "commit"))?;
+
+    refresh_search_index(repo);
+
+    Ok(())
+}
+
+/// Commit changes once no further change arrived for the quiet period, see
+/// [`notify_file_changed`]. Calling it again applies the new settings.
+pub fn start_auto_commit(settings: AutoCommitSettings) {
+    autocommit::start(settings, auto_commit);
+}
+
+/// Stop committing automatically, the pending changes are committed first
+pub fn stop_auto_commit() -> Result<(), Error> {
+    match autocommit::stop() {
+        Some((paths, settings)) => auto_commit(&settings, &paths),
+        None => Ok(()),
     }
-    .map(|_| ())
-    .map_err(|e| Error::git2(e, "commit"))
 }
 
-pub fn checkout_path(path: &str) -> Result<(), Error> {
-    info!("checkout_path called with path: {}", path);
+/// A note was changed, `path` is relative to the working tree. Only notified
+/// files are auto-committed, notifications are ignored while auto-commit is stopped.
+pub fn notify_file_changed(path: &str) {
+    autocommit::notify(path);
+}
+
+/// Commit the pending changes without waiting for the quiet period, they stay
+/// pending if that fails
+pub fn flush_auto_commit() -> Result<(), Error> {
+    let Some((paths, settings)) = autocommit::take_pending() else {
+        return Ok(());
+    };
+
+    auto_commit(&settings, &paths).inspect_err(|_| autocommit::requeue(&paths))
+}
+
+fn auto_commit(settings: &AutoCommitSettings, paths: &[String]) -> Result<(), Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let Some(repo) = repo.as_ref() else {
+        return Err(Error::repo_state("auto-commit: no repository open"));
+    };
+
+    let _journal = Journal::begin(repo, JournalOp::Commit)?;
+    if autocommit::commit(repo, settings, paths)?.is_some() {
+        refresh_search_index(repo);
+    }
+
+    Ok(())
+}
+
+/// Restore `path` in the working tree from HEAD, or from `commit`, e.g. to
+/// bring back an older version found by [`search_history`]
+pub fn checkout_path(path: &str, commit: Option<&str>) -> Result<(), Error> {
+    info!("checkout_path called with path: {} at {:?}", path, commit);
     let repo = REPO.lock().expect("repo lock");
     let repo = repo.as_ref().expect("repo");
 
-    // Get HEAD tree
-    let head = repo.head().map_err(|e| Error::git2(e, "head"))?;
-    let commit = head
-        .peel_to_commit()
-        .map_err(|e| Error::git2(e, "peel_to_commit"))?;
+    let commit = match commit {
+        Some(commit) => repo
+            .revparse_single(commit)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| Error::git2(e, "revparse"))?,
+        None => repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|e| Error::git2(e, "head"))?,
+    };
     let tree = commit.tree().map_err(|e| Error::git2(e, "tree"))?;
 
+    snapshot::save(repo, "checkout", false)?;
+
     info!("About to checkout tree for path: {}", path);
-    // Checkout the path from HEAD
+    // Checkout the path from the commit
     repo.checkout_tree(
         tree.as_object(),
         Some(
@@ -595,30 +657,35 @@ pub fn checkout_path(path: &str) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn push(cred: Option<Cred>) -> Result<(), Error> {
+pub fn push(cred: Option<Cred>, progress: &mut dyn Progress) -> Result<(), Error> {
     apply_ssh_workaround(false);
 
+    // Changes waiting for the quiet period go out with this one
+    if let Err(e) = flush_auto_commit() {
+        warn!("Failed to commit pending changes: {e}");
+    }
+
     let repo = REPO.lock().expect("repo lock");
     let repo = repo.as_ref().expect("repo");
 
     // Empty repositories cannot be pushed
     if repo.head().is_err() {
         warn!("Attempted to push empty repository");
-        return Err(Error::git2(
-            git2::Error::from_str("Cannot push empty repository - create a commit first"),
-            "push",
+        return Err(Error::repo_state(
+            "Cannot push empty repository - create a commit first",
         ));
     }
 
     // Empty repositories cannot be pushed
     if repo.head().is_err() {
         warn!("Attempted to push empty repository");
-        return Err(Error::git2(
-            git2::Error::from_str("Cannot push empty repository - create a commit first"),
-            "push",
+        return Err(Error::repo_state(
+            "Cannot push empty repository - create a commit first",
         ));
     }
 
+    let _journal = Journal::begin(repo, JournalOp::Push)?;
+
     let mut remote = repo
         .find_remote(REMOTE)
         .map_err(|e| Error::git2(e, "find_remote"))?;
@@ -631,10 +698,7 @@ pub fn push(cred: Option<Cred>) -> Result<(), Error> {
         if url.starts_with("http://") || url.starts_with("https://") {
             if cred.is_none() {
                 error!("HTTP push attempted without credentials for URL: {}", url);
-                return Err(Error::git2(
-                    git2::Error::from_str("HTTP push requires credentials"),
-                    "push",
-                ));
+                return Err(Error::auth("HTTP push requires credentials"));
             } else {
                 debug!("HTTP push with credentials for URL: {}", url);
             }
@@ -646,6 +710,34 @@ pub fn push(cred: Option<Cred>) -> Result<(), Error> {
     }
 
     let branch = current_branch(repo)?;
+    let reporter = Reporter::new(progress);
+
+    // Squash against the branch as it is on the remote now
+    let fetch = || {
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.certificate_check(|_cert, _| Ok(CertificateCheckStatus::CertificateOk));
+
+        if let Some(c) = &cred {
+            callbacks
+                .credentials(move |_url, _username_from_url, _allowed_types| credential_helper(c));
+        }
+
+        reporter.remote_callbacks(&mut callbacks);
+
+        let mut fetch_options = FetchOptions::new();
+        fetch_options
+            .remote_callbacks(callbacks)
+            .proxy_options(network::proxy_options());
+
+        network::retry("fetch", Some(&reporter), || {
+            let refspec = format!("+refs/heads/{branch}:refs/remotes/{REMOTE}/{branch}");
+            remote.fetch(&[refspec], Some(&mut fetch_options), None)
+        })
+        .map_err(|e| Error::git2(e, "fetch"))
+    };
+    if let Err(e) = squash::before_push(repo, fetch) {
+        warn!("Failed to squash commits before push: {e}");
+    }
 
     // Try normal push first
     let refspecs = [format!("refs/heads/{branch}:refs/heads/{branch}")];
@@ -656,10 +748,16 @@ pub fn push(cred: Option<Cred>) -> Result<(), Error> {
         callbacks.credentials(move |_url, _username_from_url, _allowed_types| credential_helper(c));
     }
 
+    reporter.remote_callbacks(&mut callbacks);
+
     let mut push_opts = PushOptions::new();
-    push_opts.remote_callbacks(callbacks);
+    push_opts
+        .remote_callbacks(callbacks)
+        .proxy_options(network::proxy_options());
 
-    match remote.push(&refspecs, Some(&mut push_opts)) {
+    match network::retry("push", Some(&reporter), || {
+        remote.push(&refspecs, Some(&mut push_opts))
+    }) {
         Ok(()) => return Ok(()),
         Err(e) if e.raw_code() == -11 => {
             // GIT_ENONFASTFORWARD
@@ -668,60 +766,24 @@ pub fn push(cred: Option<Cred>) -> Result<(), Error> {
             warn!("Push failed with non-fast-forward - app should sync first");
             Err(Error::git2(e, "push needs sync"))
         }
-        Err(e) => {
-            if let Some(url) = remote.url() {
-                if url.contains("github.com") {
-                    error!(
-                        "GitHub push failed - ensure you're using a Personal Access Token, not your password"
-                    );
-                    Err(Error::git2(
-                        e,
-                        "GitHub push failed - check credentials (use Personal Access Token)",
-                    ))
-                } else if url.contains("bitbucket.org") {
-                    error!(
-                        "Bitbucket push failed - ensure you're using an App Password, not your password"
-                    );
-                    Err(Error::git2(
-                        e,
-                        "Bitbucket push failed - check credentials (use App Password)",
-                    ))
-                } else if url.contains("gitlab.com") || url.contains("gitlab.") {
-                    error!(
-                        "GitLab push failed - ensure you're using a Personal Access Token, not your password"
-                    );
-                    Err(Error::git2(
-                        e,
-                        "GitLab push failed - check credentials (use Personal Access Token)",
-                    ))
-                } else if url.contains("azure.com") || url.contains("visualstudio.com") {
-                    error!(
-                        "Azure DevOps push failed - ensure you're using a Personal Access Token"
-                    );
-                    Err(Error::git2(
-                        e,
-                        "Azure DevOps push failed - check credentials (use Personal Access Token)",
-                    ))
-                } else if url.contains("codecommit.") {
-                    error!("AWS CodeCommit push failed - check IAM permissions and credentials");
-                    Err(Error::git2(
-                        e,
-                        "AWS CodeCommit push failed - check IAM permissions",
-                    ))
-                } else {
-                    Err(Error::git2(e, "push"))
-                }
-            } else {
-                Err(Error::git2(e, "push"))
-            }
-        }
+        Err(e) if reporter.is_cancelled() => Err(Error::git2(e, "push")),
+        Err(e) => match remote.url() {
+            Some(url) => Err(provider::remote_error(e, url, "push")),
+            None => Err(Error::git2(e, "push")),
+        },
     }
 }
 
-pub fn sync(cred: Option<Cred>) -> Result<(), Error> {
+pub fn sync(cred: Option<Cred>, progress: &mut dyn Progress) -> Result<(), Error> {
     apply_ssh_workaround(false);
+
+    // Changes waiting for the quiet period go out with this one
+    if let Err(e) = flush_auto_commit() {
+        warn!("Failed to commit pending changes: {e}");
+    }
     let mut repo_guard = REPO.lock().expect("repo lock");
     let repo = repo_guard.as_mut().expect("repo");
+    let reporter = Reporter::new(progress);
 
     // Empty repositories need initial setup from remote
     if repo.head().is_err() {
@@ -739,18 +801,25 @@ pub fn sync(cred: Option<Cred>) -> Result<(), Error> {
                 .credentials(move |_url, _username_from_url, _allowed_types| credential_helper(c));
         }
 
+        reporter.remote_callbacks(&mut callbacks);
+
         let mut fetch_options = FetchOptions::new();
-        fetch_options.remote_callbacks(callbacks);
+        fetch_options
+            .remote_callbacks(callbacks)
+            .proxy_options(network::proxy_options());
 
-        remote
-            .fetch(&[] as &[&str], Some(&mut fetch_options), None)
-            .map_err(|e| Error::git2(e, "fetch"))?;
+        network::retry("fetch", Some(&reporter), || {
+            remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)
+        })
+        .map_err(|e| Error::git2(e, "fetch"))?;
 
         return Ok(());
     }
 
     let branch = current_branch(repo)?;
 
+    let mut journal = Journal::begin(repo, JournalOp::Sync)?;
+
     // Stash any local changes
     let has_changes = repo
         .statuses(None)
@@ -759,7 +828,8 @@ pub fn sync(cred: Option<Cred>) -> Result<(), Error> {
 
     let stashed = if has_changes {
         match repo.stash_save(&repo.signature()?, "Auto-stash before sync", None) {
-            Ok(_) => {
+            Ok(stash) => {
+                journal.stash(stash);
                 info!("Stashed local changes");
                 true
             }
@@ -784,15 +854,34 @@ pub fn sync(cred: Option<Cred>) -> Result<(), Error> {
         callbacks.credentials(move |_url, _username_from_url, _allowed_types| credential_helper(c));
     }
 
+    reporter.remote_callbacks(&mut callbacks);
+
     let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    fetch_options
+        .remote_callbacks(callbacks)
+        .proxy_options(network::proxy_options());
 
-    remote
-        .fetch(&[] as &[&str], Some(&mut fetch_options), None)
-        .map_err(|e| Error::git2(e, "fetch"))?;
+    let fetched = network::retry("fetch", Some(&reporter), || {
+        remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)
+    });
 
     // Drop remote to release the borrow
     drop(remote);
+    drop(fetch_options);
+
+    // Nothing was touched yet: a failed or cancelled fetch must give the stash back
+    let cancelled = fetched.is_ok() && !reporter.report(Phase::Merging, 0, 0);
+    if fetched.is_err() || cancelled {
+        if stashed && let Err(e) = repo.stash_pop(0, None) {
+            warn!("Failed to restore stashed changes: {}", e);
+        }
+        return match fetched {
+            Err(e) => Err(Error::git2(e, "fetch")),
+            Ok(()) => Err(Error::git2(progress::cancelled_error(), "sync")),
+        };
+    }
+
+    journal.step(Step::Fetched);
 
     // Reset local branch to remote branch (this is like a hard pull)
     {
@@ -805,9 +894,38 @@ pub fn sync(cred: Option<Cred>) -> Result<(), Error> {
             .find_commit(remote_ref.target().unwrap())
             .map_err(|e| Error::git2(e, "find remote commit"))?;
 
+        // Local commits the remote does not have are dropped by the reset
+        let head = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|e| Error::git2(e, "head"))?
+            .id();
+        let keep_head = head != remote_commit.id()
+            && !repo
+                .graph_descendant_of(remote_commit.id(), head)
+                .map_err(|e| Error::git2(e, "graph_descendant_of"))?;
+
+        if let Err(e) = snapshot::save(repo, "sync", keep_head) {
+            // Release the borrows of the repository
+            drop(remote_commit);
+            drop(remote_ref);
+            if stashed && let Err(e) = repo.stash_pop(0, None) {
+                warn!("Failed to restore stashed changes: {}", e);
+            }
+            return Err(e);
+        }
+
+        journal.updating(remote_commit.id(), None);
+
         // Reset local branch to remote
-        repo.reset(remote_commit.as_object(), git2::ResetType::Hard, None)
-            .map_err(|e| Error::git2(e, "reset to remote"))?;
+        let mut checkout = CheckoutBuilder::new();
+        reporter.checkout(&mut checkout);
+        repo.reset(
+            remote_commit.as_object(),
+            git2::ResetType::Hard,
+            Some(&mut checkout),
+        )
+        .map_err(|e| Error::git2(e, "reset to remote"))?;
 
         // Set head to the branch
         let local_ref_name = format!("refs/heads/{}", branch);
@@ -815,21 +933,38 @@ pub fn sync(cred: Option<Cred>) -> Result<(), Error> {
             .map_err(|e| Error::git2(e, "set head"))?;
     }
 
+    journal.step(Step::Updated);
+
     // Apply stashed changes if any (now all references are dropped)
-    if stashed {
-        if let Err(e) = repo.stash_apply(0, None) {
-            warn!("Failed to apply stashed changes: {}", e);
-            // Continue anyway - the sync succeeded
-        } else {
-            info!("Successfully applied stashed changes");
-        }
+    let applied = if stashed {
+        stash::apply(repo, 0, true)
+    } else {
+        Ok(Vec::new())
+    };
+
+    refresh_search_index(repo);
+
+    // The branch is synced either way, the stash is kept in the stash list, see
+    // `list_stashes`
+    let conflicts = applied?;
+    if !conflicts.is_empty() {
+        warn!("Stashed changes conflict in {:?}", conflicts);
+        return Err(Error::conflict(&format!(
+            "Stashed changes conflict in {}",
+            conflicts.join(", ")
+        )));
     }
 
     info!("Sync completed successfully");
     Ok(())
 }
 
-pub fn pull(cred: Option<Cred>, name: &str, email: &str) -> Result<(), Error> {
+pub fn pull(
+    cred: Option<Cred>,
+    name: &str,
+    email: &str,
+    progress: &mut dyn Progress,
+) -> Result<(), Error> {
     apply_ssh_workaround(false);
 
     let repo = REPO.lock().expect("repo lock");
@@ -838,12 +973,13 @@ pub fn pull(cred: Option<Cred>, name: &str, email: &str) -> Result<(), Error> {
     // Empty repositories cannot be pulled - use sync instead
     if repo.head().is_err() {
         warn!("Attempted to pull empty repository - use sync instead");
-        return Err(Error::git2(
-            git2::Error::from_str("Cannot pull empty repository - use sync instead"),
-            "pull",
+        return Err(Error::repo_state(
+            "Cannot pull empty repository - use sync instead",
         ));
     }
 
+    let mut journal = Journal::begin(repo, JournalOp::Pull)?;
+
     let mut remote = repo
         .find_remote(REMOTE)
         .map_err(|e| Error::git2(e, "find_remote"))?;
@@ -857,8 +993,13 @@ pub fn pull(cred: Option<Cred>, name: &str, email: &str) -> Result<(), Error> {
             .credentials(move |_url, _username_from_url, _allowed_types| credential_helper(&cred));
     }
 
+    let reporter = Reporter::new(progress);
+    reporter.remote_callbacks(&mut callbacks);
+
     let mut fetch_options = FetchOptions::new();
-    fetch_options.remote_callbacks(callbacks);
+    fetch_options
+        .remote_callbacks(callbacks)
+        .proxy_options(network::proxy_options());
 
     let branch = current_branch(repo)?;
 
@@ -868,9 +1009,10 @@ pub fn pull(cred: Option<Cred>, name: &str, email: &str) -> Result<(), Error> {
         let _ = std::fs::remove_file(&fetch_head_path);
     }
 
-    remote
-        .fetch(&[] as &[&str], Some(&mut fetch_options), None)
-        .map_err(|e| Error::git2(e, "fetch"))?;
+    network::retry("fetch", Some(&reporter), || {
+        remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)
+    })
+    .map_err(|e| Error::git2(e, "fetch"))?;
 
     let fetch_head = match repo.find_reference("FETCH_HEAD") {
         Ok(r) => r,
@@ -882,28 +1024,292 @@ pub fn pull(cred: Option<Cred>, name: &str, email: &str) -> Result<(), Error> {
             }
             warn!("FETCH_HEAD was corrupted, attempting to refetch...");
 
-            // Refetch
-            remote
-                .fetch(&[] as &[&str], Some(&mut fetch_options), None)
-                .map_err(|e| Error::git2(e, "refetch after corruption"))?;
+            // Refetch, it can be cancelled and fail like the first fetch
+            network::retry("refetch", Some(&reporter), || {
+                remote.fetch(&[] as &[&str], Some(&mut fetch_options), None)
+            })
+            .map_err(|e| Error::git2(e, "refetch after corruption"))?;
 
             repo.find_reference("FETCH_HEAD")
                 .map_err(|e| Error::git2(e, "find_reference after refetch"))?
         }
     };
 
+    journal.step(Step::Fetched);
+
     let commit = repo
         .reference_to_annotated_commit(&fetch_head)
         .map_err(|e| Error::git2(e, "reference_to_annotated_commit"))?;
 
-    merge::do_merge(repo, &branch, commit, name, email).map_err(|e| Error::git2(e, "do_merge"))?;
+    // Last chance to cancel: nothing but FETCH_HEAD and remote-tracking refs changed so far
+    if !reporter.report(Phase::Merging, 0, 0) {
+        return Err(Error::git2(progress::cancelled_error(), "pull"));
+    }
+
+    // The merge checks out with force
+    let snapshot = snapshot::save(repo, "pull", false)?;
+
+    // Saving a large working tree takes a while, the snapshot is kept either way
+    if !reporter.report(Phase::Merging, 0, 0) {
+        return Err(Error::git2(progress::cancelled_error(), "pull"));
+    }
+    journal.updating(commit.id(), snapshot);
+
+    merge::do_merge(repo, &branch, commit, name, email, &reporter)
+        .map_err(|e| Error::git2(e, "do_merge"))?;
+
+    journal.step(Step::Updated);
+
+    refresh_search_index(repo);
 
     Ok(())
 }
 
+pub fn verify_repo() -> Result<Vec<Issue>, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    integrity::verify(repo)
+}
+
+/// Repair what [`verify_repo`] reports and return the issues that could not be fixed.
+///
+/// Unreadable objects are restored from a temporary bare clone of the remote,
+/// so this downloads the whole repository when objects are damaged.
+pub fn repair_repo(cred: Option<Cred>) -> Result<Vec<Issue>, Error> {
+    apply_ssh_workaround(false);
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    let issues = integrity::verify(repo)?;
+    let needs_objects = issues
+        .iter()
+        .any(|issue| issue.kind == IssueKind::UnreadableObject);
+
+    let copy_path = repo.path().join("gittasks").join("repair");
+    let copy = if needs_objects {
+        clone_for_repair(repo, &copy_path, cred)
+    } else {
+        None
+    };
+
+    let remaining = integrity::repair(repo, copy.as_ref());
+
+    drop(copy);
+    if copy_path.exists()
+        && let Err(e) = std::fs::remove_dir_all(&copy_path)
+    {
+        warn!("Failed to remove repair clone: {e}");
+    }
+
+    remaining
+}
+
+fn clone_for_repair(repo: &Repository, path: &Path, cred: Option<Cred>) -> Option<Repository> {
+    let remote = repo.find_remote(REMOTE).ok()?;
+    let url = remote.url()?;
+
+    if path.exists() {
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.certificate_check(|_cert, _| Ok(CertificateCheckStatus::CertificateOk));
+
+    if let Some(c) = &cred {
+        callbacks.credentials(move |_url, _username_from_url, _allowed_types| credential_helper(c));
+    }
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options
+        .remote_callbacks(callbacks)
+        .proxy_options(network::proxy_options());
+
+    let mut builder = git2::build::RepoBuilder::new();
+    builder.bare(true).fetch_options(fetch_options);
+
+    match network::retry("clone for repair", None, || builder.clone(url, path)) {
+        Ok(copy) => Some(copy),
+        Err(e) => {
+            warn!("Can't clone remote to restore objects: {e}");
+            None
+        }
+    }
+}
+
+/// Repack and prune the object database, see [`maintenance::run`].
+///
+/// Does nothing while there are fewer than `min_loose_objects` loose objects,
+/// so it is cheap to call after every sync.
+pub fn run_maintenance(
+    grace_period: std::time::Duration,
+    min_loose_objects: usize,
+) -> Result<MaintenanceReport, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    let loose = maintenance::loose_object_count(repo);
+    if loose < min_loose_objects {
+        debug!("Maintenance skipped: {loose} loose objects");
+        return Ok(MaintenanceReport::default());
+    }
+
+    maintenance::run(repo, grace_period, std::time::SystemTime::now())
+}
+
+/// Statistics for the storage screen; `asset_dir` is relative to the repository root.
+pub fn repo_stats(asset_dir: &str) -> Result<RepoStats, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    stats::repo_stats(repo, asset_dir)
+}
+
+pub fn list_stashes() -> Result<Vec<StashEntry>, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    stash::list(repo)
+}
+
+pub fn show_stash(index: usize) -> Result<String, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    stash::show(repo, index)
+}
+
+/// Apply the stash at `index`, see [`stash::apply`]. Returns the conflicting paths.
+pub fn apply_stash(index: usize, pop: bool) -> Result<Vec<String>, Error> {
+    let mut repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_mut().expect("repo");
+
+    let conflicts = stash::apply(repo, index, pop)?;
+
+    refresh_search_index(repo);
+
+    Ok(conflicts)
+}
+
+pub fn drop_stash(index: usize) -> Result<(), Error> {
+    let mut repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_mut().expect("repo");
+
+    stash::drop(repo, index)
+}
+
+pub fn list_snapshots() -> Result<Vec<Snapshot>, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    snapshot::list(repo)
+}
+
+pub fn show_snapshot(id: i64) -> Result<String, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    snapshot::show(repo, id)
+}
+
+pub fn restore_snapshot(id: i64) -> Result<(), Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    snapshot::restore(repo, id)?;
+
+    refresh_search_index(repo);
+
+    Ok(())
+}
+
+pub fn delete_snapshot(id: i64) -> Result<(), Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    snapshot::delete(repo, id)
+}
+
 pub fn close() {
+    if let Err(e) = flush_auto_commit() {
+        warn!("Failed to commit pending changes: {e}");
+    }
+
     let mut repo = REPO.lock().expect("repo lock");
     repo.take();
+    SEARCH_INDEX.lock().expect("search index lock").take();
+}
+
+/// The interrupted operation, if any, and the changed files
+pub fn repo_state() -> Result<RepoState, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    recovery::state(repo)
+}
+
+/// The operation of the app that was interrupted, if any, and what
+/// [`replay_journal`] would do with it
+pub fn pending_journal() -> Result<Option<Replay>, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    journal::pending(repo)
+}
+
+/// Finish or roll back the operation of the app that was interrupted, see
+/// [`journal::replay`]
+pub fn replay_journal() -> Result<Option<Replay>, Error> {
+    let mut repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_mut().expect("repo");
+
+    let replay = journal::replay(repo)?;
+
+    refresh_search_index(repo);
+
+    Ok(replay)
+}
+
+/// Keep the repository as the interrupted operation left it
+pub fn discard_journal() -> Result<(), Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    journal::discard(repo)
+}
+
+/// Resolve the interrupted operation, see [`Recovery`]. `name` and `email`
+/// sign the commit that finishes it.
+pub fn recover(recovery: Recovery, name: &str, email: &str) -> Result<RepoState, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    let sig = Signature::now(name, email).map_err(|e| Error::git2(e, "Signature::now"))?;
+    let state = recovery::recover(repo, recovery, &sig)?;
+
+    refresh_search_index(repo);
+
+    Ok(state)
+}
+
+/// Reflog of `name`, HEAD if `None`, see [`reflog::list`]
+pub fn get_reflog(name: Option<&str>) -> Result<Vec<ReflogEntry>, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    reflog::list(repo, name)
+}
+
+/// Undo e.g. a bad merge or the reset of a sync, see [`reflog::reset_to`]
+pub fn reset_to_reflog(name: Option<&str>, index: usize) -> Result<(), Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    reflog::reset_to(repo, name, index)?;
+
+    refresh_search_index(repo);
+
+    Ok(())
 }
 
 pub fn cleanup_repo() -> Result<(), Error> {
@@ -934,201 +1340,158 @@ pub fn is_change() -> Result<bool, Error> {
     Ok(count > 0)
 }
 
-pub fn get_timestamps() -> Result<HashMap<String, i64>, Error> {
-    let start = std::time::Instant::now();
-    log::debug!("Starting get_timestamps");
-
+/// Every changed path below `folder`, see [`status::status`]
+pub fn status(folder: Option<&str>, include_ignored: bool) -> Result<Vec<PathStatus>, Error> {
     let repo = REPO.lock().expect("repo lock");
     let repo = repo.as_ref().expect("repo");
 
-    // Empty repositories have no commits to timestamp
-    if let Err(_) = repo.head() {
-        debug!("Repository has no commits yet, returning empty timestamps");
-        return Ok(HashMap::new());
-    }
+    status::status(repo, folder, include_ignored)
+}
 
-    // First, get the list of all supported files in the repo at HEAD
-    let head = repo.head()?.peel_to_commit()?;
-    let tree = head.tree()?;
-    let mut supported_files = std::collections::HashSet::new();
+/// Call `f` with the full path and the entry of every blob in the HEAD tree
+fn walk_head_blobs(
+    repo: &Repository,
+    mut f: impl FnMut(String, &git2::TreeEntry),
+) -> Result<(), Error> {
+    let tree = repo.head()?.peel_to_tree()?;
 
     tree.walk(TreeWalkMode::PreOrder, |root, entry| {
         if entry.kind() == Some(git2::ObjectType::Blob)
             && let Some(name) = entry.name()
-            && let Some(extension) = Path::new(name).extension()
-            && let Some(extension) = extension.to_str()
-            && is_extension_supported(extension)
         {
-            let path = format!("{root}{name}");
-            supported_files.insert(path);
+            f(format!("{root}{name}"), entry);
         }
         TreeWalkResult::Ok
     })?;
 
-    log::debug!("Found {} supported files to process", supported_files.len());
+    Ok(())
+}
 
-    if supported_files.is_empty() {
-        return Ok(HashMap::new());
-    }
+pub fn get_timestamps(count_renames: bool) -> Result<HashMap<String, i64>, Error> {
+    log::debug!("Starting get_timestamps");
 
-    let mut file_timestamps = HashMap::new();
-
-    // Walk through commits in reverse chronological order (newest first)
-    let mut revwalk = repo.revwalk()?;
-    revwalk.push_head()?;
-    revwalk.set_sorting(git2::Sort::TIME)?;
-
-    let mut commits_processed = 0;
-
-    for oid_result in revwalk {
-        commits_processed += 1;
-
-        let oid = oid_result?;
-        let commit = repo.find_commit(oid)?;
-        let commit_time = commit.time().seconds() * 1000;
-
-        // Check if this commit modifies any files
-        let parent = commit.parents().next();
-
-        if let Some(parent) = parent {
-            if let (Ok(parent_tree), Ok(current_tree)) = (parent.tree(), commit.tree()) {
-                // Get diff between this commit and its parent
-                if let Ok(diff) =
-                    repo.diff_tree_to_tree(Some(&parent_tree), Some(&current_tree), None)
-                {
-                    // Check each delta to see what files were modified
-                    for delta in diff.deltas() {
-                        if let Some(path) = delta.new_file().path() {
-                            if let Some(path_str) = path.to_str() {
-                                // If this file is in our supported files list and we haven't seen it before
-                                if supported_files.contains(path_str)
-                                    && !file_timestamps.contains_key(path_str)
-                                {
-                                    file_timestamps.insert(path_str.to_string(), commit_time);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        } else {
-            // Initial commit - all files are "modified"
-            if let Ok(tree) = commit.tree() {
-                tree.walk(TreeWalkMode::PreOrder, |root, entry| {
-                    if entry.kind() == Some(git2::ObjectType::Blob)
-                        && let Some(name) = entry.name()
-                    {
-                        let path = format!("{root}{name}");
-                        if supported_files.contains(&path) && !file_timestamps.contains_key(&path) {
-                            file_timestamps.insert(path, commit_time);
-                        }
-                    }
-                    TreeWalkResult::Ok
-                })?;
-            }
-        }
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
 
-        // If we've found timestamps for all supported files, we can stop
-        if file_timestamps.len() >= supported_files.len() {
-            log::debug!(
-                "Found timestamps for all {} files after {} commits",
-                supported_files.len(),
-                commits_processed
-            );
-            break;
-        }
-    }
+    timestamps::get_timestamps(repo, count_renames)
+}
 
-    // Check if we have timestamps for all files
-    let missing_files: Vec<String> = supported_files
-        .iter()
-        .filter(|file| !file_timestamps.contains_key(*file))
-        .cloned()
-        .collect();
-
-    if !missing_files.is_empty() {
-        // Use current time for missing files and log an error
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis() as i64;
-
-        for file in &missing_files {
-            file_timestamps.insert(file.clone(), current_time);
-        }
+pub fn get_file_history(count_renames: bool) -> Result<HashMap<String, FileHistory>, Error> {
+    log::debug!("Starting get_file_history");
 
-        error!(
-            "Some files were never committed, using current time for {} files: {:?}",
-            missing_files.len(),
-            missing_files
-        );
-    }
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
 
-    let duration = start.elapsed();
-    log::debug!(
-        "get_timestamps completed in {}ms, processed {} commits, found timestamps for {} files",
-        duration.as_millis(),
-        commits_processed,
-        file_timestamps.len()
-    );
+    timestamps::file_history(repo, count_renames)
+}
 
-    Ok(file_timestamps)
+pub fn get_git_log(limit: usize) -> Result<Vec<GitLogEntry>, Error> {
+    query_git_log(&LogQuery {
+        limit,
+        ..Default::default()
+    })
 }
 
-#[derive(Debug)]
-pub struct GitLogEntry {
-    pub hash: String,
-    pub message: String,
-    pub author: String,
-    pub date: String,
+pub fn get_git_graph(offset: usize, limit: usize) -> Result<Vec<GraphRow>, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    history::graph(repo, offset, limit)
 }
 
-pub fn get_git_log(limit: usize) -> Result<Vec<GitLogEntry>, Error> {
+pub fn search_history(query: &HistoryQuery) -> Result<Vec<HistoryMatch>, Error> {
     let start = std::time::Instant::now();
-    log::debug!("Starting get_git_log with limit {}", limit);
 
     let repo = REPO.lock().expect("repo lock");
     let repo = repo.as_ref().expect("repo");
 
-    // Empty repositories have no commits yet
-    if repo.head().is_err() {
-        debug!("Repository has no commits yet, returning empty log");
-        return Ok(Vec::new());
-    }
+    let matches = history::search(repo, query)?;
+
+    log::debug!(
+        "search_history completed in {}ms, {} matches",
+        start.elapsed().as_millis(),
+        matches.len()
+    );
 
-    let mut revwalk = repo.revwalk()?;
-    revwalk.push_head()?;
+    Ok(matches)
+}
 
-    let mut entries = Vec::new();
+pub fn get_commit_details(hash: &str) -> Result<CommitDetails, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
 
-    for oid in revwalk.take(limit) {
-        let oid = oid?;
-        let commit = repo.find_commit(oid)?;
+    history::commit_details(repo, hash)
+}
 
-        let hash = oid.to_string();
-        let message = commit.message().unwrap_or("").to_string();
-        let author = commit.author().name().unwrap_or("").to_string();
+pub fn query_git_log(query: &LogQuery) -> Result<Vec<GitLogEntry>, Error> {
+    let start = std::time::Instant::now();
+    log::debug!("Starting query_git_log with {:?}", query);
 
-        // Format date as readable string in local timezone
-        let time = commit.time();
-        let datetime = DateTime::from_timestamp(time.seconds(), 0).unwrap_or(DateTime::UNIX_EPOCH);
-        let local_datetime = datetime.with_timezone(&Local);
-        let date = local_datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
 
-        entries.push(GitLogEntry {
-            hash,
-            message,
-            author,
-            date,
-        });
-    }
+    let entries = history::query(repo, query)?;
 
     let duration = start.elapsed();
     log::debug!(
-        "get_git_log completed in {}ms, returned {} entries",
+        "query_git_log completed in {}ms, returned {} entries",
         duration.as_millis(),
         entries.len()
     );
 
     Ok(entries)
 }
+
+/// Bring the search index up to date, if it is in use
+fn refresh_search_index(repo: &Repository) {
+    if let Some(index) = SEARCH_INDEX.lock().expect("search index lock").as_mut()
+        && let Err(e) = index.refresh(repo)
+    {
+        warn!("Failed to refresh the search index: {e}");
+    }
+}
+
+/// Re-index the notes changed since the last update, e.g. after saving a note.
+/// Returns the number of files looked at.
+pub fn update_search_index() -> Result<usize, Error> {
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    SEARCH_INDEX
+        .lock()
+        .expect("search index lock")
+        .get_or_insert_with(|| search::Index::load(repo))
+        .refresh(repo)
+}
+
+/// See [`search::Index::search`]
+pub fn search(query: &str, folder: Option<&str>, limit: usize) -> Result<Vec<SearchResult>, Error> {
+    let start = std::time::Instant::now();
+
+    let repo = REPO.lock().expect("repo lock");
+    let repo = repo.as_ref().expect("repo");
+
+    let Some(workdir) = repo.workdir() else {
+        return Ok(Vec::new());
+    };
+
+    let mut index = SEARCH_INDEX.lock().expect("search index lock");
+    let index = match index.as_mut() {
+        Some(index) => index,
+        None => {
+            let mut loaded = search::Index::load(repo);
+            loaded.refresh(repo)?;
+            index.insert(loaded)
+        }
+    };
+
+    let results = index.search(workdir, query, folder, limit);
+
+    log::debug!(
+        "search completed in {}ms, {} results",
+        start.elapsed().as_millis(),
+        results.len()
+    );
+
+    Ok(results)
+}
@@ -0,0 +1,358 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    thread,
+    time::Duration,
+};
+
+use git2::{ErrorClass, ErrorCode, ProxyOptions};
+
+use super::progress::{self, Phase, Reporter};
+use crate::Error;
+
+/// Upper bound for the delay between two attempts
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How often the progress is asked whether to cancel while waiting for a retry
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// SSH errors raised before the session is established, lowercase
+const SSH_CONNECT_ERRORS: [&str; 5] = [
+    "failed to connect",
+    "failed to start ssh session",
+    "banner",
+    "unable to exchange encryption keys",
+    "timed out",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkSettings {
+    /// Time allowed to establish a connection, in milliseconds
+    pub connect_timeout_ms: i32,
+    /// Time allowed for a single read or write on an established connection, in milliseconds
+    pub timeout_ms: i32,
+    /// `http://[user:password@]host:port` proxy for HTTP(S) remotes,
+    /// `None` to use `http.proxy` from the git config or the `https_proxy` environment variables
+    pub proxy: Option<String>,
+    /// How often a failed connection is retried, 0 to disable retries
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further attempt
+    pub retry_delay_ms: u64,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 7000,
+            timeout_ms: 7000,
+            proxy: None,
+            max_retries: 2,
+            retry_delay_ms: 1000,
+        }
+    }
+}
+
+static SETTINGS: LazyLock<Mutex<NetworkSettings>> =
+    LazyLock::new(|| Mutex::new(NetworkSettings::default()));
+
+pub fn set_network_settings(settings: NetworkSettings) -> Result<(), Error> {
+    if settings.connect_timeout_ms < 0 || settings.timeout_ms < 0 {
+        return Err(Error::invalid_input("timeouts must not be negative"));
+    }
+
+    if let Some(proxy) = &settings.proxy
+        && !proxy.starts_with("http://")
+        && !proxy.starts_with("https://")
+    {
+        return Err(Error::invalid_input(&format!(
+            "unsupported proxy url: {proxy}"
+        )));
+    }
+
+    apply_timeouts(&settings);
+
+    info!(
+        "Network settings: connect timeout {}ms, timeout {}ms, proxy {}, {} retries",
+        settings.connect_timeout_ms,
+        settings.timeout_ms,
+        if settings.proxy.is_some() {
+            "set"
+        } else {
+            "auto"
+        },
+        settings.max_retries
+    );
+
+    *SETTINGS.lock().expect("network settings lock") = settings;
+
+    Ok(())
+}
+
+pub fn network_settings() -> NetworkSettings {
+    SETTINGS.lock().expect("network settings lock").clone()
+}
+
+/// libgit2 only has process wide timeouts
+pub fn apply_timeouts(settings: &NetworkSettings) {
+    unsafe {
+        libgit2_sys::git_libgit2_opts(
+            libgit2_sys::GIT_OPT_SET_SERVER_CONNECT_TIMEOUT as std::ffi::c_int,
+            settings.connect_timeout_ms,
+        );
+        libgit2_sys::git_libgit2_opts(
+            libgit2_sys::GIT_OPT_SET_SERVER_TIMEOUT as std::ffi::c_int,
+            settings.timeout_ms,
+        );
+    };
+}
+
+/// Proxy for fetch and push options, see [`NetworkSettings::proxy`]
+pub fn proxy_options() -> ProxyOptions<'static> {
+    let mut options = ProxyOptions::new();
+
+    match &SETTINGS.lock().expect("network settings lock").proxy {
+        Some(url) => options.url(url),
+        None => options.auto(),
+    };
+
+    options
+}
+
+/// Run a network operation, retrying with exponential backoff while it fails
+/// with an error that may go away on its own (dropped connection, timeout).
+///
+/// The wait reports [`Phase::Retrying`] to `reporter`, which can cancel it: the
+/// caller usually holds the repository lock meanwhile.
+pub fn retry<T>(
+    operation: &str,
+    reporter: Option<&Reporter>,
+    mut f: impl FnMut() -> Result<T, git2::Error>,
+) -> Result<T, git2::Error> {
+    let settings = network_settings();
+    retry_with(&settings, operation, reporter, &mut f)
+}
+
+fn retry_with<T>(
+    settings: &NetworkSettings,
+    operation: &str,
+    reporter: Option<&Reporter>,
+    f: &mut dyn FnMut() -> Result<T, git2::Error>,
+) -> Result<T, git2::Error> {
+    let mut attempt = 0;
+
+    loop {
+        match f() {
+            Err(e) if attempt < settings.max_retries && is_transient(&e) => {
+                let delay = backoff(settings.retry_delay_ms, attempt);
+                attempt += 1;
+                warn!(
+                    "{operation} failed ({e}), retry {attempt}/{} in {}ms",
+                    settings.max_retries,
+                    delay.as_millis()
+                );
+                wait(delay, reporter)?;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Sleep for `delay`, fails with [`progress::cancelled_error`] once `reporter` cancels
+fn wait(delay: Duration, reporter: Option<&Reporter>) -> Result<(), git2::Error> {
+    let Some(reporter) = reporter else {
+        thread::sleep(delay);
+        return Ok(());
+    };
+
+    let mut waited = Duration::ZERO;
+    loop {
+        let percent = (waited.as_millis() * 100)
+            .checked_div(delay.as_millis())
+            .unwrap_or(100) as i32;
+        if !reporter.report(Phase::Retrying, percent, 0) {
+            return Err(progress::cancelled_error());
+        }
+        if waited >= delay {
+            return Ok(());
+        }
+
+        let step = CANCEL_CHECK_INTERVAL.min(delay - waited);
+        thread::sleep(step);
+        waited += step;
+    }
+}
+
+fn backoff(initial_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(initial_ms.saturating_mul(1 << attempt.min(16))).min(MAX_RETRY_DELAY)
+}
+
+/// Connection problems are worth another try, rejected credentials,
+/// certificates or refs are not.
+fn is_transient(error: &git2::Error) -> bool {
+    match (error.code(), error.class()) {
+        (ErrorCode::Timeout, _) => true,
+        (ErrorCode::Auth | ErrorCode::Certificate | ErrorCode::User | ErrorCode::NotFound, _) => {
+            false
+        }
+        // Failed requests, TLS handshakes and sockets
+        (_, ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssl | ErrorClass::Os) => true,
+        // Once the session is up, SSH errors come from the remote
+        (_, ErrorClass::Ssh) => {
+            let message = error.message().to_lowercase();
+            SSH_CONNECT_ERRORS
+                .iter()
+                .any(|connect| message.contains(connect))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retry_transient() {
+        let settings = NetworkSettings {
+            max_retries: 2,
+            retry_delay_ms: 0,
+            ..Default::default()
+        };
+
+        // Dropped connections are retried until they succeed
+        let mut calls = 0;
+        let res = retry_with(&settings, "fetch", None, &mut || {
+            calls += 1;
+            if calls < 3 {
+                Err(git2::Error::new(
+                    ErrorCode::GenericError,
+                    ErrorClass::Net,
+                    "connection reset",
+                ))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(res.unwrap(), 3);
+
+        // ... but only `max_retries` times
+        let mut calls = 0;
+        let res: Result<(), _> = retry_with(&settings, "fetch", None, &mut || {
+            calls += 1;
+            Err(git2::Error::new(
+                ErrorCode::Timeout,
+                ErrorClass::Net,
+                "timed out",
+            ))
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 3);
+
+        // Rejected credentials are not retried
+        let mut calls = 0;
+        let res: Result<(), _> = retry_with(&settings, "push", None, &mut || {
+            calls += 1;
+            Err(git2::Error::new(
+                ErrorCode::Auth,
+                ErrorClass::Http,
+                "authentication required",
+            ))
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn transient_classes() {
+        let transient = |class, message| {
+            is_transient(&git2::Error::new(ErrorCode::GenericError, class, message))
+        };
+
+        assert!(transient(
+            ErrorClass::Http,
+            "unexpected http status code: 502"
+        ));
+        assert!(transient(ErrorClass::Ssl, "SSL error: connection reset"));
+        assert!(transient(
+            ErrorClass::Os,
+            "failed to connect: Network is unreachable"
+        ));
+        assert!(transient(
+            ErrorClass::Ssh,
+            "Failed to start SSH session: Unable to exchange encryption keys"
+        ));
+        assert!(transient(ErrorClass::Ssh, "Failed getting banner"));
+
+        assert!(!transient(ErrorClass::Ssh, "ERROR: Repository not found."));
+        assert!(!transient(ErrorClass::Reference, "reference not found"));
+        assert!(!is_transient(&git2::Error::new(
+            ErrorCode::Certificate,
+            ErrorClass::Ssl,
+            "invalid certificate"
+        )));
+        assert!(!is_transient(&git2::Error::new(
+            ErrorCode::NotFound,
+            ErrorClass::Http,
+            "repository not found"
+        )));
+    }
+
+    struct CancelAfter(usize);
+
+    impl progress::Progress for CancelAfter {
+        fn report(&mut self, phase: Phase, _percent: i32, _bytes: usize) -> bool {
+            assert_eq!(phase, Phase::Retrying);
+            self.0 = self.0.saturating_sub(1);
+            self.0 > 0
+        }
+    }
+
+    #[test]
+    fn retry_cancelled() {
+        let settings = NetworkSettings {
+            max_retries: 2,
+            retry_delay_ms: 60_000,
+            ..Default::default()
+        };
+
+        let mut progress = CancelAfter(3);
+        let reporter = Reporter::new(&mut progress);
+        let mut calls = 0;
+        let res: Result<(), _> = retry_with(&settings, "fetch", Some(&reporter), &mut || {
+            calls += 1;
+            Err(git2::Error::new(
+                ErrorCode::GenericError,
+                ErrorClass::Net,
+                "connection reset",
+            ))
+        });
+
+        assert_eq!(res.unwrap_err().code(), ErrorCode::User);
+        assert!(reporter.is_cancelled());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        assert_eq!(backoff(500, 0), Duration::from_millis(500));
+        assert_eq!(backoff(500, 1), Duration::from_millis(1000));
+        assert_eq!(backoff(500, 2), Duration::from_millis(2000));
+        assert_eq!(backoff(500, 10), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn settings_validation() {
+        let proxy = NetworkSettings {
+            proxy: Some("socks5://proxy:1080".to_string()),
+            ..Default::default()
+        };
+        assert!(set_network_settings(proxy).is_err());
+
+        let timeout = NetworkSettings {
+            timeout_ms: -1,
+            ..Default::default()
+        };
+        assert!(set_network_settings(timeout).is_err());
+
+        assert_eq!(network_settings(), NetworkSettings::default());
+    }
+}
@@ -0,0 +1,125 @@
+use std::cell::{Cell, RefCell};
+
+use git2::{PackBuilderStage, RemoteCallbacks, build::CheckoutBuilder};
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Downloading objects (clone, fetch)
+    Receiving = 0,
+    /// Resolving deltas of the received pack
+    Resolving = 1,
+    /// Building the pack to upload
+    Packing = 2,
+    /// Uploading the pack (push)
+    Uploading = 3,
+    /// Updating the working tree
+    Checkout = 4,
+    /// Merging fetched changes; last chance to cancel a pull
+    Merging = 5,
+    /// Waiting before a failed connection is retried, the percent is the time waited
+    Retrying = 6,
+}
+
+/// Receives the progress of network operations, return `false` to cancel.
+///
+/// Cancellation is honoured while receiving, resolving and waiting for a retry,
+/// and before push negotiation and merging starts. Once libgit2 uploads a pack or updates the
+/// working tree it can't be interrupted, so the return value is ignored there.
+pub trait Progress {
+    fn report(&mut self, phase: Phase, percent: i32, bytes: usize) -> bool;
+}
+
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn report(&mut self, _phase: Phase, _percent: i32, _bytes: usize) -> bool {
+        true
+    }
+}
+
+/// Shares one [`Progress`] between the libgit2 callbacks of an operation,
+/// and remembers whether it asked to cancel.
+pub struct Reporter<'p> {
+    progress: RefCell<&'p mut dyn Progress>,
+    cancelled: Cell<bool>,
+}
+
+impl<'p> Reporter<'p> {
+    pub fn new(progress: &'p mut dyn Progress) -> Self {
+        Self {
+            progress: RefCell::new(progress),
+            cancelled: Cell::new(false),
+        }
+    }
+
+    /// Returns `false` once the operation should stop.
+    pub fn report(&self, phase: Phase, percent: i32, bytes: usize) -> bool {
+        if !self.cancelled.get() && !self.progress.borrow_mut().report(phase, percent, bytes) {
+            info!("Operation cancelled during {phase:?}");
+            self.cancelled.set(true);
+        }
+        !self.cancelled.get()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+
+    /// Install the fetch and push progress callbacks.
+    pub fn remote_callbacks<'a>(&'a self, callbacks: &mut RemoteCallbacks<'a>) {
+        callbacks.transfer_progress(|stats| {
+            if stats.received_objects() < stats.total_objects() {
+                self.report(
+                    Phase::Receiving,
+                    percent(stats.received_objects(), stats.total_objects()),
+                    stats.received_bytes(),
+                )
+            } else {
+                self.report(
+                    Phase::Resolving,
+                    percent(stats.indexed_deltas(), stats.total_deltas()),
+                    stats.received_bytes(),
+                )
+            }
+        });
+
+        callbacks.pack_progress(|stage, current, total| {
+            if stage == PackBuilderStage::Deltafication {
+                self.report(Phase::Packing, percent(current, total), 0);
+            }
+        });
+
+        callbacks.push_transfer_progress(|current, total, bytes| {
+            self.report(Phase::Uploading, percent(current, total), bytes);
+        });
+
+        callbacks.push_negotiation(|_updates| {
+            if self.report(Phase::Uploading, 0, 0) {
+                Ok(())
+            } else {
+                Err(cancelled_error())
+            }
+        });
+    }
+
+    /// Install the working tree progress callback.
+    pub fn checkout<'a>(&'a self, builder: &mut CheckoutBuilder<'a>) {
+        builder.progress(|_path, completed, total| {
+            self.report(Phase::Checkout, percent(completed, total), 0);
+        });
+    }
+}
+
+/// Error returned by libgit2 when a callback aborts, see [`crate::ErrorCategory::Cancelled`]
+pub fn cancelled_error() -> git2::Error {
+    git2::Error::new(
+        git2::ErrorCode::User,
+        git2::ErrorClass::Callback,
+        "operation cancelled",
+    )
+}
+
+fn percent(current: usize, total: usize) -> i32 {
+    (current * 100).checked_div(total).unwrap_or(0) as i32
+}
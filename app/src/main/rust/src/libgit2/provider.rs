@@ -0,0 +1,329 @@
+use crate::Error;
+
+/// Git hosting service behind a remote URL
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Unknown = 0,
+    GitHub = 1,
+    GitLab = 2,
+    Bitbucket = 3,
+    AzureDevOps = 4,
+    CodeCommit = 5,
+    Gitea = 6,
+    Forgejo = 7,
+    Codeberg = 8,
+    Sourcehut = 9,
+}
+
+/// How the user can fix a failed remote operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remediation {
+    pub provider: Provider,
+    /// Kind of credential the provider expects, e.g. "Personal Access Token"
+    pub credential: &'static str,
+    /// Page where that credential can be created
+    pub token_url: Option<String>,
+    /// Scopes or permissions the credential needs
+    pub scopes: Vec<&'static str>,
+}
+
+impl Provider {
+    pub fn detect(url: &str) -> Self {
+        let Some(host) = host(url) else {
+            return Self::Unknown;
+        };
+
+        if host == "github.com" || host.starts_with("github.") {
+            Self::GitHub
+        } else if host == "gitlab.com" || host.starts_with("gitlab.") {
+            Self::GitLab
+        } else if host == "bitbucket.org" {
+            Self::Bitbucket
+        } else if host.ends_with("azure.com") || host.ends_with("visualstudio.com") {
+            Self::AzureDevOps
+        } else if host.contains("codecommit.") {
+            Self::CodeCommit
+        } else if host == "codeberg.org" {
+            Self::Codeberg
+        } else if host == "sr.ht" || host.ends_with(".sr.ht") {
+            Self::Sourcehut
+        } else if host.starts_with("forgejo.") {
+            Self::Forgejo
+        } else if host.starts_with("gitea.") {
+            Self::Gitea
+        } else {
+            Self::Unknown
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unknown => "Git",
+            Self::GitHub => "GitHub",
+            Self::GitLab => "GitLab",
+            Self::Bitbucket => "Bitbucket",
+            Self::AzureDevOps => "Azure DevOps",
+            Self::CodeCommit => "AWS CodeCommit",
+            Self::Gitea => "Gitea",
+            Self::Forgejo => "Forgejo",
+            Self::Codeberg => "Codeberg",
+            Self::Sourcehut => "sourcehut",
+        }
+    }
+}
+
+impl Remediation {
+    pub fn for_url(url: &str) -> Self {
+        let provider = Provider::detect(url);
+        let host = host(url).unwrap_or_default();
+
+        if !is_http(url) {
+            return Self::ssh(provider, &host);
+        }
+
+        let (credential, token_url, scopes) = match provider {
+            Provider::GitHub => (
+                "Personal Access Token",
+                Some(format!("https://{host}/settings/tokens")),
+                vec!["repo"],
+            ),
+            Provider::GitLab => (
+                "Personal Access Token",
+                Some(format!(
+                    "https://{host}/-/user_settings/personal_access_tokens"
+                )),
+                vec!["read_repository", "write_repository"],
+            ),
+            Provider::Bitbucket => (
+                "App Password",
+                Some("https://bitbucket.org/account/settings/app-passwords/".to_string()),
+                vec!["repository:write"],
+            ),
+            Provider::AzureDevOps => (
+                "Personal Access Token",
+                Some(match azure_organization(url) {
+                    Some(org) if host == "dev.azure.com" => {
+                        format!("https://dev.azure.com/{org}/_usersSettings/tokens")
+                    }
+                    _ => format!("https://{host}/_usersSettings/tokens"),
+                }),
+                vec!["vso.code_write"],
+            ),
+            Provider::CodeCommit => (
+                "HTTPS Git credentials for AWS CodeCommit",
+                Some("https://console.aws.amazon.com/iam/home#/security_credentials".to_string()),
+                vec!["codecommit:GitPull", "codecommit:GitPush"],
+            ),
+            Provider::Gitea | Provider::Forgejo | Provider::Codeberg => (
+                "Access Token",
+                Some(format!("https://{host}/user/settings/applications")),
+                vec!["write:repository"],
+            ),
+            // git.sr.ht only accepts pushes over SSH
+            Provider::Sourcehut => return Self::ssh(provider, &host),
+            Provider::Unknown => ("password or access token", None, vec![]),
+        };
+
+        Self {
+            provider,
+            credential,
+            token_url,
+            scopes,
+        }
+    }
+
+    fn ssh(provider: Provider, host: &str) -> Self {
+        let token_url = match provider {
+            Provider::GitHub => Some(format!("https://{host}/settings/keys")),
+            Provider::GitLab => Some(format!("https://{host}/-/user_settings/ssh_keys")),
+            Provider::Bitbucket => {
+                Some("https://bitbucket.org/account/settings/ssh-keys/".to_string())
+            }
+            Provider::AzureDevOps => Some("https://dev.azure.com/_usersSettings/keys".to_string()),
+            Provider::CodeCommit => {
+                Some("https://console.aws.amazon.com/iam/home#/security_credentials".to_string())
+            }
+            Provider::Gitea | Provider::Forgejo | Provider::Codeberg => {
+                Some(format!("https://{host}/user/settings/keys"))
+            }
+            Provider::Sourcehut => Some("https://meta.sr.ht/keys".to_string()),
+            Provider::Unknown => None,
+        };
+
+        Self {
+            provider,
+            credential: "SSH key",
+            token_url,
+            scopes: vec![],
+        }
+    }
+
+    /// Short advice for logs and error messages
+    pub fn hint(&self) -> String {
+        match self.provider {
+            Provider::CodeCommit => "check IAM permissions".to_string(),
+            _ => format!("check credentials (use {})", self.credential),
+        }
+    }
+}
+
+/// Wrap the error of a failed clone/push/fetch with provider specific advice.
+pub fn remote_error(error: git2::Error, url: &str, operation: &str) -> Error {
+    // Local and file:// remotes have no credentials to fix
+    if Provider::detect(url) == Provider::Unknown && !is_http(url) && !is_ssh(url) {
+        return Error::git2(error, operation);
+    }
+
+    let remediation = Remediation::for_url(url);
+
+    let msg = format!(
+        "{} {operation} failed - {}",
+        remediation.provider.name(),
+        remediation.hint()
+    );
+    error!("{msg} ({url})");
+
+    Error::remote(error, &msg, remediation)
+}
+
+fn is_http(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn is_ssh(url: &str) -> bool {
+    url.starts_with("ssh://") || (!url.contains("://") && url.contains('@') && url.contains(':'))
+}
+
+/// Host of `scheme://[user@]host[:port]/path` or scp-like `user@host:path` URLs, lowercased
+fn host(url: &str) -> Option<String> {
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest,
+        None if url.contains(':') => url,
+        None => return None,
+    };
+
+    let authority = rest.split(['/', ':']).next()?;
+    let host = authority.rsplit('@').next()?;
+
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+fn azure_organization(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    rest.split('/').nth(1).filter(|org| !org.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        let cases = [
+            ("https://github.com/user/notes.git", Provider::GitHub),
+            ("git@github.com:user/notes.git", Provider::GitHub),
+            ("https://gitlab.com/user/notes.git", Provider::GitLab),
+            (
+                "https://gitlab.example.org/user/notes.git",
+                Provider::GitLab,
+            ),
+            (
+                "https://user@bitbucket.org/user/notes.git",
+                Provider::Bitbucket,
+            ),
+            (
+                "https://dev.azure.com/org/project/_git/notes",
+                Provider::AzureDevOps,
+            ),
+            (
+                "https://org.visualstudio.com/project/_git/notes",
+                Provider::AzureDevOps,
+            ),
+            (
+                "https://git-codecommit.eu-west-1.amazonaws.com/v1/repos/notes",
+                Provider::CodeCommit,
+            ),
+            ("https://codeberg.org/user/notes.git", Provider::Codeberg),
+            ("https://gitea.example.org/user/notes.git", Provider::Gitea),
+            (
+                "ssh://git@forgejo.example.org:2222/user/notes.git",
+                Provider::Forgejo,
+            ),
+            ("git@git.sr.ht:~user/notes", Provider::Sourcehut),
+            ("https://example.org/notes.git", Provider::Unknown),
+            ("/local/path/notes", Provider::Unknown),
+        ];
+
+        for (url, provider) in cases {
+            assert_eq!(Provider::detect(url), provider, "{url}");
+        }
+    }
+
+    #[test]
+    fn remediation() {
+        let r = Remediation::for_url("https://github.com/user/notes.git");
+        assert_eq!(r.credential, "Personal Access Token");
+        assert_eq!(
+            r.token_url.as_deref(),
+            Some("https://github.com/settings/tokens")
+        );
+        assert_eq!(r.scopes, vec!["repo"]);
+
+        let r = Remediation::for_url("https://gitlab.example.org/user/notes.git");
+        assert_eq!(
+            r.token_url.as_deref(),
+            Some("https://gitlab.example.org/-/user_settings/personal_access_tokens")
+        );
+
+        let r = Remediation::for_url("https://dev.azure.com/org/project/_git/notes");
+        assert_eq!(
+            r.token_url.as_deref(),
+            Some("https://dev.azure.com/org/_usersSettings/tokens")
+        );
+
+        let r = Remediation::for_url("https://codeberg.org/user/notes.git");
+        assert_eq!(
+            r.token_url.as_deref(),
+            Some("https://codeberg.org/user/settings/applications")
+        );
+        assert_eq!(r.scopes, vec!["write:repository"]);
+
+        // SSH remotes need a key, not a token
+        let r = Remediation::for_url("git@github.com:user/notes.git");
+        assert_eq!(r.credential, "SSH key");
+        assert_eq!(
+            r.token_url.as_deref(),
+            Some("https://github.com/settings/keys")
+        );
+
+        let r = Remediation::for_url("https://git.sr.ht/~user/notes");
+        assert_eq!(r.credential, "SSH key");
+
+        let e = remote_error(
+            git2::Error::from_str("failed"),
+            "file:///tmp/notes.git",
+            "clone",
+        );
+        assert!(matches!(e, Error::Git2 { .. }));
+
+        let e = remote_error(
+            git2::Error::from_str("failed"),
+            "https://github.com/user/notes.git",
+            "push",
+        );
+        assert_eq!(
+            e.to_string(),
+            "GitHub push failed - check credentials (use Personal Access Token): failed"
+        );
+
+        let r = Remediation::for_url("https://example.org/notes.git");
+        assert_eq!(r.provider, Provider::Unknown);
+        assert_eq!(r.token_url, None);
+        assert_eq!(r.hint(), "check credentials (use password or access token)");
+    }
+}
@@ -0,0 +1,316 @@
+use std::{fs, path::Path};
+
+use git2::{
+    ErrorCode, IndexAddOption, Oid, RebaseOptions, Repository, RepositoryState, ResetType,
+    Signature,
+};
+
+use super::{
+    signing, snapshot,
+    status::{self, FileState, PathStatus},
+};
+use crate::Error;
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    None = 0,
+    Merge = 1,
+    Rebase = 2,
+    CherryPick = 3,
+    Revert = 4,
+    Bisect = 5,
+    ApplyMailbox = 6,
+}
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Go back to where the operation started, uncommitted changes are saved in a snapshot
+    Abort = 0,
+    /// Stage the working tree as the resolution and finish the operation
+    Continue = 1,
+    /// End the operation but leave the working tree as it is, as uncommitted changes
+    KeepWorkingTree = 2,
+}
+
+impl Recovery {
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Self::Abort),
+            1 => Some(Self::Continue),
+            2 => Some(Self::KeepWorkingTree),
+            _ => None,
+        }
+    }
+}
+
+/// An operation left unfinished, e.g. by another git client, and the files it affects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoState {
+    pub operation: Operation,
+    /// Every changed path, conflicts are [`FileState::Conflicted`]
+    pub files: Vec<PathStatus>,
+}
+
+pub fn state(repo: &Repository) -> Result<RepoState, Error> {
+    Ok(RepoState {
+        operation: operation(repo),
+        files: status::status(repo, None, false)?,
+    })
+}
+
+/// Resolve the interrupted operation, or the conflicts left without one, as chosen by the user
+pub fn recover(
+    repo: &Repository,
+    recovery: Recovery,
+    signature: &Signature,
+) -> Result<RepoState, Error> {
+    let operation = operation(repo);
+    info!("Recovering from {operation:?} with {recovery:?}");
+
+    match recovery {
+        Recovery::Abort => {
+            snapshot::save(repo, "abort", false)?;
+            reset(repo, operation, ResetType::Hard)?;
+        }
+        Recovery::KeepWorkingTree => reset(repo, operation, ResetType::Mixed)?,
+        Recovery::Continue => match operation {
+            Operation::None => {
+                return Err(Error::invalid_input("No operation to continue"));
+            }
+            Operation::Rebase => {
+                stage_resolution(repo)?;
+                if !continue_rebase(repo, signature)? {
+                    // Stopped at the next conflict
+                    return state(repo);
+                }
+            }
+            Operation::Merge | Operation::CherryPick | Operation::Revert => {
+                stage_resolution(repo)?;
+                commit_resolution(repo, operation, signature)?;
+            }
+            Operation::Bisect | Operation::ApplyMailbox => {
+                return Err(Error::invalid_input(&format!(
+                    "{operation:?} can only be continued with git"
+                )));
+            }
+        },
+    }
+
+    repo.cleanup_state()
+        .map_err(|e| Error::git2(e, "cleanup_state"))?;
+
+    state(repo)
+}
+
+fn operation(repo: &Repository) -> Operation {
+    match repo.state() {
+        RepositoryState::Clean => Operation::None,
+        RepositoryState::Merge => Operation::Merge,
+        RepositoryState::Revert | RepositoryState::RevertSequence => Operation::Revert,
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Operation::CherryPick,
+        RepositoryState::Bisect => Operation::Bisect,
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => Operation::Rebase,
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+            Operation::ApplyMailbox
+        }
+    }
+}
+
+/// Move HEAD back to where `operation` started and reset to it
+fn reset(repo: &Repository, operation: Operation, reset_type: ResetType) -> Result<(), Error> {
+    match original_head(repo, operation)? {
+        Some(OriginalHead::Branch(branch)) => repo
+            .set_head(&branch)
+            .map_err(|e| Error::git2(e, "set_head"))?,
+        Some(OriginalHead::Detached(oid)) => repo
+            .set_head_detached(oid)
+            .map_err(|e| Error::git2(e, "set_head_detached"))?,
+        None => {}
+    }
+
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| Error::git2(e, "head"))?;
+
+    repo.reset(head.as_object(), reset_type, None)
+        .map_err(|e| Error::git2(e, "reset"))
+}
+
+enum OriginalHead {
+    Branch(String),
+    Detached(Oid),
+}
+
+/// Where a rebase or bisect started, the other operations do not move HEAD
+fn original_head(repo: &Repository, operation: Operation) -> Result<Option<OriginalHead>, Error> {
+    let read = |file: &str| {
+        fs::read_to_string(repo.path().join(file))
+            .ok()
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    };
+
+    let head = match operation {
+        Operation::Rebase | Operation::ApplyMailbox => {
+            let dir = ["rebase-merge", "rebase-apply"]
+                .into_iter()
+                .find(|dir| repo.path().join(dir).is_dir());
+            let Some(dir) = dir else {
+                return Ok(None);
+            };
+
+            match read(&format!("{dir}/head-name")).filter(|name| name != "detached HEAD") {
+                Some(branch) => Some(OriginalHead::Branch(branch)),
+                None => read(&format!("{dir}/orig-head"))
+                    .and_then(|oid| Oid::from_str(&oid).ok())
+                    .map(OriginalHead::Detached),
+            }
+        }
+        // Holds the branch name, or the commit when bisecting from a detached HEAD
+        Operation::Bisect => read("BISECT_START").map(|start| match Oid::from_str(&start) {
+            Ok(oid) if start.len() == 40 => OriginalHead::Detached(oid),
+            _ => OriginalHead::Branch(format!("refs/heads/{start}")),
+        }),
+        _ => None,
+    };
+
+    Ok(head)
+}
+
+/// Stage the working tree, refusing files that still have conflict markers
+fn stage_resolution(repo: &Repository) -> Result<(), Error> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::repo_state("Repository has no working directory"))?;
+
+    let unresolved: Vec<String> = state(repo)?
+        .files
+        .into_iter()
+        .filter(|file| file.index == Some(FileState::Conflicted))
+        .map(|file| file.path)
+        .filter(|path| has_conflict_markers(&workdir.join(path)))
+        .collect();
+
+    if !unresolved.is_empty() {
+        return Err(Error::conflict(&format!(
+            "Resolve the conflicts in {} first",
+            unresolved.join(", ")
+        )));
+    }
+
+    let mut index = repo.index().map_err(|e| Error::git2(e, "index"))?;
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+        .map_err(|e| Error::git2(e, "add_all"))?;
+    // Files resolved by deleting them
+    index
+        .update_all(["*"].iter(), None)
+        .map_err(|e| Error::git2(e, "update_all"))?;
+    index.write().map_err(|e| Error::git2(e, "write"))?;
+
+    Ok(())
+}
+
+fn has_conflict_markers(path: &Path) -> bool {
+    let Ok(content) = fs::read(path) else {
+        return false;
+    };
+
+    String::from_utf8_lossy(&content)
+        .lines()
+        .any(|line| line.starts_with("<<<<<<< ") || line.starts_with(">>>>>>> "))
+}
+
+/// Commit the staged resolution of a merge, cherry-pick or revert.
+/// Only the current commit of a sequence of picks is finished.
+fn commit_resolution(
+    repo: &Repository,
+    operation: Operation,
+    signature: &Signature,
+) -> Result<(), Error> {
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| Error::git2(e, "head"))?;
+
+    let mut parents = vec![head];
+    let mut author = signature.to_owned();
+
+    match operation {
+        Operation::Merge => {
+            let merge_heads = fs::read_to_string(repo.path().join("MERGE_HEAD"))
+                .map_err(|e| Error::io(e, "MERGE_HEAD"))?;
+            for line in merge_heads.lines().filter(|line| !line.is_empty()) {
+                let oid = Oid::from_str(line.trim())?;
+                parents.push(repo.find_commit(oid)?);
+            }
+        }
+        // A picked commit keeps its author
+        Operation::CherryPick => {
+            if let Ok(picked) = repo
+                .find_reference("CHERRY_PICK_HEAD")
+                .and_then(|reference| reference.peel_to_commit())
+            {
+                author = picked.author().to_owned();
+            }
+        }
+        _ => {}
+    }
+
+    let message = repo
+        .message()
+        .unwrap_or_else(|_| format!("Finish {operation:?}"));
+
+    let tree = repo
+        .index()
+        .and_then(|mut index| index.write_tree())
+        .and_then(|oid| repo.find_tree(oid))
+        .map_err(|e| Error::git2(e, "write_tree"))?;
+
+    let parents: Vec<_> = parents.iter().collect();
+    signing::commit(repo, &author, signature, &message, &tree, &parents)
+        .map_err(|e| Error::git2(e, "commit"))?;
+
+    Ok(())
+}
+
+/// Commit the current step and apply the remaining ones.
+/// Returns false when a step stopped with conflicts.
+///
+/// Only rebases started by libgit2 can be continued, not those of the git command line.
+fn continue_rebase(repo: &Repository, signature: &Signature) -> Result<bool, Error> {
+    let mut rebase = repo
+        .open_rebase(Some(&mut RebaseOptions::new()))
+        .map_err(|_| Error::repo_state("This rebase can only be continued with git"))?;
+
+    let commit = |rebase: &mut git2::Rebase| match rebase.commit(None, signature, None) {
+        // The step became empty, e.g. the resolution dropped all its changes
+        Err(e) if e.code() == ErrorCode::Applied => Ok(()),
+        res => res.map(|_| ()).map_err(|e| Error::git2(e, "rebase commit")),
+    };
+
+    if rebase.operation_current().is_some() {
+        commit(&mut rebase)?;
+    }
+
+    while let Some(operation) = rebase.next() {
+        operation.map_err(|e| Error::git2(e, "rebase next"))?;
+
+        if repo.index()?.has_conflicts() {
+            return Ok(false);
+        }
+
+        commit(&mut rebase)?;
+    }
+
+    rebase
+        .finish(Some(signature))
+        .map_err(|e| Error::git2(e, "rebase finish"))?;
+
+    Ok(true)
+}
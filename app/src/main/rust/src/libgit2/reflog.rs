@@ -0,0 +1,110 @@
+use git2::{Repository, ResetType};
+
+use super::snapshot;
+use crate::Error;
+
+/// A move of HEAD or of a branch, the newest has index 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub index: usize,
+    /// Zero id when the ref was created
+    pub old_id: String,
+    pub new_id: String,
+    pub message: String,
+    /// Time of the move, in milliseconds
+    pub time: i64,
+}
+
+/// Entries of the reflog of `name`, HEAD if `None`, newest first.
+/// Branches can be given by their short name.
+pub fn list(repo: &Repository, name: Option<&str>) -> Result<Vec<ReflogEntry>, Error> {
+    let name = ref_name(repo, name)?;
+    let reflog = repo.reflog(&name).map_err(|e| Error::git2(e, "reflog"))?;
+
+    Ok(reflog
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| ReflogEntry {
+            index,
+            old_id: entry.id_old().to_string(),
+            new_id: entry.id_new().to_string(),
+            message: entry.message().unwrap_or("").to_string(),
+            time: entry.committer().when().seconds() * 1000,
+        })
+        .collect())
+}
+
+/// Move the branch back to where entry `index` of the reflog of `name` left it.
+///
+/// The current branch is reset with its working tree, after saving a snapshot that
+/// keeps both the uncommitted changes and the commits it moves away from.
+/// Other branches are only moved, after a snapshot keeps their commits.
+pub fn reset_to(repo: &Repository, name: Option<&str>, index: usize) -> Result<(), Error> {
+    let name = ref_name(repo, name)?;
+    let reflog = repo.reflog(&name).map_err(|e| Error::git2(e, "reflog"))?;
+
+    let Some(entry) = reflog.get(index) else {
+        return Err(Error::invalid_input(&format!(
+            "No entry {index} in the reflog of {name}"
+        )));
+    };
+
+    // The commit may have been pruned since
+    let target = repo
+        .find_commit(entry.id_new())
+        .map_err(|e| Error::git2(e, "find reflog commit"))?;
+
+    let head = repo.head().map_err(|e| Error::git2(e, "head"))?;
+    let is_current = name == "HEAD" || head.name() == Some(name.as_str());
+
+    if !is_current {
+        let mut reference = repo
+            .find_reference(&name)
+            .map_err(|e| Error::git2(e, "find_reference"))?;
+        let old = reference
+            .peel_to_commit()
+            .map_err(|e| Error::git2(e, "find branch commit"))?;
+        snapshot::save_commit(repo, "reset", &old)?;
+
+        reference
+            .set_target(target.id(), &format!("reset: moving to {name}@{{{index}}}"))
+            .map_err(|e| Error::git2(e, "set_target"))?;
+
+        info!("Moved {name} to {}", target.id());
+        return Ok(());
+    }
+
+    snapshot::save(repo, "reset", true)?;
+
+    repo.reset(target.as_object(), ResetType::Hard, None)
+        .map_err(|e| Error::git2(e, "reset"))?;
+
+    info!("Reset {name} to {}", target.id());
+
+    Ok(())
+}
+
+/// HEAD or a local branch, remote-tracking branches and the refs of the app are
+/// not moved by the user
+fn ref_name(repo: &Repository, name: Option<&str>) -> Result<String, Error> {
+    let name = name.unwrap_or("HEAD");
+    if name == "HEAD" {
+        return Ok(name.to_string());
+    }
+
+    let branch = if name.starts_with("refs/") {
+        if !name.starts_with("refs/heads/") {
+            return Err(Error::invalid_input(&format!(
+                "{name} is not a local branch"
+            )));
+        }
+        name.to_string()
+    } else {
+        format!("refs/heads/{name}")
+    };
+    if repo.find_reference(&branch).is_err() {
+        return Err(Error::invalid_input(&format!("No branch {name}")));
+    }
+
+    Ok(branch)
+}
@@ -0,0 +1,362 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+use git2::{Commit, ConfigLevel, ErrorClass, ErrorCode, Oid, Repository, Signature, Tree};
+use ssh_key::{HashAlg, LineEnding, PrivateKey, PublicKey, SshSig};
+
+use crate::Error;
+
+/// Namespace used by `git` for SSH signatures (`gpg.format=ssh`)
+pub const SSHSIG_NAMESPACE: &str = "git";
+
+/// Local config key of the trusted signing keys file, like for `git verify-commit`
+pub const ALLOWED_SIGNERS_CONFIG: &str = "gpg.ssh.allowedSignersFile";
+
+static SIGNING_KEY: LazyLock<Mutex<Option<PrivateKey>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Set by the app, takes precedence over [`ALLOWED_SIGNERS_CONFIG`]
+static ALLOWED_SIGNERS_FILE: LazyLock<Mutex<Option<PathBuf>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Trusted signing keys in the `ssh-keygen` allowed signers format, `None` to use
+/// [`ALLOWED_SIGNERS_CONFIG`] of the repository.
+pub fn set_allowed_signers_file(path: Option<&str>) {
+    match path {
+        Some(path) => info!("Allowed signers from {path}"),
+        None => info!("Allowed signers from the repository config"),
+    }
+
+    *ALLOWED_SIGNERS_FILE.lock().expect("allowed signers lock") = path.map(PathBuf::from);
+}
+
+/// Set the OpenSSH private key used to sign new commits, or `None` to disable signing.
+pub fn set_signing_key(private_key: Option<&str>) -> Result<(), Error> {
+    let key = match private_key {
+        Some(private_key) => {
+            let key = PrivateKey::from_openssh(private_key)
+                .map_err(|e| Error::invalid_input(&format!("invalid signing key: {e}")))?;
+
+            if key.is_encrypted() {
+                return Err(Error::invalid_input(
+                    "encrypted signing keys are not supported",
+                ));
+            }
+
+            info!("Commit signing enabled ({})", key.algorithm());
+            Some(key)
+        }
+        None => {
+            info!("Commit signing disabled");
+            None
+        }
+    };
+
+    *SIGNING_KEY.lock().expect("signing key lock") = key;
+
+    Ok(())
+}
+
+pub fn is_signing_enabled() -> bool {
+    SIGNING_KEY.lock().expect("signing key lock").is_some()
+}
+
+/// Create a commit and move HEAD to it, like `repo.commit(Some("HEAD"), ...)`.
+///
+/// When a signing key is set, the commit carries an SSHSIG signature in its
+/// `gpgsig` header, so forges show it as verified.
+pub fn commit(
+    repo: &Repository,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree: &Tree,
+    parents: &[&Commit],
+) -> Result<Oid, git2::Error> {
+    if !is_signing_enabled() {
+        return repo.commit(Some("HEAD"), author, committer, message, tree, parents);
+    }
+
+    let oid = write(repo, author, committer, message, tree, parents)?;
+
+    let summary = message.lines().next().unwrap_or_default();
+    let reflog_msg = match parents.len() {
+        0 => format!("commit (initial): {summary}"),
+        1 => format!("commit: {summary}"),
+        _ => format!("commit (merge): {summary}"),
+    };
+    update_head(
+        repo,
+        oid,
+        parents.first().map(|parent| parent.id()),
+        &reflog_msg,
+    )?;
+
+    debug!("Created signed commit {oid}");
+    Ok(oid)
+}
+
+/// Replace `commit`, which HEAD points to, by a commit with the same parents,
+/// like `git commit --amend`. Signed like [`commit`].
+pub fn amend(
+    repo: &Repository,
+    commit: &Commit,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree: &Tree,
+) -> Result<Oid, git2::Error> {
+    let parents: Vec<Commit> = commit.parents().collect();
+    let parents: Vec<&Commit> = parents.iter().collect();
+
+    let oid = write(repo, author, committer, message, tree, &parents)?;
+
+    let summary = message.lines().next().unwrap_or_default();
+    update_head(
+        repo,
+        oid,
+        Some(commit.id()),
+        &format!("commit (amend): {summary}"),
+    )?;
+
+    debug!("Amended {} as {oid}", commit.id());
+    Ok(oid)
+}
+
+/// Write the commit object, signed when a key is set, without moving any ref
+pub fn write(
+    repo: &Repository,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree: &Tree,
+    parents: &[&Commit],
+) -> Result<Oid, git2::Error> {
+    let key = SIGNING_KEY.lock().expect("signing key lock");
+
+    let Some(key) = key.as_ref() else {
+        return repo.commit(None, author, committer, message, tree, parents);
+    };
+
+    let buffer = repo.commit_create_buffer(author, committer, message, tree, parents)?;
+    let content = buffer
+        .as_str()
+        .ok_or_else(|| git2::Error::from_str("commit buffer is not valid utf-8"))?;
+
+    let signature = key
+        .sign(SSHSIG_NAMESPACE, HashAlg::Sha512, content.as_bytes())
+        .and_then(|sig| sig.to_pem(LineEnding::LF))
+        .map_err(|e| git2::Error::from_str(&format!("failed to sign commit: {e}")))?;
+
+    repo.commit_signed(content, &signature, None)
+}
+
+/// Move HEAD, or the branch it points to, from `expected` to `oid` like `repo.commit`
+/// would. Fails if another writer moved it meanwhile, `None` expects an unborn branch.
+fn update_head(
+    repo: &Repository,
+    oid: Oid,
+    expected: Option<Oid>,
+    reflog_msg: &str,
+) -> Result<(), git2::Error> {
+    let head = repo.find_reference("HEAD")?;
+    match (head.symbolic_target(), expected) {
+        (Some(target), Some(expected)) => {
+            repo.reference_matching(target, oid, true, expected, reflog_msg)?;
+        }
+        (Some(target), None) => {
+            repo.reference(target, oid, false, reflog_msg)?;
+        }
+        (None, expected) => {
+            if head.target() != expected {
+                return Err(git2::Error::new(
+                    ErrorCode::Modified,
+                    ErrorClass::Reference,
+                    "HEAD moved since the commit was prepared",
+                ));
+            }
+            repo.set_head_detached(oid)?;
+        }
+    }
+
+    Ok(())
+}
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Unsigned = 0,
+    /// Good signature from a key in the allowed signers list
+    Valid = 1,
+    /// Signature does not match the commit content
+    Invalid = 2,
+    /// Good signature, but the key is not in the allowed signers list
+    UntrustedKey = 3,
+    /// Signed with a format we can't verify (e.g. OpenPGP)
+    Unsupported = 4,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitSignature {
+    pub status: SignatureStatus,
+    /// Principal from the allowed signers list, or the key fingerprint for untrusted keys
+    pub signer: Option<String>,
+}
+
+impl CommitSignature {
+    fn unsigned() -> Self {
+        Self {
+            status: SignatureStatus::Unsigned,
+            signer: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct AllowedSigner {
+    principals: Vec<String>,
+    namespaces: Option<Vec<String>>,
+    key: PublicKey,
+}
+
+#[derive(Debug, Default)]
+pub struct AllowedSigners {
+    signers: Vec<AllowedSigner>,
+}
+
+impl AllowedSigners {
+    /// Load the allowed signers list from the file set by the app, or else from the
+    /// file in the local config of the repository. The working tree comes from the
+    /// remote and is never trusted. Without a file nobody is trusted.
+    pub fn load(repo: &Repository) -> Self {
+        let path = ALLOWED_SIGNERS_FILE
+            .lock()
+            .expect("allowed signers lock")
+            .clone()
+            .or_else(|| {
+                let config = repo.config().ok()?.open_level(ConfigLevel::Local).ok()?;
+                // Relative to the git directory
+                Some(
+                    repo.path()
+                        .join(config.get_path(ALLOWED_SIGNERS_CONFIG).ok()?),
+                )
+            });
+        let Some(path) = path else {
+            return Self::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) => {
+                warn!("Can't read allowed signers {}: {e}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse lines of `principals [options] keytype base64-key [comment]`.
+    pub fn parse(content: &str) -> Self {
+        let mut signers = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let Some(principals) = tokens.next() else {
+                continue;
+            };
+
+            let mut namespaces = None;
+            let mut key = None;
+
+            while let Some(token) = tokens.next() {
+                if let Some(value) = token.strip_prefix("namespaces=") {
+                    namespaces = Some(
+                        value
+                            .trim_matches('"')
+                            .split(',')
+                            .map(str::to_string)
+                            .collect(),
+                    );
+                } else if is_key_type(token) {
+                    key = tokens
+                        .next()
+                        .and_then(|data| PublicKey::from_openssh(&format!("{token} {data}")).ok());
+                    break;
+                }
+            }
+
+            match key {
+                Some(key) => signers.push(AllowedSigner {
+                    principals: principals.split(',').map(str::to_string).collect(),
+                    namespaces,
+                    key,
+                }),
+                None => warn!("Ignoring invalid allowed signers line: {line}"),
+            }
+        }
+
+        Self { signers }
+    }
+
+    fn find(&self, sig: &SshSig) -> Option<&AllowedSigner> {
+        self.signers.iter().find(|signer| {
+            signer.key.key_data() == sig.public_key()
+                && signer
+                    .namespaces
+                    .as_ref()
+                    .is_none_or(|namespaces| namespaces.iter().any(|ns| ns == sig.namespace()))
+        })
+    }
+}
+
+fn is_key_type(token: &str) -> bool {
+    token.starts_with("ssh-") || token.starts_with("ecdsa-") || token.starts_with("sk-")
+}
+
+/// Check the signature of a commit against the allowed signers list.
+pub fn verify_commit(repo: &Repository, oid: Oid, signers: &AllowedSigners) -> CommitSignature {
+    let (signature, content) = match repo.extract_signature(&oid, None) {
+        Ok(res) => res,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return CommitSignature::unsigned(),
+        Err(e) => {
+            warn!("extract_signature {oid}: {e}");
+            return CommitSignature::unsigned();
+        }
+    };
+
+    let sig = match SshSig::from_pem(&*signature) {
+        Ok(sig) => sig,
+        Err(_) => {
+            return CommitSignature {
+                status: SignatureStatus::Unsupported,
+                signer: None,
+            };
+        }
+    };
+
+    let key = PublicKey::from(sig.public_key().clone());
+
+    if sig.namespace() != SSHSIG_NAMESPACE || key.verify(SSHSIG_NAMESPACE, &content, &sig).is_err()
+    {
+        return CommitSignature {
+            status: SignatureStatus::Invalid,
+            signer: None,
+        };
+    }
+
+    match signers.find(&sig) {
+        Some(signer) => CommitSignature {
+            status: SignatureStatus::Valid,
+            signer: signer.principals.first().cloned(),
+        },
+        None => CommitSignature {
+            status: SignatureStatus::UntrustedKey,
+            signer: Some(key.fingerprint(HashAlg::Sha256).to_string()),
+        },
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{LazyLock, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use git2::{
+    Commit, ErrorCode, Index, IndexEntry, IndexTime, Oid, Repository, Signature, StatusOptions,
+    Tree, build::CheckoutBuilder,
+};
+
+use super::history::{self, ChangedFile};
+use crate::Error;
+
+/// Snapshots are refs below this prefix, named after the time they were taken in milliseconds
+const REF_PREFIX: &str = "refs/gittasks/backups/";
+
+/// Mode of files that are not in the index yet
+const FILE_MODE: u32 = 0o100644;
+
+/// How many snapshots are kept, older ones are deleted when a new one is taken
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retention {
+    pub max_count: usize,
+    pub max_age: Duration,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_count: 50,
+            max_age: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+static RETENTION: LazyLock<Mutex<Retention>> = LazyLock::new(|| Mutex::new(Retention::default()));
+
+/// Fails without any snapshot to keep, the newest one is kept anyway
+pub fn set_snapshot_retention(retention: Retention) -> Result<(), Error> {
+    if retention.max_count == 0 {
+        return Err(Error::invalid_input("at least one snapshot must be kept"));
+    }
+
+    info!(
+        "Snapshot retention: {} snapshots, {}s",
+        retention.max_count,
+        retention.max_age.as_secs()
+    );
+
+    *RETENTION.lock().expect("snapshot retention lock") = retention;
+
+    Ok(())
+}
+
+/// The index and working tree as they were before a destructive operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Time the snapshot was taken, in milliseconds, identifies the snapshot
+    pub id: i64,
+    pub hash: String,
+    /// Operation that was about to run, e.g. `sync`
+    pub reason: String,
+    /// Commit HEAD pointed to, still reachable through the snapshot
+    pub head: Option<String>,
+    /// Changes compared to `head`, including untracked files
+    pub files: Vec<ChangedFile>,
+}
+
+/// Save the index and the working tree, including untracked files, before `reason`
+/// overwrites them. Returns `None` if there was nothing to save.
+///
+/// With `keep_head` a snapshot is taken even without changes, to keep the commits
+/// of HEAD reachable, e.g. before a branch is reset.
+///
+/// The snapshot is a commit on top of HEAD with the working tree, its second
+/// parent holds the index, like `git stash` does.
+pub(super) fn save(repo: &Repository, reason: &str, keep_head: bool) -> Result<Option<Oid>, Error> {
+    save_keeping(repo, reason, keep_head, None)
+}
+
+/// [`save`], without pruning the snapshot `keep`
+fn save_keeping(
+    repo: &Repository,
+    reason: &str,
+    keep_head: bool,
+    keep: Option<Oid>,
+) -> Result<Option<Oid>, Error> {
+    let head = repo.head().and_then(|head| head.peel_to_commit()).ok();
+    let head_tree = head.as_ref().map(Commit::tree_id);
+
+    let index_tree = index_tree(repo, head_tree)?;
+    let worktree_tree = worktree_tree(repo, index_tree)?;
+
+    let unchanged = match head_tree {
+        Some(head_tree) => index_tree == Some(head_tree) && worktree_tree == head_tree,
+        None => repo.find_tree(worktree_tree)?.is_empty(),
+    };
+    if unchanged && !keep_head {
+        return Ok(None);
+    }
+
+    let index_tree = match index_tree {
+        Some(index_tree) => repo.find_tree(index_tree)?,
+        None => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+    };
+
+    record(
+        repo,
+        reason,
+        head.as_ref(),
+        &index_tree,
+        worktree_tree,
+        keep,
+    )
+    .map(Some)
+}
+
+/// Keep the commits of a branch that is not checked out reachable, e.g. before it
+/// is moved. The snapshot has no changes on top of `commit`.
+pub(super) fn save_commit(repo: &Repository, reason: &str, commit: &Commit) -> Result<Oid, Error> {
+    let tree = commit.tree()?;
+
+    record(repo, reason, Some(commit), &tree, tree.id(), None)
+}
+
+fn record(
+    repo: &Repository,
+    reason: &str,
+    head: Option<&Commit>,
+    index_tree: &Tree,
+    worktree_tree: Oid,
+    keep: Option<Oid>,
+) -> Result<Oid, Error> {
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("GitTasks", "gittasks@localhost"))?;
+
+    let parents: Vec<&Commit> = head.into_iter().collect();
+    let index_commit = repo.commit(
+        None,
+        &signature,
+        &signature,
+        &format!("index before {reason}"),
+        index_tree,
+        &parents,
+    )?;
+    let index_commit = repo.find_commit(index_commit)?;
+
+    let mut parents = parents;
+    parents.push(&index_commit);
+    let snapshot = repo.commit(
+        None,
+        &signature,
+        &signature,
+        reason,
+        &repo.find_tree(worktree_tree)?,
+        &parents,
+    )?;
+
+    let mut id = now_ms();
+    loop {
+        match repo.reference(&format!("{REF_PREFIX}{id}"), snapshot, false, reason) {
+            Ok(_) => break,
+            // Two snapshots within the same millisecond
+            Err(e) if e.code() == ErrorCode::Exists => id += 1,
+            Err(e) => return Err(Error::git2(e, "snapshot reference")),
+        }
+    }
+
+    info!("Saved snapshot {id} before {reason}");
+
+    prune(repo, id, keep);
+
+    Ok(snapshot)
+}
+
+/// Snapshots, newest first
+pub fn list(repo: &Repository) -> Result<Vec<Snapshot>, Error> {
+    let mut snapshots = Vec::new();
+
+    for (id, commit) in snapshot_commits(repo)? {
+        let head = commit.parent(0).ok();
+        let head_tree = match &head {
+            Some(head) => Some(head.tree()?),
+            None => None,
+        };
+        let diff = repo.diff_tree_to_tree(head_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+        snapshots.push(Snapshot {
+            id,
+            hash: commit.id().to_string(),
+            reason: commit.message().unwrap_or("").to_string(),
+            head: head.map(|head| head.id().to_string()),
+            files: history::diff_files(diff)?,
+        });
+    }
+
+    Ok(snapshots)
+}
+
+/// The changes saved in snapshot `id` as a unified diff
+pub fn show(repo: &Repository, id: i64) -> Result<String, Error> {
+    let commit = find(repo, id)?;
+    let head_tree = match commit.parent(0) {
+        Ok(head) => Some(head.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(head_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+    history::patch_text(&diff)
+}
+
+/// Write the files changed in snapshot `id` back to the working tree, as uncommitted
+/// changes on top of the current HEAD. Files deleted in the snapshot are left alone.
+///
+/// The current state is saved in a new snapshot first.
+pub fn restore(repo: &Repository, id: i64) -> Result<(), Error> {
+    let count = restore_commit(repo, &find(repo, id)?)?;
+
+    info!("Restored {count} files from snapshot {id}");
+
+    Ok(())
+}
+
+/// [`restore`] the snapshot `commit`, returns how many files were written
+pub(super) fn restore_commit(repo: &Repository, commit: &Commit) -> Result<usize, Error> {
+    let head_tree = match commit.parent(0) {
+        Ok(head) => Some(head.tree()?),
+        Err(_) => None,
+    };
+    let tree = commit.tree()?;
+    let diff = repo.diff_tree_to_tree(head_tree.as_ref(), Some(&tree), None)?;
+
+    let paths: Vec<&Path> = diff
+        .deltas()
+        .filter(|delta| delta.status() != git2::Delta::Deleted)
+        .filter_map(|delta| delta.new_file().path())
+        .collect();
+
+    if paths.is_empty() {
+        return Ok(0);
+    }
+
+    // The snapshot may be old enough to be pruned by the one taken now
+    save_keeping(repo, "restore", false, Some(commit.id()))?;
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force().update_index(false);
+    for path in &paths {
+        checkout.path(path);
+    }
+
+    repo.checkout_tree(tree.as_object(), Some(&mut checkout))
+        .map_err(|e| Error::git2(e, "checkout snapshot"))?;
+
+    Ok(paths.len())
+}
+
+pub fn delete(repo: &Repository, id: i64) -> Result<(), Error> {
+    find(repo, id)?;
+
+    repo.find_reference(&format!("{REF_PREFIX}{id}"))?
+        .delete()
+        .map_err(|e| Error::git2(e, "delete snapshot"))
+}
+
+/// Delete the snapshots beyond the [`Retention`], `now` is the snapshot just taken.
+/// It is kept, as well as the newest one and `keep`.
+fn prune(repo: &Repository, now: i64, keep: Option<Oid>) {
+    let retention = RETENTION.lock().expect("snapshot retention lock").clone();
+    let max_age = retention.max_age.as_millis() as i64;
+
+    let snapshots = match snapshot_commits(repo) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            warn!("Failed to list snapshots: {e}");
+            return;
+        }
+    };
+
+    for (position, (id, commit)) in snapshots.into_iter().enumerate() {
+        let kept = position == 0 || id == now || Some(commit.id()) == keep;
+        if kept || (position < retention.max_count && now - id <= max_age) {
+            continue;
+        }
+
+        let res = repo
+            .find_reference(&format!("{REF_PREFIX}{id}"))
+            .and_then(|mut reference| reference.delete());
+        match res {
+            Ok(()) => debug!("Pruned snapshot {id}"),
+            Err(e) => warn!("Failed to prune snapshot {id}: {e}"),
+        }
+    }
+}
+
+/// Snapshot commits by id, newest first
+fn snapshot_commits(repo: &Repository) -> Result<Vec<(i64, Commit<'_>)>, Error> {
+    let mut snapshots = Vec::new();
+
+    for reference in repo.references_glob(&format!("{REF_PREFIX}*"))? {
+        let reference = reference?;
+        let Some(id) = reference
+            .name()
+            .and_then(|name| name.strip_prefix(REF_PREFIX))
+            .and_then(|id| id.parse().ok())
+        else {
+            continue;
+        };
+
+        snapshots.push((id, reference.peel_to_commit()?));
+    }
+
+    snapshots.sort_by_key(|(id, _)| std::cmp::Reverse(*id));
+
+    Ok(snapshots)
+}
+
+fn find(repo: &Repository, id: i64) -> Result<Commit<'_>, Error> {
+    match repo.find_reference(&format!("{REF_PREFIX}{id}")) {
+        Ok(reference) => Ok(reference.peel_to_commit()?),
+        Err(e) if e.code() == ErrorCode::NotFound => {
+            Err(Error::invalid_input(&format!("No snapshot {id}")))
+        }
+        Err(e) => Err(Error::git2(e, "find snapshot")),
+    }
+}
+
+/// Tree of the index, HEAD's tree while it has conflicts
+fn index_tree(repo: &Repository, head_tree: Option<Oid>) -> Result<Option<Oid>, Error> {
+    let mut index = repo.index()?;
+    // The cached index misses changes made by other git clients
+    index.read(false)?;
+
+    if index.has_conflicts() {
+        return Ok(head_tree);
+    }
+
+    Ok(Some(index.write_tree()?))
+}
+
+/// Tree of the working tree: the index with every changed and untracked file
+/// replaced by its content on disk
+fn worktree_tree(repo: &Repository, index_tree: Option<Oid>) -> Result<Oid, Error> {
+    let Some(workdir) = repo.workdir() else {
+        return Err(Error::repo_state("Cannot snapshot a bare repository"));
+    };
+
+    let mut index = Index::new()?;
+    if let Some(index_tree) = index_tree {
+        index.read_tree(&repo.find_tree(index_tree)?)?;
+    }
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let status = entry.status();
+        let changed = status.is_wt_new()
+            || status.is_wt_modified()
+            || status.is_wt_deleted()
+            || status.is_wt_typechange()
+            || status.is_conflicted();
+        let Some(path) = entry.path() else {
+            continue;
+        };
+        if !changed {
+            continue;
+        }
+
+        let full_path = workdir.join(path);
+        match fs::symlink_metadata(&full_path) {
+            Ok(metadata) if metadata.is_file() => {
+                let mode = index
+                    .get_path(Path::new(path), 0)
+                    .map_or(FILE_MODE, |e| e.mode);
+                index.add(&IndexEntry {
+                    ctime: IndexTime::new(0, 0),
+                    mtime: IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode,
+                    uid: 0,
+                    gid: 0,
+                    file_size: metadata.len() as u32,
+                    id: repo.blob_path(&full_path)?,
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.as_bytes().to_vec(),
+                })?;
+            }
+            // Deleted, or something that is not a regular file
+            _ => {
+                let _ = index.remove_path(Path::new(path));
+            }
+        }
+    }
+
+    Ok(index.write_tree_to(repo)?)
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
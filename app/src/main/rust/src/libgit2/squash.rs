@@ -0,0 +1,197 @@
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use git2::{Commit, ErrorCode, Oid, Repository};
+
+use super::{REMOTE, signing, snapshot};
+use crate::Error;
+
+/// Squashing before push is disabled while `None`
+static WINDOW: LazyLock<Mutex<Option<Duration>>> = LazyLock::new(|| Mutex::new(None));
+
+/// Collapse local commits before each push, see [`squash`], `None` to disable it
+pub fn set_squash_window(window: Option<Duration>) {
+    match window {
+        Some(window) => info!("Squash before push within {}s", window.as_secs()),
+        None => info!("Squash before push disabled"),
+    }
+
+    *WINDOW.lock().expect("squash window lock") = window;
+}
+
+/// Squash with the configured window, if enabled. `fetch` updates the remote-tracking
+/// branch first, so that nothing is squashed when the push would be rejected.
+pub(super) fn before_push(
+    repo: &Repository,
+    fetch: impl FnOnce() -> Result<(), Error>,
+) -> Result<usize, Error> {
+    let Some(window) = *WINDOW.lock().expect("squash window lock") else {
+        return Ok(0);
+    };
+
+    fetch()?;
+    squash(repo, window)
+}
+
+/// Collapse runs of consecutive unpushed commits of the same author into one commit,
+/// a run spans at most `window` from its first commit. Returns how many commits
+/// were removed.
+///
+/// Only the commits after the remote-tracking branch and after the last merge are
+/// rewritten, the old ones stay reachable through a snapshot. Nothing is squashed
+/// when the branch diverged from the remote-tracking branch, the push would be
+/// rejected and a sync merges them first.
+pub(super) fn squash(repo: &Repository, window: Duration) -> Result<usize, Error> {
+    let head = repo.head().map_err(|e| Error::git2(e, "head"))?;
+    let (Some(branch), Some(ref_name)) = (head.shorthand(), head.name()) else {
+        return Ok(0);
+    };
+    if !head.is_branch() {
+        return Ok(0);
+    }
+
+    let upstream = match repo.find_reference(&format!("refs/remotes/{REMOTE}/{branch}")) {
+        Ok(upstream) => upstream
+            .peel_to_commit()
+            .map_err(|e| Error::git2(e, "upstream"))?
+            .id(),
+        // Nothing tells which commits the remote already has
+        Err(e) if e.code() == ErrorCode::NotFound => return Ok(0),
+        Err(e) => return Err(Error::git2(e, "upstream")),
+    };
+
+    let head_oid = head.target().expect("branches point to a commit");
+    let fast_forward = head_oid == upstream
+        || repo
+            .graph_descendant_of(head_oid, upstream)
+            .map_err(|e| Error::git2(e, "graph_descendant_of"))?;
+    if !fast_forward {
+        info!("{branch} diverged from {REMOTE}, not squashing before push");
+        return Ok(0);
+    }
+
+    let mut walk = repo.revwalk().map_err(|e| Error::git2(e, "revwalk"))?;
+    walk.push_head().map_err(|e| Error::git2(e, "revwalk"))?;
+    walk.hide(upstream).map_err(|e| Error::git2(e, "revwalk"))?;
+    let unpushed: HashSet<Oid> = walk
+        .collect::<Result<_, _>>()
+        .map_err(|e| Error::git2(e, "revwalk"))?;
+
+    let mut commits = Vec::new();
+    let mut commit = head.peel_to_commit().map_err(|e| Error::git2(e, "head"))?;
+    while unpushed.contains(&commit.id()) && commit.parent_count() == 1 {
+        let parent = commit.parent(0)?;
+        commits.push(commit);
+        commit = parent;
+    }
+    commits.reverse();
+
+    let runs = runs(&commits, window);
+    if runs.len() == commits.len() {
+        return Ok(0);
+    }
+
+    snapshot::save(repo, "squash", true)?;
+
+    let mut parent = commit;
+    let mut rewriting = false;
+    for run in &runs {
+        // Nothing changes before the first run that is squashed
+        if !rewriting && run.len() == 1 {
+            parent = run[0].clone();
+            continue;
+        }
+        rewriting = true;
+
+        let last = run.last().expect("runs are not empty");
+        let oid = signing::write(
+            repo,
+            &last.author(),
+            &last.committer(),
+            &message(run),
+            &last.tree()?,
+            &[&parent],
+        )
+        .map_err(|e| Error::git2(e, "squash commit"))?;
+        parent = repo.find_commit(oid)?;
+    }
+
+    let removed = commits.len() - runs.len();
+    repo.reference_matching(
+        ref_name,
+        parent.id(),
+        true,
+        head_oid,
+        &format!("squash: {} commits into {}", commits.len(), runs.len()),
+    )
+    .map_err(|e| Error::git2(e, "update branch"))?;
+
+    info!("Squashed {removed} commits of {branch} before push");
+
+    Ok(removed)
+}
+
+/// Split `commits`, oldest first, into the runs that become one commit
+fn runs<'r>(commits: &[Commit<'r>], window: Duration) -> Vec<Vec<Commit<'r>>> {
+    let mut runs: Vec<Vec<Commit>> = Vec::new();
+
+    for commit in commits {
+        let author = commit.author();
+        let joins = runs
+            .last()
+            .and_then(|run| run.first())
+            .is_some_and(|first| {
+                let first_author = first.author();
+                first_author.name_bytes() == author.name_bytes()
+                    && first_author.email_bytes() == author.email_bytes()
+                    && author.when().seconds() - first_author.when().seconds()
+                        <= window.as_secs() as i64
+            });
+
+        match runs.last_mut() {
+            Some(run) if joins => run.push(commit.clone()),
+            _ => runs.push(vec![commit.clone()]),
+        }
+    }
+
+    runs
+}
+
+/// Message of a squashed run: a shared summary is kept once with the other lines
+/// of all messages, different messages are joined by blank lines
+fn message(run: &[Commit]) -> String {
+    let messages: Vec<&str> = run
+        .iter()
+        .map(|commit| commit.message().unwrap_or("").trim())
+        .collect();
+
+    let summary = messages[0].lines().next().unwrap_or("");
+    if messages
+        .iter()
+        .all(|message| message.lines().next().unwrap_or("") == summary)
+    {
+        let mut body: Vec<&str> = Vec::new();
+        for line in messages.iter().flat_map(|message| message.lines().skip(1)) {
+            if !line.trim().is_empty() && !body.contains(&line) {
+                body.push(line);
+            }
+        }
+
+        if body.is_empty() {
+            return summary.to_string();
+        }
+        return format!("{summary}\n\n{}", body.join("\n"));
+    }
+
+    let mut distinct: Vec<&str> = Vec::new();
+    for message in messages {
+        if !distinct.contains(&message) {
+            distinct.push(message);
+        }
+    }
+
+    distinct.join("\n\n")
+}
@@ -0,0 +1,144 @@
+use git2::{Commit, Diff, Repository, StashApplyOptions, Status, build::CheckoutBuilder};
+
+use super::history::{self, ChangedFile};
+use crate::Error;
+
+/// Uncommitted changes put aside by `git stash` or by `sync`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashEntry {
+    /// Position in the stash list, 0 is the newest
+    pub index: usize,
+    pub hash: String,
+    pub message: String,
+    /// Time the changes were stashed, in milliseconds
+    pub time: i64,
+    pub files: Vec<ChangedFile>,
+}
+
+/// Stashes of the repository, newest first
+pub fn list(repo: &Repository) -> Result<Vec<StashEntry>, Error> {
+    let reflog = repo.reflog("refs/stash")?;
+
+    let mut stashes = Vec::new();
+
+    for (index, entry) in reflog.iter().enumerate() {
+        let commit = repo.find_commit(entry.id_new())?;
+
+        stashes.push(StashEntry {
+            index,
+            hash: commit.id().to_string(),
+            message: entry
+                .message()
+                .or_else(|| commit.summary())
+                .unwrap_or("")
+                .to_string(),
+            time: commit.time().seconds() * 1000,
+            files: history::diff_files(stash_diff(repo, &commit)?)?,
+        });
+    }
+
+    Ok(stashes)
+}
+
+/// The changes of the stash at `index` as a unified diff
+pub fn show(repo: &Repository, index: usize) -> Result<String, Error> {
+    let commit = find(repo, index)?;
+
+    history::patch_text(&stash_diff(repo, &commit)?)
+}
+
+/// Apply the stash at `index` to the working tree and return the paths left with
+/// conflict markers. With `pop` the stash is dropped, unless there were conflicts.
+///
+/// Fails without touching anything when an uncommitted change would be overwritten.
+pub fn apply(repo: &mut Repository, index: usize, pop: bool) -> Result<Vec<String>, Error> {
+    // libgit2 only notices this after restoring the untracked files
+    let dirty = dirty_paths(repo, &find(repo, index)?)?;
+    if !dirty.is_empty() {
+        return Err(Error::conflict(&format!(
+            "Uncommitted changes to {} would be overwritten by the stash",
+            dirty.join(", ")
+        )));
+    }
+
+    // Not `allow_conflicts`, that would skip files with uncommitted changes
+    let mut checkout = CheckoutBuilder::new();
+    checkout.conflict_style_merge(true);
+
+    let mut options = StashApplyOptions::new();
+    options.checkout_options(checkout);
+
+    repo.stash_apply(index, Some(&mut options))
+        .map_err(|e| Error::git2(e, "stash_apply"))?;
+
+    let mut conflicts = Vec::new();
+    for conflict in repo.index()?.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            conflicts.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+
+    if !conflicts.is_empty() {
+        warn!("Stash {index} applied with conflicts in {conflicts:?}");
+    } else if pop {
+        repo.stash_drop(index)
+            .map_err(|e| Error::git2(e, "stash_drop"))?;
+    }
+
+    Ok(conflicts)
+}
+
+pub fn drop(repo: &mut Repository, index: usize) -> Result<(), Error> {
+    find(repo, index)?;
+
+    repo.stash_drop(index)
+        .map_err(|e| Error::git2(e, "stash_drop"))
+}
+
+fn find(repo: &Repository, index: usize) -> Result<Commit<'_>, Error> {
+    let reflog = repo.reflog("refs/stash")?;
+
+    let Some(entry) = reflog.get(index) else {
+        return Err(Error::invalid_input(&format!("No stash at index {index}")));
+    };
+
+    Ok(repo.find_commit(entry.id_new())?)
+}
+
+/// Paths changed by `stash` that have uncommitted changes or exist untracked
+fn dirty_paths(repo: &Repository, stash: &Commit) -> Result<Vec<String>, Error> {
+    let diff = stash_diff(repo, stash)?;
+
+    let mut dirty = Vec::new();
+
+    for delta in diff.deltas() {
+        let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) else {
+            continue;
+        };
+
+        // Files that are neither tracked nor in the working tree are not found
+        if let Ok(status) = repo.status_file(path)
+            && status != Status::CURRENT
+        {
+            dirty.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(dirty)
+}
+
+/// Working tree changes of `stash` compared to the commit it was made on,
+/// including the untracked files it holds
+fn stash_diff<'r>(repo: &'r Repository, stash: &Commit) -> Result<Diff<'r>, Error> {
+    let base = stash.parent(0)?.tree()?;
+    let mut diff = repo.diff_tree_to_tree(Some(&base), Some(&stash.tree()?), None)?;
+
+    // Third parent, only there when the stash includes untracked files
+    if let Ok(untracked) = stash.parent(2) {
+        let untracked = repo.diff_tree_to_tree(None, Some(&untracked.tree()?), None)?;
+        diff.merge(&untracked)?;
+    }
+
+    Ok(diff)
+}
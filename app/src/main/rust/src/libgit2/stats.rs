@@ -0,0 +1,131 @@
+use std::{collections::HashMap, path::Path};
+
+use git2::Repository;
+
+use crate::{
+    Error,
+    mime_types::{ExtensionType, extension_type},
+};
+
+/// Number of entries in [`RepoStats::largest_blobs`]
+const LARGEST_BLOBS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contributor {
+    /// Name used in the most recent commit of this email
+    pub name: String,
+    pub email: String,
+    pub commits: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobSize {
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// What the repository holds; file counts and sizes are taken from HEAD
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepoStats {
+    pub commit_count: usize,
+    /// Sorted by number of commits, most active first
+    pub contributors: Vec<Contributor>,
+    pub markdown_notes: usize,
+    pub text_notes: usize,
+    /// Files that are not notes, e.g. images and attachments
+    pub other_files: usize,
+    pub total_blob_bytes: u64,
+    /// Largest files first
+    pub largest_blobs: Vec<BlobSize>,
+    /// Size of `.git` on disk
+    pub git_dir_bytes: u64,
+    pub loose_objects: usize,
+    pub packed_objects: usize,
+    /// Size of the files below the asset directory
+    pub asset_bytes: u64,
+}
+
+pub fn repo_stats(repo: &Repository, asset_dir: &str) -> Result<RepoStats, Error> {
+    let (loose_objects, packed_objects) = super::maintenance::object_counts(repo);
+
+    let mut stats = RepoStats {
+        git_dir_bytes: super::maintenance::dir_size(repo.path()),
+        loose_objects,
+        packed_objects,
+        ..Default::default()
+    };
+
+    // Empty repositories have nothing else to count
+    if repo.head().is_err() {
+        return Ok(stats);
+    }
+
+    count_commits(repo, &mut stats)?;
+
+    let odb = repo.odb()?;
+    let asset_prefix = format!("{}/", asset_dir.trim_matches('/'));
+    let mut blobs = Vec::new();
+
+    super::walk_head_blobs(repo, |path, entry| {
+        let extension = Path::new(&path)
+            .extension()
+            .and_then(|extension| extension.to_str());
+
+        match extension.and_then(extension_type) {
+            Some(ExtensionType::Markdown) => stats.markdown_notes += 1,
+            Some(ExtensionType::Text) => stats.text_notes += 1,
+            None => stats.other_files += 1,
+        }
+
+        let bytes = match odb.read_header(entry.id()) {
+            Ok((size, _)) => size as u64,
+            Err(e) => {
+                warn!("read_header {path}: {e}");
+                0
+            }
+        };
+
+        stats.total_blob_bytes += bytes;
+        if !asset_dir.is_empty() && path.starts_with(&asset_prefix) {
+            stats.asset_bytes += bytes;
+        }
+        blobs.push(BlobSize { path, bytes });
+    })?;
+
+    blobs.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    blobs.truncate(LARGEST_BLOBS);
+    stats.largest_blobs = blobs;
+
+    Ok(stats)
+}
+
+fn count_commits(repo: &Repository, stats: &mut RepoStats) -> Result<(), Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(git2::Sort::TIME)?;
+
+    let mut contributors: HashMap<String, Contributor> = HashMap::new();
+
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let author = commit.author();
+        let email = author.email().unwrap_or_default().to_lowercase();
+
+        stats.commit_count += 1;
+        contributors
+            .entry(email.clone())
+            // Newest commit comes first, so the name is the current one
+            .or_insert_with(|| Contributor {
+                name: author.name().unwrap_or_default().to_string(),
+                email,
+                commits: 0,
+            })
+            .commits += 1;
+    }
+
+    let mut contributors: Vec<Contributor> = contributors.into_values().collect();
+    contributors.sort_by(|a, b| b.commits.cmp(&a.commits).then_with(|| a.name.cmp(&b.name)));
+    stats.contributors = contributors;
+
+    Ok(())
+}
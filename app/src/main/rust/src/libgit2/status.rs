@@ -0,0 +1,115 @@
+use git2::{Repository, Status, StatusEntry, StatusOptions};
+
+use crate::Error;
+
+// important: the values are read by the Kotlin side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    New = 0,
+    Modified = 1,
+    Deleted = 2,
+    Renamed = 3,
+    Conflicted = 4,
+    Ignored = 5,
+}
+
+/// A path that differs between HEAD, the index and the working tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathStatus {
+    pub path: String,
+    /// Path before a rename, in the index or the working tree
+    pub old_path: Option<String>,
+    /// Staged change compared to HEAD, `None` if nothing is staged
+    pub index: Option<FileState>,
+    /// Change in the working tree compared to the index, `None` if there is none
+    pub worktree: Option<FileState>,
+}
+
+/// Changed paths, below `folder` if given, sorted by path
+pub fn status(
+    repo: &Repository,
+    folder: Option<&str>,
+    include_ignored: bool,
+) -> Result<Vec<PathStatus>, Error> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(include_ignored)
+        .recurse_ignored_dirs(include_ignored)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true)
+        .sort_case_sensitively(true);
+
+    if let Some(folder) = folder {
+        options.pathspec(folder.trim_end_matches('/'));
+    }
+
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .map_err(|e| Error::git2(e, "statuses"))?;
+
+    Ok(statuses
+        .iter()
+        .filter_map(|entry| path_status(&entry))
+        .collect())
+}
+
+fn path_status(entry: &StatusEntry) -> Option<PathStatus> {
+    let status = entry.status();
+
+    let (index, worktree) = if status.is_conflicted() {
+        (Some(FileState::Conflicted), Some(FileState::Conflicted))
+    } else if status.is_ignored() {
+        (None, Some(FileState::Ignored))
+    } else {
+        (index_state(status), worktree_state(status))
+    };
+
+    // Renames are reported on the new path, the deltas hold both
+    let rename = entry
+        .index_to_workdir()
+        .filter(|_| status.is_wt_renamed())
+        .or_else(|| entry.head_to_index().filter(|_| status.is_index_renamed()));
+    let path = |file: git2::DiffFile| file.path().map(|path| path.to_string_lossy().to_string());
+
+    let (path, old_path) = match rename {
+        Some(delta) => (path(delta.new_file())?, path(delta.old_file())),
+        None => (entry.path()?.to_string(), None),
+    };
+
+    Some(PathStatus {
+        path,
+        old_path,
+        index,
+        worktree,
+    })
+}
+
+fn index_state(status: Status) -> Option<FileState> {
+    if status.is_index_new() {
+        Some(FileState::New)
+    } else if status.is_index_renamed() {
+        Some(FileState::Renamed)
+    } else if status.is_index_deleted() {
+        Some(FileState::Deleted)
+    } else if status.is_index_modified() || status.is_index_typechange() {
+        Some(FileState::Modified)
+    } else {
+        None
+    }
+}
+
+fn worktree_state(status: Status) -> Option<FileState> {
+    if status.is_wt_new() {
+        Some(FileState::New)
+    } else if status.is_wt_renamed() {
+        Some(FileState::Renamed)
+    } else if status.is_wt_deleted() {
+        Some(FileState::Deleted)
+    } else if status.is_wt_modified() || status.is_wt_typechange() {
+        Some(FileState::Modified)
+    } else {
+        None
+    }
+}
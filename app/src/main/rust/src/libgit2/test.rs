@@ -3,6 +3,7 @@ use serial_test::serial;
 use std::fs;
 use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 
 fn run_git_command(dir: &Path, args: &[&str]) {
     let output = Command::new("git")
@@ -173,19 +174,20 @@ fn test_commit_all_integration(local_repo: &Path) {
 
 fn test_push_integration() {
     // Test push function
-    push(None).expect("Failed to push using our push function");
+    push(None, &mut NoProgress).expect("Failed to push using our push function");
     println!("✓ test_push function successful");
 }
 
 fn test_pull_integration() {
     // Test pull function
-    pull(None, "Test User", "test@example.com").expect("Failed to pull using our pull function");
+    pull(None, "Test User", "test@example.com", &mut NoProgress)
+        .expect("Failed to pull using our pull function");
     println!("✓ test_pull function successful");
 }
 
 fn test_sync_integration() {
     // Test sync function
-    sync(None).expect("Failed to sync using our sync function");
+    sync(None, &mut NoProgress).expect("Failed to sync using our sync function");
     println!("✓ test_sync function successful");
 }
 
@@ -297,7 +299,7 @@ fn test_get_timestamps() {
     std::env::set_current_dir(&original_dir).expect("Failed to restore directory");
 
     // Now test get_timestamps
-    let timestamps = get_timestamps().expect("Failed to get timestamps");
+    let timestamps = get_timestamps(true).expect("Failed to get timestamps");
 
     println!("Timestamps result:");
     for (file, timestamp) in &timestamps {
@@ -419,7 +421,7 @@ fn test_get_timestamps_sorted() {
     std::env::set_current_dir(&original_dir).expect("Failed to restore directory");
 
     // Get timestamps
-    let timestamps = get_timestamps().expect("Failed to get timestamps");
+    let timestamps = get_timestamps(true).expect("Failed to get timestamps");
 
     // Convert to vec and sort by timestamp (oldest first)
     let mut sorted_timestamps: Vec<_> = timestamps.into_iter().collect();
@@ -553,7 +555,8 @@ fn test_pull_remote_changes() {
     fs::remove_dir_all(&remote_temp_dir).expect("Failed to clean up remote temp dir");
 
     // Now use our pull function to get the remote changes
-    pull(None, "Test User", "test@example.com").expect("Failed to pull remote changes");
+    pull(None, "Test User", "test@example.com", &mut NoProgress)
+        .expect("Failed to pull remote changes");
 
     // Verify we got the remote commit
     let after_pull_commit = last_commit().expect("Failed to get commit after pull");
@@ -670,7 +673,7 @@ fn test_push_conflict_resolution() {
         .expect("Failed to commit locally");
 
     // Try to push - this should fail due to conflict
-    let push_result = push(None);
+    let push_result = push(None, &mut NoProgress);
     match push_result {
         Ok(_) => {
             println!("Push succeeded - no conflict detected");
@@ -930,7 +933,7 @@ fn test_sync_function() {
 
     // Now local has "Local modified content" and remote has "Remote modified content"
     // Sync should fetch remote and reset local to match remote
-    sync(None).expect("Failed to sync");
+    sync(None, &mut NoProgress).expect("Failed to sync");
 
     // Verify local now matches remote
     let content = fs::read_to_string("test.txt").expect("Failed to read file");
@@ -950,6 +953,11 @@ fn test_sync_function() {
         "Should have different commit after sync"
     );
 
+    // The dropped local commit is kept by a snapshot
+    let snapshots = list_snapshots().expect("Failed to list snapshots");
+    assert_eq!(snapshots[0].reason, "sync");
+    assert_eq!(snapshots[0].head, Some(local_commit));
+
     // Clean up
     std::env::set_current_dir(original_dir).expect("Failed to restore original directory");
     fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
@@ -1048,7 +1056,7 @@ fn test_pull_conflict_resolution() {
     fs::remove_dir_all(&remote_temp_dir).expect("Failed to clean up remote temp dir");
 
     // Now try to pull - this should create a merge conflict
-    let pull_result = pull(None, "Local User", "local@example.com");
+    let pull_result = pull(None, "Local User", "local@example.com", &mut NoProgress);
     match pull_result {
         Ok(_) => {
             println!("Pull succeeded - automatic merge performed");
@@ -1228,7 +1236,7 @@ fn test_pull_conflict_resolution_from_different_cwd() {
 
     // Now try to pull - this should trigger conflict resolution
     // The bug was that path resolution used relative paths assuming CWD was repo root
-    let pull_result = pull(None, "Local User", "local@example.com");
+    let pull_result = pull(None, "Local User", "local@example.com", &mut NoProgress);
 
     match pull_result {
         Ok(_) => {
@@ -1345,3 +1353,2458 @@ fn test_clone_empty_repository() {
 
     println!("✓ test_clone_empty_repository completed successfully");
 }
+
+#[test]
+#[serial]
+fn test_commit_all_signed() {
+    init_lib("/tmp".to_string());
+
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let test_dir = current_dir.join("test_repos_signing");
+
+    if test_dir.exists() {
+        fs::remove_dir_all(&test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(&test_dir).expect("Failed to create test dir");
+
+    create_repo(&test_dir.to_string_lossy()).expect("Failed to create repository");
+
+    // Sign with a key generated the same way the app does
+    let keys = crate::key_gen::gen_keys().expect("Failed to generate keys");
+    set_signing_key(Some(&keys.private)).expect("Failed to set signing key");
+    assert!(is_signing_enabled());
+
+    fs::write(test_dir.join("note.md"), "# Signed note\n").expect("Failed to write note");
+    commit_all("Test User", "test@example.com", "Add signed note")
+        .expect("Failed to commit signed note");
+
+    set_signing_key(None).expect("Failed to disable signing");
+    assert!(!is_signing_enabled());
+
+    // HEAD must have moved to the signed commit
+    let output = Command::new("git")
+        .args(["log", "--format=%s", "-1"])
+        .current_dir(&test_dir)
+        .output()
+        .expect("Failed to run git log");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "Add signed note"
+    );
+
+    // Let git verify the signature against the generated public key
+    let allowed_signers = test_dir.join(".git").join("allowed_signers");
+    fs::write(
+        &allowed_signers,
+        format!("test@example.com {}\n", keys.public),
+    )
+    .expect("Failed to write allowed signers");
+
+    run_git_command(
+        &test_dir,
+        &[
+            "-c",
+            "gpg.format=ssh",
+            "-c",
+            &format!("gpg.ssh.allowedSignersFile={}", allowed_signers.display()),
+            "verify-commit",
+            "HEAD",
+        ],
+    );
+
+    // Unsigned commits still work once signing is disabled
+    fs::write(test_dir.join("other.md"), "# Unsigned note\n").expect("Failed to write note");
+    commit_all("Test User", "test@example.com", "Add unsigned note")
+        .expect("Failed to commit unsigned note");
+
+    let output = Command::new("git")
+        .args(["cat-file", "commit", "HEAD"])
+        .current_dir(&test_dir)
+        .output()
+        .expect("Failed to run git cat-file");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("gpgsig"));
+
+    fs::remove_dir_all(&test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_commit_all_signed completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_git_log_signature_status() {
+    init_lib("/tmp".to_string());
+
+    let current_dir = std::env::current_dir().expect("Failed to get current directory");
+    let test_dir = current_dir.join("test_repos_verify_signatures");
+
+    if test_dir.exists() {
+        fs::remove_dir_all(&test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(&test_dir).expect("Failed to create test dir");
+
+    create_repo(&test_dir.to_string_lossy()).expect("Failed to create repository");
+
+    let trusted = crate::key_gen::gen_keys().expect("Failed to generate keys");
+    let untrusted = crate::key_gen::gen_keys().expect("Failed to generate keys");
+
+    // Only the first device is trusted by the team
+    fs::write(
+        test_dir.join(".git/allowed_signers"),
+        format!(
+            "# team devices\nphone@example.com namespaces=\"git\" {}\n",
+            trusted.public
+        ),
+    )
+    .expect("Failed to write allowed signers");
+    run_git_command(
+        &test_dir,
+        &["config", signing::ALLOWED_SIGNERS_CONFIG, "allowed_signers"],
+    );
+    // Anyone who can push could add their key to a file in the working tree
+    let untrusted_signers = test_dir.join(".allowed_signers");
+    fs::write(
+        &untrusted_signers,
+        format!("intruder@example.com {}\n", untrusted.public),
+    )
+    .expect("Failed to write allowed signers");
+
+    set_signing_key(Some(&trusted.private)).expect("Failed to set signing key");
+    commit_all("Test User", "test@example.com", "Trusted commit").expect("Failed to commit");
+
+    set_signing_key(Some(&untrusted.private)).expect("Failed to set signing key");
+    fs::write(test_dir.join("note.md"), "# Note\n").expect("Failed to write note");
+    commit_all("Test User", "test@example.com", "Untrusted commit").expect("Failed to commit");
+
+    set_signing_key(None).expect("Failed to disable signing");
+    fs::write(test_dir.join("note.md"), "# Note\nmore\n").expect("Failed to write note");
+    commit_all("Test User", "test@example.com", "Unsigned commit").expect("Failed to commit");
+
+    // Craft a commit whose signature covers different content, and an OpenPGP one
+    {
+        let repo = git2::Repository::open(&test_dir).expect("Failed to open repository");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let tree = head.tree().unwrap();
+        let sig = git2::Signature::now("Test User", "test@example.com").unwrap();
+        let key = ssh_key::PrivateKey::from_openssh(&*trusted.private).unwrap();
+
+        let buffer = repo
+            .commit_create_buffer(&sig, &sig, "Tampered commit", &tree, &[&head])
+            .unwrap();
+        let forged = key
+            .sign("git", ssh_key::HashAlg::Sha512, b"something else")
+            .unwrap()
+            .to_pem(ssh_key::LineEnding::LF)
+            .unwrap();
+        let tampered = repo
+            .commit_signed(buffer.as_str().unwrap(), &forged, None)
+            .unwrap();
+        let tampered = repo.find_commit(tampered).unwrap();
+
+        let buffer = repo
+            .commit_create_buffer(&sig, &sig, "PGP commit", &tree, &[&tampered])
+            .unwrap();
+        let pgp = repo
+            .commit_signed(
+                buffer.as_str().unwrap(),
+                "-----BEGIN PGP SIGNATURE-----\n\niQEz\n-----END PGP SIGNATURE-----",
+                None,
+            )
+            .unwrap();
+        repo.head()
+            .unwrap()
+            .set_target(pgp, "test: add crafted commits")
+            .unwrap();
+    }
+
+    let log = get_git_log(10).expect("Failed to get git log");
+    let status_of = |message: &str| {
+        let entry = log
+            .iter()
+            .find(|e| e.message.trim() == message)
+            .unwrap_or_else(|| panic!("{message} not found in log"));
+        (entry.signature_status, entry.signer.clone())
+    };
+
+    assert_eq!(
+        status_of("Trusted commit"),
+        (
+            SignatureStatus::Valid,
+            Some("phone@example.com".to_string())
+        )
+    );
+
+    let (status, signer) = status_of("Untrusted commit");
+    assert_eq!(status, SignatureStatus::UntrustedKey);
+    assert!(signer.unwrap().starts_with("SHA256:"));
+
+    assert_eq!(
+        status_of("Unsigned commit"),
+        (SignatureStatus::Unsigned, None)
+    );
+    assert_eq!(
+        status_of("Tampered commit"),
+        (SignatureStatus::Invalid, None)
+    );
+    assert_eq!(
+        status_of("PGP commit"),
+        (SignatureStatus::Unsupported, None)
+    );
+    assert_eq!(
+        status_of("Welcome to GitTasks!"),
+        (SignatureStatus::Unsigned, None)
+    );
+
+    // The file set by the app takes precedence
+    set_allowed_signers_file(Some(&untrusted_signers.to_string_lossy()));
+    let log = get_git_log(10).expect("Failed to get git log");
+    set_allowed_signers_file(None);
+    let signer_of = |message: &str| {
+        log.iter()
+            .find(|e| e.message.trim() == message)
+            .and_then(|e| e.signer.clone())
+    };
+    assert_eq!(
+        signer_of("Untrusted commit").as_deref(),
+        Some("intruder@example.com")
+    );
+    assert!(signer_of("Trusted commit").unwrap().starts_with("SHA256:"));
+
+    fs::remove_dir_all(&test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_git_log_signature_status completed successfully");
+}
+
+/// Records the reported phases and cancels once `cancel_at` is reached
+struct CancelAt {
+    cancel_at: Phase,
+    phases: Vec<Phase>,
+}
+
+impl Progress for CancelAt {
+    fn report(&mut self, phase: Phase, _percent: i32, _bytes: usize) -> bool {
+        self.phases.push(phase);
+        phase != self.cancel_at
+    }
+}
+
+#[test]
+#[serial]
+fn test_pull_cancelled() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_pull_cancelled");
+    let remote_repo = test_dir.join("remote");
+    let local_repo = test_dir.join("local");
+    let other_repo = test_dir.join("other");
+
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+
+    fs::create_dir_all(&remote_repo).expect("Failed to create remote repo dir");
+    run_git_command(&remote_repo, &["init", "--bare"]);
+    let remote_url = format!("file://{}", remote_repo.canonicalize().unwrap().display());
+
+    // Seed the remote and clone it twice
+    for repo in [&local_repo, &other_repo] {
+        run_git_command(
+            test_dir,
+            &[
+                "clone",
+                &remote_url,
+                repo.file_name().unwrap().to_str().unwrap(),
+            ],
+        );
+        run_git_command(repo, &["config", "user.name", "Test User"]);
+        run_git_command(repo, &["config", "user.email", "test@example.com"]);
+        if repo == &local_repo {
+            fs::write(repo.join("README.md"), "# Notes\n").expect("Failed to write README");
+            run_git_command(repo, &["add", "README.md"]);
+            run_git_command(repo, &["commit", "-m", "Initial commit"]);
+            run_git_command(repo, &["push", "origin", "HEAD"]);
+        }
+    }
+    run_git_command(&other_repo, &["pull", "origin", "HEAD"]);
+    fs::write(other_repo.join("README.md"), "# Notes\nRemote change\n")
+        .expect("Failed to write remote change");
+    run_git_command(&other_repo, &["commit", "-am", "Remote change"]);
+    run_git_command(&other_repo, &["push", "origin", "HEAD"]);
+
+    open_repo(&local_repo.to_string_lossy()).expect("Failed to open repository");
+    let before = last_commit().expect("Failed to get commit before pull");
+
+    // Cancelling before the merge leaves the branch and working tree untouched
+    let mut progress = CancelAt {
+        cancel_at: Phase::Merging,
+        phases: Vec::new(),
+    };
+    let err = pull(None, "Test User", "test@example.com", &mut progress)
+        .expect_err("Pull should be cancelled");
+    assert_eq!(err.category(), crate::ErrorCategory::Cancelled);
+    assert_eq!(progress.phases.last(), Some(&Phase::Merging));
+    assert_eq!(last_commit().as_deref(), Some(before.as_str()));
+    assert_eq!(
+        fs::read_to_string(local_repo.join("README.md")).unwrap(),
+        "# Notes\n"
+    );
+
+    // Without cancelling the same pull goes through and reports the checkout
+    let mut progress = CancelAt {
+        cancel_at: Phase::Uploading,
+        phases: Vec::new(),
+    };
+    pull(None, "Test User", "test@example.com", &mut progress).expect("Failed to pull");
+    assert!(progress.phases.contains(&Phase::Checkout));
+    assert_ne!(last_commit().as_deref(), Some(before.as_str()));
+
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_pull_cancelled completed successfully");
+}
+
+fn git_output(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("Failed to run git command");
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[test]
+#[serial]
+fn test_verify_and_repair_repo() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_integrity");
+    let remote_repo = test_dir.join("remote");
+    let local_repo = test_dir.join("local");
+
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+
+    fs::create_dir_all(&remote_repo).expect("Failed to create remote repo dir");
+    run_git_command(&remote_repo, &["init", "--bare"]);
+    let remote_url = format!("file://{}", remote_repo.canonicalize().unwrap().display());
+
+    run_git_command(test_dir, &["clone", &remote_url, "local"]);
+    run_git_command(&local_repo, &["config", "user.name", "Test User"]);
+    run_git_command(&local_repo, &["config", "user.email", "test@example.com"]);
+    for (file, content) in [("README.md", "# Notes\n"), ("todo.md", "- [ ] first\n")] {
+        fs::write(local_repo.join(file), content).expect("Failed to write file");
+        run_git_command(&local_repo, &["add", file]);
+        run_git_command(&local_repo, &["commit", "-m", file]);
+    }
+    run_git_command(&local_repo, &["push", "origin", "HEAD"]);
+
+    let branch = git_output(&local_repo, &["branch", "--show-current"]);
+    let head = git_output(&local_repo, &["rev-parse", "HEAD"]);
+    let blob = git_output(&local_repo, &["rev-parse", "HEAD:todo.md"]);
+
+    open_repo(&local_repo.to_string_lossy()).expect("Failed to open repository");
+    assert_eq!(verify_repo().expect("Failed to verify"), vec![]);
+
+    // Uncommitted work must survive the repair
+    fs::write(local_repo.join("todo.md"), "- [x] first\n").expect("Failed to write change");
+
+    // Simulate a process killed while writing a loose object, a ref and the index
+    let git_dir = local_repo.join(".git");
+    let object = git_dir.join("objects").join(&blob[..2]).join(&blob[2..]);
+    let mut permissions = fs::metadata(&object).unwrap().permissions();
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    fs::set_permissions(&object, permissions).unwrap();
+    fs::write(&object, b"x").expect("Failed to corrupt object");
+    fs::write(git_dir.join("refs/heads").join(&branch), "").expect("Failed to corrupt ref");
+    fs::write(git_dir.join("index"), b"DIRC garbage").expect("Failed to corrupt index");
+
+    let issues = verify_repo().expect("Failed to verify");
+    let kinds: Vec<IssueKind> = issues.iter().map(|issue| issue.kind).collect();
+    assert!(kinds.contains(&IssueKind::UnreadableObject), "{issues:?}");
+    assert!(kinds.contains(&IssueKind::BrokenRef), "{issues:?}");
+    assert!(kinds.contains(&IssueKind::CorruptIndex), "{issues:?}");
+
+    assert_eq!(repair_repo(None).expect("Failed to repair"), vec![]);
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), head);
+    run_git_command(&local_repo, &["fsck", "--full"]);
+    assert_eq!(
+        fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+        "- [x] first\n"
+    );
+    assert_eq!(
+        git_output(&local_repo, &["status", "--porcelain"]),
+        "M todo.md"
+    );
+    assert!(!git_dir.join("gittasks/repair").exists());
+
+    // HEAD left unparseable
+    fs::write(git_dir.join("HEAD"), "garbage").expect("Failed to corrupt HEAD");
+    let issues = verify_repo().expect("Failed to verify");
+    assert_eq!(issues.len(), 1, "{issues:?}");
+    assert_eq!(issues[0].kind, IssueKind::DanglingHead);
+
+    assert_eq!(repair_repo(None).expect("Failed to repair"), vec![]);
+    assert_eq!(
+        git_output(&local_repo, &["symbolic-ref", "HEAD"]),
+        format!("refs/heads/{branch}")
+    );
+
+    // A local branch that can't be restored is kept and reported, a broken
+    // remote-tracking branch is removed since the next fetch recreates it
+    for name in ["refs/heads/draft", "refs/remotes/origin/gone"] {
+        fs::write(git_dir.join(name), "").expect("Failed to corrupt ref");
+    }
+    let remaining = repair_repo(None).expect("Failed to repair");
+    assert_eq!(
+        remaining
+            .iter()
+            .map(|issue| (issue.kind, issue.target.as_str()))
+            .collect::<Vec<_>>(),
+        [(IssueKind::BrokenRef, "refs/heads/draft")]
+    );
+    assert!(git_dir.join("refs/heads/draft").exists());
+    assert!(!git_dir.join("refs/remotes/origin/gone").exists());
+    fs::remove_file(git_dir.join("refs/heads/draft")).unwrap();
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_verify_and_repair_repo completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_run_maintenance() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_maintenance");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    for i in 0..5 {
+        fs::write(test_dir.join("note.md"), format!("# Note\n{i}\n")).expect("Failed to write");
+        run_git_command(test_dir, &["add", "note.md"]);
+        run_git_command(test_dir, &["commit", "-m", &format!("commit {i}")]);
+    }
+
+    // Unreachable blobs, one of them older than the grace period
+    fs::write(test_dir.join("young.txt"), "young").unwrap();
+    fs::write(test_dir.join("old.txt"), "old").unwrap();
+    let young = git_output(test_dir, &["hash-object", "-w", "young.txt"]);
+    let old = git_output(test_dir, &["hash-object", "-w", "old.txt"]);
+    fs::remove_file(test_dir.join("young.txt")).unwrap();
+    fs::remove_file(test_dir.join("old.txt")).unwrap();
+    let old_path = test_dir
+        .join(".git/objects")
+        .join(&old[..2])
+        .join(&old[2..]);
+    fs::File::open(&old_path)
+        .and_then(|f| {
+            f.set_modified(std::time::SystemTime::now() - Duration::from_secs(30 * 24 * 3600))
+        })
+        .expect("Failed to backdate object");
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    let grace_period = Duration::from_secs(14 * 24 * 3600);
+
+    // Below the threshold nothing happens
+    let report = run_maintenance(grace_period, 1000).expect("Failed to run maintenance");
+    assert_eq!(report, MaintenanceReport::default());
+
+    let report = run_maintenance(grace_period, 0).expect("Failed to run maintenance");
+    assert_eq!(report.objects_packed, 15);
+    assert_eq!(report.objects_pruned, 1);
+    assert!(report.bytes_reclaimed() > 0, "{report:?}");
+    run_git_command(test_dir, &["fsck", "--full"]);
+    run_git_command(test_dir, &["cat-file", "-e", &young]);
+    assert!(!old_path.exists());
+    let counts = git_output(test_dir, &["count-objects", "-v"]);
+    assert!(counts.contains("count: 1\n"), "{counts}");
+    assert!(counts.contains("packs: 1\n"), "{counts}");
+
+    // A branch that got deleted right after its objects were packed ...
+    run_git_command(test_dir, &["checkout", "-q", "-b", "draft"]);
+    fs::write(test_dir.join("draft.md"), "draft").unwrap();
+    run_git_command(test_dir, &["add", "draft.md"]);
+    run_git_command(test_dir, &["commit", "-m", "draft"]);
+    let draft = git_output(test_dir, &["rev-parse", "HEAD"]);
+    run_maintenance(grace_period, 0).expect("Failed to run maintenance");
+    run_git_command(test_dir, &["checkout", "-q", "-"]);
+    run_git_command(test_dir, &["branch", "-D", "draft"]);
+    run_git_command(test_dir, &["reflog", "expire", "--expire=now", "--all"]);
+
+    // ... keeps its objects in a cruft pack during the grace period
+    let report = run_maintenance(grace_period, 0).expect("Failed to run maintenance");
+    assert_eq!(report.objects_pruned, 0);
+    run_git_command(test_dir, &["cat-file", "-e", &draft]);
+    let counts = git_output(test_dir, &["count-objects", "-v"]);
+    assert!(counts.contains("packs: 2\n"), "{counts}");
+
+    // Written by another git client, it would list the packs that get removed
+    run_git_command(test_dir, &["multi-pack-index", "write"]);
+    let midx = test_dir.join(".git/objects/pack/multi-pack-index");
+    assert!(midx.exists());
+
+    let report = run_maintenance(Duration::ZERO, 0).expect("Failed to run maintenance");
+    assert!(!midx.exists());
+    assert!(report.objects_pruned >= 3, "{report:?}");
+    assert!(
+        !Command::new("git")
+            .current_dir(test_dir)
+            .args(["cat-file", "-e", &draft])
+            .status()
+            .unwrap()
+            .success()
+    );
+    run_git_command(test_dir, &["fsck", "--full"]);
+    let counts = git_output(test_dir, &["count-objects", "-v"]);
+    assert!(counts.contains("count: 0\n"), "{counts}");
+    assert!(counts.contains("packs: 1\n"), "{counts}");
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_run_maintenance completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_repo_stats() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_stats");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("assets")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    let files: [(&str, &[u8], &str); 4] = [
+        ("todo.md", b"- [ ] one\n", "alice@example.com"),
+        ("notes/ideas.md", b"# Ideas\n", "bob@example.com"),
+        ("log.txt", b"started\n", "Alice@Example.com"),
+        ("assets/photo.png", &[0u8; 4096], "alice@example.com"),
+    ];
+    for (path, content, email) in files {
+        let file = test_dir.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, content).expect("Failed to write file");
+        run_git_command(test_dir, &["add", path]);
+        run_git_command(
+            test_dir,
+            &[
+                "-c",
+                "user.name=Someone",
+                "-c",
+                &format!("user.email={email}"),
+                "commit",
+                "-m",
+                path,
+            ],
+        );
+    }
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    let stats = repo_stats("assets").expect("Failed to get stats");
+
+    assert_eq!(stats.commit_count, 4);
+    assert_eq!(stats.contributors.len(), 2);
+    assert_eq!(stats.contributors[0].email, "alice@example.com");
+    assert_eq!(stats.contributors[0].commits, 3);
+    assert_eq!((stats.markdown_notes, stats.text_notes), (2, 1));
+    assert_eq!(stats.other_files, 1);
+    assert_eq!(stats.total_blob_bytes, 10 + 8 + 8 + 4096);
+    assert_eq!(stats.asset_bytes, 4096);
+    assert_eq!(stats.largest_blobs[0].path, "assets/photo.png");
+    assert_eq!(stats.largest_blobs.len(), 4);
+    assert_eq!((stats.loose_objects, stats.packed_objects), (14, 0));
+    assert!(stats.git_dir_bytes > 0);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_repo_stats completed successfully");
+}
+
+/// Commit `content` to `path` with a fixed author and committer time
+fn commit_file_at(dir: &Path, path: &str, content: &str, seconds: i64) {
+    fs::write(dir.join(path), content).expect("Failed to write file");
+    run_git_command(dir, &["add", path]);
+    commit_at(dir, path, seconds, "Test User");
+}
+
+/// Commit the staged changes as `author` at a fixed time
+fn commit_at(dir: &Path, message: &str, seconds: i64, author: &str) {
+    let date = format!("@{seconds} +0000");
+    let output = Command::new("git")
+        .current_dir(dir)
+        .env("GIT_AUTHOR_DATE", &date)
+        .env("GIT_COMMITTER_DATE", &date)
+        .args(["-c", &format!("user.name={author}")])
+        .args(["-c", "user.email=test@example.com"])
+        .args(["commit", "-m", message])
+        .output()
+        .expect("Failed to run git command");
+    assert!(output.status.success(), "git commit failed: {output:?}");
+}
+
+#[test]
+#[serial]
+fn test_get_timestamps_cache() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_timestamps_cache");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    commit_file_at(test_dir, "a.md", "a", 1000);
+    commit_file_at(test_dir, "b.md", "b", 2000);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    // The first call walks the whole history and persists the result
+    let timestamps = get_timestamps(true).expect("Failed to get timestamps");
+    assert_eq!(timestamps["a.md"], 1_000_000);
+    assert_eq!(timestamps["b.md"], 2_000_000);
+
+    let cache_file = test_dir.join(".git/gittasks/timestamps");
+    let cache = fs::read_to_string(&cache_file).expect("Cache was not written");
+    let head = git_output(test_dir, &["rev-parse", "HEAD"]);
+    assert!(cache.contains(&format!("head {head}\n")));
+
+    // Mark the cached value so that it shows whether the cache was used
+    fs::write(
+        &cache_file,
+        cache.replace("\t1000000\tTest User", "\t42\tTest User"),
+    )
+    .unwrap();
+
+    // Only the new commit is looked at
+    commit_file_at(test_dir, "b.md", "b2", 3000);
+    let timestamps = get_timestamps(true).expect("Failed to get timestamps");
+    assert_eq!(timestamps["a.md"], 42);
+    assert_eq!(timestamps["b.md"], 3_000_000);
+
+    // Rewritten history invalidates the cache
+    run_git_command(test_dir, &["reset", "--hard", "HEAD~1"]);
+    commit_file_at(test_dir, "c.md", "c", 4000);
+    let timestamps = get_timestamps(true).expect("Failed to get timestamps");
+    assert_eq!(timestamps.len(), 3);
+    assert_eq!(timestamps["a.md"], 1_000_000);
+    assert_eq!(timestamps["b.md"], 2_000_000);
+    assert_eq!(timestamps["c.md"], 4_000_000);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_get_timestamps_cache completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_get_file_history() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_file_history");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    commit_file_at(test_dir, "a.md", "# A\nsome content\n", 1000);
+    commit_file_at(test_dir, "b.md", "# B\n", 2000);
+
+    fs::write(test_dir.join("a.md"), "# A\nsome content\nmore\n").unwrap();
+    run_git_command(test_dir, &["add", "a.md"]);
+    commit_at(test_dir, "edit a", 3000, "Alice");
+
+    // Moving a note keeps its creation time
+    fs::create_dir_all(test_dir.join("notes")).unwrap();
+    run_git_command(test_dir, &["mv", "a.md", "notes/a.md"]);
+    commit_at(test_dir, "move a", 4000, "Bob");
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    let files = get_file_history(true).expect("Failed to get file history");
+
+    assert_eq!(files.len(), 2);
+    let a = &files["notes/a.md"];
+    assert_eq!((a.created, a.modified), (1_000_000, 4_000_000));
+    assert_eq!(a.last_author, "Bob");
+    let b = &files["b.md"];
+    assert_eq!((b.created, b.modified), (2_000_000, 2_000_000));
+    assert_eq!(b.last_author, "Test User");
+    assert_eq!(b.last_author_email, "test@example.com");
+
+    // Incremental updates see the same history
+    fs::write(test_dir.join("b.md"), "# B\nchanged\n").unwrap();
+    run_git_command(test_dir, &["add", "b.md"]);
+    commit_at(test_dir, "edit b", 5000, "Carol");
+
+    let b = &get_file_history(true).expect("Failed to get file history")["b.md"];
+    assert_eq!((b.created, b.modified), (2_000_000, 5_000_000));
+    assert_eq!(b.last_author, "Carol");
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_get_file_history completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_get_timestamps_renames() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_timestamps_renames");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    commit_file_at(test_dir, "notes/x.md", "# X\nfirst note\n", 1000);
+    commit_file_at(test_dir, "notes/y.md", "# Y\nsecond note\n", 2000);
+
+    // Move the whole folder, and edit one note while at it
+    run_git_command(test_dir, &["mv", "notes", "archive"]);
+    fs::write(test_dir.join("archive/y.md"), "# Y\nsecond note\nedited\n").unwrap();
+    run_git_command(test_dir, &["add", "-A"]);
+    commit_at(test_dir, "archive notes", 3000, "Test User");
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    // The move only counts as a modification if asked to
+    let timestamps = get_timestamps(false).expect("Failed to get timestamps");
+    assert_eq!(timestamps.len(), 2);
+    assert_eq!(timestamps["archive/x.md"], 1_000_000);
+    assert_eq!(timestamps["archive/y.md"], 3_000_000);
+
+    let files = get_file_history(true).expect("Failed to get file history");
+    assert_eq!(files["archive/x.md"].modified, 3_000_000);
+    assert_eq!(files["archive/x.md"].created, 1_000_000);
+    assert_eq!(files["archive/y.md"].created, 2_000_000);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_get_timestamps_renames completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_query_git_log() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_query_log");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    let commit = |path: &str, message: &str, seconds: i64, author: &str| {
+        fs::write(test_dir.join(path), message).expect("Failed to write file");
+        run_git_command(test_dir, &["add", path]);
+        commit_at(test_dir, message, seconds, author);
+    };
+
+    run_git_command(test_dir, &["init", "-b", "master"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit("a.md", "add a", 1000, "Alice");
+    commit("notes/one.md", "add note one", 2000, "Bob");
+    commit("notes/two.md", "add note two", 3000, "Alice");
+    run_git_command(test_dir, &["checkout", "-b", "side"]);
+    commit("b.md", "add b", 4000, "Bob");
+    run_git_command(test_dir, &["checkout", "master"]);
+    commit("c.md", "add c", 5000, "Alice");
+    run_git_command(test_dir, &["merge", "--no-ff", "--no-commit", "side"]);
+    commit_at(test_dir, "merge side", 6000, "Alice");
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let messages = |query: LogQuery| -> Vec<String> {
+        query_git_log(&query)
+            .expect("Failed to query log")
+            .into_iter()
+            .map(|entry| entry.message.trim().to_string())
+            .collect()
+    };
+
+    assert_eq!(get_git_log(20).unwrap().len(), 6);
+    assert_eq!(
+        messages(LogQuery {
+            no_merges: true,
+            ..Default::default()
+        }),
+        ["add c", "add b", "add note two", "add note one", "add a"]
+    );
+    assert_eq!(
+        messages(LogQuery {
+            author: Some("BOB".to_string()),
+            ..Default::default()
+        }),
+        ["add b", "add note one"]
+    );
+    assert_eq!(
+        messages(LogQuery {
+            since: Some(2_000_000),
+            until: Some(4_000_000),
+            ..Default::default()
+        }),
+        ["add b", "add note two", "add note one"]
+    );
+    assert_eq!(
+        messages(LogQuery {
+            path: Some("notes/".to_string()),
+            ..Default::default()
+        }),
+        ["add note two", "add note one"]
+    );
+    assert_eq!(
+        messages(LogQuery {
+            message: Some("Note".to_string()),
+            author: Some("alice".to_string()),
+            ..Default::default()
+        }),
+        ["add note two"]
+    );
+
+    // Pages continue after a cursor or an offset
+    let first_page = query_git_log(&LogQuery {
+        limit: 2,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(first_page[1].message.trim(), "add c");
+    assert_eq!(
+        messages(LogQuery {
+            limit: 2,
+            after: Some(first_page[1].hash.clone()),
+            ..Default::default()
+        }),
+        ["add b", "add note two"]
+    );
+    assert_eq!(
+        messages(LogQuery {
+            limit: 2,
+            offset: 2,
+            ..Default::default()
+        }),
+        ["add b", "add note two"]
+    );
+
+    assert!(
+        query_git_log(&LogQuery {
+            after: Some("not a hash".to_string()),
+            ..Default::default()
+        })
+        .is_err()
+    );
+    let error = query_git_log(&LogQuery {
+        after: Some("0".repeat(40)),
+        ..Default::default()
+    })
+    .expect_err("Should reject an unknown cursor");
+    assert_eq!(error.category(), crate::ErrorCategory::InvalidInput);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_query_git_log completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_commit_details() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_commit_details");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    fs::write(test_dir.join("a.md"), "one\ntwo\n").unwrap();
+    fs::write(test_dir.join("b.md"), "# B\nsome note\n").unwrap();
+    fs::write(test_dir.join("old.txt"), "obsolete\n").unwrap();
+    run_git_command(test_dir, &["add", "-A"]);
+    commit_at(test_dir, "initial", 1000, "Alice");
+
+    fs::write(test_dir.join("a.md"), "one\n2\nthree\n").unwrap();
+    fs::write(test_dir.join("c.md"), "new\n").unwrap();
+    fs::remove_file(test_dir.join("old.txt")).unwrap();
+    run_git_command(test_dir, &["mv", "b.md", "notes/b.md"]);
+    run_git_command(test_dir, &["add", "-A"]);
+
+    let output = Command::new("git")
+        .current_dir(test_dir)
+        .env("GIT_AUTHOR_DATE", "@2000 +0200")
+        .env("GIT_COMMITTER_DATE", "@3000 -0130")
+        .args(["-c", "user.name=Bob", "-c", "user.email=bob@example.com"])
+        .args(["commit", "-m", "sync\n\nfrom phone"])
+        .output()
+        .expect("Failed to run git command");
+    assert!(output.status.success(), "git commit failed: {output:?}");
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let head = git_output(test_dir, &["rev-parse", "HEAD"]);
+    let parent = git_output(test_dir, &["rev-parse", "HEAD~1"]);
+
+    let details = get_commit_details(&head).expect("Failed to get commit details");
+    assert_eq!(details.hash, head);
+    assert_eq!(details.parents, std::slice::from_ref(&parent));
+    assert_eq!(details.message, "sync\n\nfrom phone\n");
+    assert_eq!(
+        details.author,
+        Person {
+            name: "Bob".to_string(),
+            email: "bob@example.com".to_string(),
+            time: 2_000_000,
+            offset_minutes: 120,
+        }
+    );
+    assert_eq!(
+        (details.committer.time, details.committer.offset_minutes),
+        (3_000_000, -90)
+    );
+
+    let mut files = details.files.clone();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let summary: Vec<_> = files
+        .iter()
+        .map(|file| {
+            (
+                file.path.as_str(),
+                file.old_path.as_deref(),
+                file.status,
+                file.additions,
+                file.deletions,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("a.md", None, FileStatus::Modified, 2, 1),
+            ("c.md", None, FileStatus::Added, 1, 0),
+            ("notes/b.md", Some("b.md"), FileStatus::Renamed, 0, 0),
+            ("old.txt", None, FileStatus::Deleted, 0, 1),
+        ]
+    );
+
+    // The initial commit adds everything
+    let details = get_commit_details(&parent).expect("Failed to get commit details");
+    assert!(details.parents.is_empty());
+    assert_eq!(details.files.len(), 3);
+    assert!(
+        details
+            .files
+            .iter()
+            .all(|file| file.status == FileStatus::Added)
+    );
+
+    // The log only lists files when asked to
+    assert!(get_git_log(1).unwrap()[0].files.is_empty());
+    let log = query_git_log(&LogQuery {
+        limit: 1,
+        include_files: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(log[0].files.len(), 4);
+
+    assert!(get_commit_details("nope").is_err());
+    assert!(get_commit_details(&"0".repeat(40)).is_err());
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_commit_details completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_git_graph() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_git_graph");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init", "-b", "master"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, "a.md", "a", 1000);
+    commit_file_at(test_dir, "b.md", "b", 2000);
+    run_git_command(test_dir, &["checkout", "-b", "side"]);
+    commit_file_at(test_dir, "side.md", "side", 3000);
+    run_git_command(test_dir, &["checkout", "master"]);
+    commit_file_at(test_dir, "c.md", "c", 4000);
+    run_git_command(test_dir, &["merge", "--no-ff", "--no-commit", "side"]);
+    commit_at(test_dir, "merge side", 5000, "Test User");
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let rows = get_git_graph(0, 20).expect("Failed to get graph");
+    let hash = |rev: &str| git_output(test_dir, &["rev-parse", rev]);
+
+    // Same order as the log
+    let log: Vec<String> = get_git_log(20)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.hash)
+        .collect();
+    let graph: Vec<String> = rows.iter().map(|row| row.hash.clone()).collect();
+    assert_eq!(graph, log);
+
+    let edge = |from, to| GraphEdge { from, to };
+    let layout: Vec<_> = rows
+        .iter()
+        .map(|row| (row.column, row.edges.clone()))
+        .collect();
+    assert_eq!(
+        layout,
+        [
+            // merge: forks into master and side
+            (0, vec![edge(0, 0), edge(0, 1)]),
+            // c
+            (0, vec![edge(0, 0), edge(1, 1)]),
+            // side joins master at b
+            (1, vec![edge(0, 0), edge(1, 0)]),
+            // b
+            (0, vec![edge(0, 0)]),
+            // a
+            (0, vec![]),
+        ]
+    );
+    assert_eq!(rows[0].parents, [hash("master~1"), hash("side")]);
+    assert_eq!(rows[4].parents, Vec::<String>::new());
+
+    // Pages are laid out as part of the whole graph
+    let page = get_git_graph(1, 2).expect("Failed to get graph");
+    assert_eq!(page, rows[1..3]);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_git_graph completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_search_index() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_search");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 1000);
+    commit_file_at(
+        test_dir,
+        "notes/tea.md",
+        "Green tea\nwith a little milk",
+        2000,
+    );
+    commit_file_at(test_dir, "photo.png", "milk", 3000);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let paths = |query: &str, folder: Option<&str>| -> Vec<String> {
+        search(query, folder, 10)
+            .expect("Failed to search")
+            .into_iter()
+            .map(|result| result.path)
+            .collect()
+    };
+
+    // The index is built on the first search, unsupported files are skipped
+    let results = search("milk", None, 10).expect("Failed to search");
+    assert_eq!(results.len(), 2);
+    let todo = results.iter().find(|r| r.path == "todo.md").unwrap();
+    assert_eq!(todo.line, 3);
+    assert_eq!(todo.snippet, "# Todo - [ ] buy milk");
+    assert_eq!(paths("milk", Some("notes")), ["notes/tea.md"]);
+    assert_eq!(paths("\"green tea\"", None), ["notes/tea.md"]);
+    assert!(test_dir.join(".git/gittasks/search").exists());
+
+    // Commits update the index
+    fs::write(test_dir.join("notes/coffee.md"), "Espresso").unwrap();
+    commit_all("Test User", "test@example.com", "Add coffee").expect("Failed to commit");
+    assert_eq!(paths("espresso", None), ["notes/coffee.md"]);
+
+    // The saved index is used after reopening
+    close();
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    assert_eq!(paths("espres*", None), ["notes/coffee.md"]);
+
+    // Changes in the working tree are picked up by an explicit update
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] buy bread\n").unwrap();
+    fs::remove_file(test_dir.join("notes/tea.md")).unwrap();
+    assert_eq!(update_search_index().unwrap(), 2);
+    assert!(paths("milk", None).is_empty());
+    assert_eq!(paths("bread", None), ["todo.md"]);
+
+    // ... as are commits made outside, e.g. by a pull
+    fs::write(test_dir.join("notes/coffee.md"), "Cappuccino").unwrap();
+    run_git_command(test_dir, &["add", "notes/coffee.md"]);
+    commit_at(test_dir, "Change coffee", 4000, "Test User");
+    update_search_index().unwrap();
+    assert!(paths("espresso", None).is_empty());
+    assert_eq!(paths("cappuccino", None), ["notes/coffee.md"]);
+    // Still dirty, so still up to date
+    assert_eq!(paths("bread", None), ["todo.md"]);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_search_index completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_search_history() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_search_history");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] Call Alice\n", 1000);
+    commit_file_at(test_dir, "notes/ideas.md", "Alice likes tea\n", 2000);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [x] Call Alice\n", 3000);
+    commit_file_at(test_dir, "todo.md", "# Todo\n", 4000);
+    run_git_command(test_dir, &["rm", "notes/ideas.md"]);
+    commit_at(test_dir, "Remove ideas", 5000, "Test User");
+
+    let hash_at = |n: usize| git_output(test_dir, &["rev-parse", &format!("HEAD~{n}")]);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let query = HistoryQuery {
+        pattern: "alice".to_string(),
+        ignore_case: true,
+        path: None,
+        since: None,
+        until: None,
+        limit: 10,
+    };
+
+    // Newest first, deleted files included
+    let matches = search_history(&query).expect("Failed to search history");
+    let found: Vec<(&str, usize, &str)> = matches
+        .iter()
+        .map(|m| (m.path.as_str(), m.line_number, m.line.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("todo.md", 3, "- [x] Call Alice"),
+            ("notes/ideas.md", 1, "Alice likes tea"),
+            ("todo.md", 3, "- [ ] Call Alice"),
+        ]
+    );
+    assert_eq!(matches[0].hash, hash_at(2));
+    assert_eq!(matches[0].time, 3_000_000);
+    assert_eq!(matches[1].hash, hash_at(3));
+
+    // Case, path and date range
+    let search = |query: HistoryQuery| -> Vec<String> {
+        search_history(&query)
+            .expect("Failed to search history")
+            .into_iter()
+            .map(|m| m.line)
+            .collect()
+    };
+    assert!(
+        search(HistoryQuery {
+            ignore_case: false,
+            ..query.clone()
+        })
+        .is_empty()
+    );
+    assert_eq!(
+        search(HistoryQuery {
+            path: Some("notes/".to_string()),
+            ..query.clone()
+        }),
+        ["Alice likes tea"]
+    );
+    assert_eq!(
+        search(HistoryQuery {
+            until: Some(2_000_000),
+            ..query.clone()
+        }),
+        ["Alice likes tea", "- [ ] Call Alice"]
+    );
+    assert_eq!(
+        search(HistoryQuery {
+            since: Some(3_000_000),
+            ..query.clone()
+        }),
+        ["- [x] Call Alice"]
+    );
+    assert_eq!(
+        search(HistoryQuery {
+            limit: 1,
+            ..query.clone()
+        })
+        .len(),
+        1
+    );
+    assert!(search(HistoryQuery { limit: 0, ..query }).is_empty());
+
+    // Matches can be restored from their commit
+    checkout_path(&matches[1].path, Some(&matches[1].hash)).expect("Failed to checkout");
+    assert_eq!(
+        fs::read_to_string(test_dir.join("notes/ideas.md")).unwrap(),
+        "Alice likes tea\n"
+    );
+    checkout_path(&matches[0].path, Some(&matches[0].hash)).expect("Failed to checkout");
+    assert_eq!(
+        fs::read_to_string(test_dir.join("todo.md")).unwrap(),
+        "# Todo\n\n- [x] Call Alice\n"
+    );
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_search_history completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_stash() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_stash");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 1000);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    assert!(list_stashes().expect("Failed to list stashes").is_empty());
+
+    // A stash with a modified and an untracked file
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] buy milk\n").unwrap();
+    fs::write(test_dir.join("idea.md"), "Plant a tree\n").unwrap();
+    run_git_command(test_dir, &["stash", "push", "-u", "-m", "Before trip"]);
+    // ... and an older one below it
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] buy bread\n").unwrap();
+    run_git_command(test_dir, &["stash", "push", "-m", "Bread"]);
+
+    let stashes = list_stashes().expect("Failed to list stashes");
+    assert_eq!(stashes.len(), 2);
+    assert_eq!(stashes[0].index, 0);
+    assert!(stashes[0].message.ends_with("Bread"));
+    assert!(stashes[1].message.ends_with("Before trip"));
+    assert_eq!(
+        stashes[1].hash,
+        git_output(test_dir, &["rev-parse", "stash@{1}"])
+    );
+    let files: Vec<(&str, FileStatus, usize, usize)> = stashes[1]
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.status, f.additions, f.deletions))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            ("idea.md", FileStatus::Added, 1, 0),
+            ("todo.md", FileStatus::Modified, 1, 1)
+        ]
+    );
+
+    let diff = show_stash(1).expect("Failed to show stash");
+    assert!(diff.contains("-- [ ] buy milk\n+- [x] buy milk\n"));
+    assert!(diff.contains("+Plant a tree\n"));
+    assert!(show_stash(2).is_err());
+
+    // Pop applies and drops
+    let conflicts = apply_stash(0, true).expect("Failed to pop stash");
+    assert!(conflicts.is_empty());
+    assert_eq!(
+        fs::read_to_string(test_dir.join("todo.md")).unwrap(),
+        "# Todo\n\n- [ ] buy bread\n"
+    );
+    assert_eq!(list_stashes().unwrap().len(), 1);
+
+    // Uncommitted changes are never overwritten
+    let err = apply_stash(0, true).unwrap_err();
+    assert_eq!(err.category(), crate::ErrorCategory::Conflict);
+    assert!(!test_dir.join("idea.md").exists());
+    assert_eq!(list_stashes().unwrap().len(), 1);
+
+    // Conflicts with committed changes are reported, the stash is kept
+    commit_all("Test User", "test@example.com", "Bread").expect("Failed to commit");
+    let conflicts = apply_stash(0, true).expect("Failed to pop stash");
+    assert_eq!(conflicts, ["todo.md"]);
+    let content = fs::read_to_string(test_dir.join("todo.md")).unwrap();
+    assert!(content.contains("<<<<<<<"));
+    assert!(content.contains("- [x] buy milk"));
+    assert!(content.contains("- [ ] buy bread"));
+    assert_eq!(
+        fs::read_to_string(test_dir.join("idea.md")).unwrap(),
+        "Plant a tree\n"
+    );
+    assert_eq!(list_stashes().unwrap().len(), 1);
+
+    drop_stash(0).expect("Failed to drop stash");
+    assert!(list_stashes().unwrap().is_empty());
+    assert!(drop_stash(0).is_err());
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_stash completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_sync_drops_own_stash() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_sync_stash");
+    let remote_repo = test_dir.join("remote");
+    let local_repo = test_dir.join("local");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(&remote_repo).expect("Failed to create remote repo dir");
+
+    run_git_command(&remote_repo, &["init", "--bare"]);
+    let remote_url = format!("file://{}", remote_repo.canonicalize().unwrap().display());
+
+    let temp_dir = test_dir.join("temp");
+    fs::create_dir_all(&temp_dir).expect("Failed to create temp dir");
+    run_git_command(&temp_dir, &["init"]);
+    commit_file_at(&temp_dir, "todo.md", "# Todo\n", 1000);
+    commit_file_at(&temp_dir, "notes.md", "Notes\n", 1000);
+    run_git_command(&temp_dir, &["remote", "add", "origin", &remote_url]);
+    run_git_command(&temp_dir, &["push", "-u", "origin", "master"]);
+
+    run_git_command(test_dir, &["clone", &remote_url, "local"]);
+    run_git_command(&local_repo, &["config", "user.name", "Local User"]);
+    run_git_command(&local_repo, &["config", "user.email", "local@example.com"]);
+
+    // Change on the remote and an uncommitted change to another note here
+    commit_file_at(&temp_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 2000);
+    run_git_command(&temp_dir, &["push"]);
+
+    open_repo(&local_repo.to_string_lossy()).expect("Failed to open repository");
+    fs::write(local_repo.join("notes.md"), "Notes\nMore notes\n").unwrap();
+
+    sync(None, &mut NoProgress).expect("Failed to sync");
+
+    assert_eq!(
+        fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+        "# Todo\n\n- [ ] buy milk\n"
+    );
+    assert_eq!(
+        fs::read_to_string(local_repo.join("notes.md")).unwrap(),
+        "Notes\nMore notes\n"
+    );
+    assert!(list_stashes().expect("Failed to list stashes").is_empty());
+
+    // A conflicting change is reported, the branch is synced and the stash is kept
+    commit_file_at(&temp_dir, "todo.md", "# Todo\n\n- [x] buy milk\n", 3000);
+    run_git_command(&temp_dir, &["push"]);
+    fs::write(local_repo.join("todo.md"), "# Todo\n\n- [ ] buy oat milk\n").unwrap();
+
+    let error = sync(None, &mut NoProgress).expect_err("Stash should conflict");
+    assert_eq!(error.category(), crate::ErrorCategory::Conflict);
+    assert!(error.to_string().contains("todo.md"));
+    assert_eq!(
+        git_output(&local_repo, &["rev-parse", "HEAD"]),
+        git_output(&temp_dir, &["rev-parse", "HEAD"])
+    );
+    assert_eq!(list_stashes().expect("Failed to list stashes").len(), 1);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_sync_drops_own_stash completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_status() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_status");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, ".gitignore", "*.tmp\n", 1000);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 1000);
+    commit_file_at(test_dir, "old.md", "Old note\n", 1000);
+    commit_file_at(
+        test_dir,
+        "notes/tea.md",
+        "Green tea\nBlack tea\nWhite tea\nOolong\n",
+        1000,
+    );
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    assert!(
+        status(None, false)
+            .expect("Failed to get status")
+            .is_empty()
+    );
+
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] buy milk\n").unwrap();
+    fs::remove_file(test_dir.join("old.md")).unwrap();
+    run_git_command(test_dir, &["mv", "notes/tea.md", "notes/teas.md"]);
+    fs::write(test_dir.join("notes/coffee.md"), "Espresso\n").unwrap();
+    run_git_command(test_dir, &["add", "notes/coffee.md"]);
+    fs::write(test_dir.join("notes/coffee.md"), "Espresso\nLatte\n").unwrap();
+    fs::write(test_dir.join("idea.md"), "Plant a tree\n").unwrap();
+    fs::write(test_dir.join("cache.tmp"), "").unwrap();
+
+    use FileState::*;
+    let states = |folder: Option<&str>, include_ignored: bool| {
+        status(folder, include_ignored)
+            .expect("Failed to get status")
+            .into_iter()
+            .map(|s| (s.path, s.old_path, s.index, s.worktree))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        states(None, false),
+        [
+            ("idea.md".to_string(), None, None, Some(New)),
+            (
+                "notes/coffee.md".to_string(),
+                None,
+                Some(New),
+                Some(Modified)
+            ),
+            (
+                "notes/teas.md".to_string(),
+                Some("notes/tea.md".to_string()),
+                Some(Renamed),
+                None
+            ),
+            ("old.md".to_string(), None, None, Some(Deleted)),
+            ("todo.md".to_string(), None, None, Some(Modified)),
+        ]
+    );
+    assert_eq!(
+        states(Some("notes/"), false)
+            .into_iter()
+            .map(|(path, ..)| path)
+            .collect::<Vec<_>>(),
+        ["notes/coffee.md", "notes/teas.md"]
+    );
+    assert_eq!(
+        states(Some("cache.tmp"), true),
+        [("cache.tmp".to_string(), None, None, Some(Ignored))]
+    );
+    assert!(states(Some("cache.tmp"), false).is_empty());
+
+    // Conflicts of a merge
+    run_git_command(test_dir, &["reset", "--hard"]);
+    run_git_command(test_dir, &["clean", "-fd"]);
+    run_git_command(test_dir, &["checkout", "-b", "other"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy bread\n", 2000);
+    run_git_command(test_dir, &["checkout", "-"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy tea\n", 3000);
+    let merged = Command::new("git")
+        .current_dir(test_dir)
+        .args(["merge", "other"])
+        .output()
+        .expect("Failed to run git command");
+    assert!(!merged.status.success());
+
+    assert_eq!(
+        states(None, false),
+        [(
+            "todo.md".to_string(),
+            None,
+            Some(Conflicted),
+            Some(Conflicted)
+        )]
+    );
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_status completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_snapshots() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_snapshots");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir.join("notes")).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 1000);
+    commit_file_at(test_dir, "notes/tea.md", "Green tea\n", 1000);
+    let head = git_output(test_dir, &["rev-parse", "HEAD"]);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    assert!(
+        list_snapshots()
+            .expect("Failed to list snapshots")
+            .is_empty()
+    );
+
+    // Nothing to save
+    cleanup_repo().expect("Failed to clean up");
+    assert!(list_snapshots().unwrap().is_empty());
+
+    // Staged, unstaged and untracked changes wiped by a cleanup are saved first
+    fs::write(test_dir.join("notes/tea.md"), "Green tea\nBlack tea\n").unwrap();
+    run_git_command(test_dir, &["add", "notes/tea.md"]);
+    fs::write(test_dir.join("notes/tea.md"), "Green tea\nOolong\n").unwrap();
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] buy milk\n").unwrap();
+    fs::write(test_dir.join("idea.md"), "Plant a tree\n").unwrap();
+    cleanup_repo().expect("Failed to clean up");
+    assert_eq!(
+        fs::read_to_string(test_dir.join("todo.md")).unwrap(),
+        "# Todo\n\n- [ ] buy milk\n"
+    );
+
+    let snapshots = list_snapshots().expect("Failed to list snapshots");
+    assert_eq!(snapshots.len(), 1);
+    let snapshot = &snapshots[0];
+    assert_eq!(snapshot.reason, "cleanup");
+    assert_eq!(snapshot.head.as_deref(), Some(head.as_str()));
+    let files: Vec<(&str, FileStatus)> = snapshot
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.status))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            ("idea.md", FileStatus::Added),
+            ("notes/tea.md", FileStatus::Modified),
+            ("todo.md", FileStatus::Modified)
+        ]
+    );
+    assert!(
+        show_snapshot(snapshot.id)
+            .expect("Failed to show snapshot")
+            .contains("-- [ ] buy milk\n+- [x] buy milk\n")
+    );
+    // The index is the second parent
+    assert_eq!(
+        git_output(
+            test_dir,
+            &["show", &format!("{}^2:notes/tea.md", snapshot.hash)]
+        ),
+        "Green tea\nBlack tea"
+    );
+    assert!(show_snapshot(42).is_err());
+
+    // Restoring saves the current state first
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] buy bread\n").unwrap();
+    restore_snapshot(snapshot.id).expect("Failed to restore snapshot");
+    assert_eq!(
+        fs::read_to_string(test_dir.join("todo.md")).unwrap(),
+        "# Todo\n\n- [x] buy milk\n"
+    );
+    assert_eq!(
+        fs::read_to_string(test_dir.join("notes/tea.md")).unwrap(),
+        "Green tea\nOolong\n"
+    );
+    let snapshots = list_snapshots().unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0].reason, "restore");
+    assert!(
+        show_snapshot(snapshots[0].id)
+            .unwrap()
+            .contains("+- [ ] buy bread\n")
+    );
+
+    // Old snapshots and those beyond the limit are pruned
+    run_git_command(
+        test_dir,
+        &["update-ref", "refs/gittasks/backups/1000", &snapshot.hash],
+    );
+    assert_eq!(list_snapshots().unwrap().len(), 3);
+    set_snapshot_retention(Retention {
+        max_count: 2,
+        max_age: Duration::from_secs(3600),
+    })
+    .unwrap();
+    fs::write(test_dir.join("todo.md"), "# Todo\n").unwrap();
+    checkout_path("todo.md", None).expect("Failed to checkout");
+    set_snapshot_retention(Retention::default()).unwrap();
+
+    let snapshots = list_snapshots().unwrap();
+    assert_eq!(
+        snapshots
+            .iter()
+            .map(|s| s.reason.as_str())
+            .collect::<Vec<_>>(),
+        ["checkout", "restore"]
+    );
+
+    delete_snapshot(snapshots[0].id).expect("Failed to delete snapshot");
+    assert_eq!(list_snapshots().unwrap().len(), 1);
+    assert!(delete_snapshot(snapshots[0].id).is_err());
+
+    // The snapshot being restored survives the pruning of the one taken before
+    assert!(
+        set_snapshot_retention(Retention {
+            max_count: 0,
+            ..Default::default()
+        })
+        .is_err()
+    );
+    run_git_command(
+        test_dir,
+        &["update-ref", "refs/gittasks/backups/1000", &snapshot.hash],
+    );
+    set_snapshot_retention(Retention {
+        max_count: 1,
+        max_age: Duration::from_secs(3600),
+    })
+    .unwrap();
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] buy tea\n").unwrap();
+    restore_snapshot(1000).expect("Failed to restore snapshot");
+    set_snapshot_retention(Retention::default()).unwrap();
+
+    let snapshots = list_snapshots().unwrap();
+    assert_eq!(
+        snapshots
+            .iter()
+            .map(|s| (s.reason.as_str(), s.id == 1000))
+            .collect::<Vec<_>>(),
+        [("restore", false), (snapshot.reason.as_str(), true)]
+    );
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_snapshots completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_recovery() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_recovery");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    // Fails on the conflict and leaves the operation in progress
+    let git_conflict = |args: &[&str]| {
+        let output = Command::new("git")
+            .current_dir(test_dir)
+            .env("GIT_EDITOR", "true")
+            .args(args)
+            .output()
+            .expect("Failed to run git command");
+        assert!(!output.status.success(), "git {args:?} should conflict");
+    };
+    let todo = || fs::read_to_string(test_dir.join("todo.md")).unwrap();
+
+    run_git_command(test_dir, &["init"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 1000);
+    run_git_command(test_dir, &["branch", "other"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy tea\n", 2000);
+    run_git_command(test_dir, &["checkout", "other"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy bread\n", 3000);
+    run_git_command(test_dir, &["checkout", "master"]);
+
+    // Opening leaves an interrupted merge and other edits alone
+    git_conflict(&["merge", "other"]);
+    fs::write(test_dir.join("idea.md"), "Plant a tree\n").unwrap();
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+    assert!(todo().contains("<<<<<<<"));
+    assert!(test_dir.join(".git/MERGE_HEAD").exists());
+
+    let state = repo_state().expect("Failed to get state");
+    assert_eq!(state.operation, Operation::Merge);
+    let files: Vec<(&str, Option<FileState>)> = state
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.index))
+        .collect();
+    assert_eq!(
+        files,
+        [("idea.md", None), ("todo.md", Some(FileState::Conflicted))]
+    );
+
+    // Continuing needs the conflict markers gone
+    let err = recover(Recovery::Continue, "Test User", "test@example.com").unwrap_err();
+    assert_eq!(err.category(), crate::ErrorCategory::Conflict);
+
+    fs::write(
+        test_dir.join("todo.md"),
+        "# Todo\n\n- [ ] buy tea and bread\n",
+    )
+    .unwrap();
+    let state =
+        recover(Recovery::Continue, "Test User", "test@example.com").expect("Failed to continue");
+    assert_eq!(state.operation, Operation::None);
+    assert!(state.files.is_empty());
+    let parents = git_output(test_dir, &["rev-list", "--parents", "-n", "1", "HEAD"]);
+    assert_eq!(parents.split(' ').count(), 3);
+    assert_eq!(
+        git_output(test_dir, &["log", "-1", "--format=%s"]),
+        "Merge branch 'other'"
+    );
+    assert!(recover(Recovery::Continue, "Test User", "test@example.com").is_err());
+
+    // Keeping the working tree ends a cherry-pick without touching the files
+    run_git_command(test_dir, &["checkout", "other"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy cheese\n", 4000);
+    run_git_command(test_dir, &["checkout", "master"]);
+    let head = git_output(test_dir, &["rev-parse", "HEAD"]);
+    git_conflict(&["cherry-pick", "other"]);
+    assert_eq!(repo_state().unwrap().operation, Operation::CherryPick);
+
+    let conflicted = todo();
+    let state = recover(Recovery::KeepWorkingTree, "Test User", "test@example.com")
+        .expect("Failed to keep working tree");
+    assert_eq!(state.operation, Operation::None);
+    assert_eq!(
+        state
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.index, f.worktree))
+            .collect::<Vec<_>>(),
+        [("todo.md", None, Some(FileState::Modified))]
+    );
+    assert_eq!(todo(), conflicted);
+    assert_eq!(git_output(test_dir, &["rev-parse", "HEAD"]), head);
+
+    // Aborting a rebase goes back to the branch, the changes are kept in a snapshot
+    run_git_command(test_dir, &["checkout", "--", "todo.md"]);
+    git_conflict(&["rebase", "other"]);
+    assert_eq!(repo_state().unwrap().operation, Operation::Rebase);
+
+    let state = recover(Recovery::Abort, "Test User", "test@example.com").expect("Failed to abort");
+    assert_eq!(state.operation, Operation::None);
+    assert!(state.files.is_empty());
+    assert_eq!(
+        git_output(test_dir, &["symbolic-ref", "HEAD"]),
+        "refs/heads/master"
+    );
+    assert_eq!(git_output(test_dir, &["rev-parse", "HEAD"]), head);
+    assert_eq!(todo(), "# Todo\n\n- [ ] buy tea and bread\n");
+    assert_eq!(list_snapshots().unwrap()[0].reason, "abort");
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_recovery completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_reflog() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_reflog");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init", "-b", "master"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n", 1000);
+    let first = git_output(test_dir, &["rev-parse", "HEAD"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n\n- [ ] buy milk\n", 2000);
+    let second = git_output(test_dir, &["rev-parse", "HEAD"]);
+    run_git_command(test_dir, &["branch", "other"]);
+
+    // A reset that loses a commit
+    run_git_command(test_dir, &["reset", "--hard", &first]);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let reflog = get_reflog(None).expect("Failed to read reflog");
+    assert_eq!(reflog.len(), 3);
+    assert_eq!(reflog[0].index, 0);
+    assert_eq!(reflog[0].old_id, second);
+    assert_eq!(reflog[0].new_id, first);
+    assert!(reflog[0].message.starts_with("reset: moving to"));
+    assert_eq!(reflog[1].new_id, second);
+    assert_eq!(reflog[2].old_id, "0".repeat(40));
+
+    let branch = get_reflog(Some("master")).expect("Failed to read branch reflog");
+    assert_eq!(branch.len(), 3);
+    assert_eq!(get_reflog(Some("other")).unwrap().len(), 1);
+    assert!(get_reflog(Some("missing")).is_err());
+
+    // Undo the reset, uncommitted changes and the old HEAD are kept in a snapshot
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] buy bread\n").unwrap();
+    reset_to_reflog(None, 1).expect("Failed to reset");
+    assert_eq!(git_output(test_dir, &["rev-parse", "HEAD"]), second);
+    assert_eq!(
+        git_output(test_dir, &["symbolic-ref", "HEAD"]),
+        "refs/heads/master"
+    );
+    assert_eq!(
+        fs::read_to_string(test_dir.join("todo.md")).unwrap(),
+        "# Todo\n\n- [ ] buy milk\n"
+    );
+    let snapshot = &list_snapshots().unwrap()[0];
+    assert_eq!(snapshot.reason, "reset");
+    assert_eq!(snapshot.head.as_deref(), Some(first.as_str()));
+    assert!(
+        show_snapshot(snapshot.id)
+            .unwrap()
+            .contains("+- [ ] buy bread")
+    );
+
+    // Other branches are only moved, their commits are kept in a snapshot
+    run_git_command(test_dir, &["checkout", "other"]);
+    reset_to_reflog(Some("master"), 1).expect("Failed to reset");
+    assert_eq!(git_output(test_dir, &["rev-parse", "master"]), first);
+    assert_eq!(git_output(test_dir, &["rev-parse", "HEAD"]), second);
+    let snapshot = &list_snapshots().unwrap()[0];
+    assert_eq!(snapshot.reason, "reset");
+    assert_eq!(snapshot.head.as_deref(), Some(second.as_str()));
+    assert!(snapshot.files.is_empty());
+
+    assert!(reset_to_reflog(None, 42).is_err());
+
+    // Only HEAD and local branches
+    run_git_command(
+        test_dir,
+        &["update-ref", "refs/remotes/origin/master", &second],
+    );
+    for name in ["refs/remotes/origin/master", "refs/gittasks/backups/1000"] {
+        let error = reset_to_reflog(Some(name), 0).expect_err("Should reject the ref");
+        assert_eq!(error.category(), crate::ErrorCategory::InvalidInput);
+    }
+    assert!(get_reflog(Some("refs/remotes/origin/master")).is_err());
+    assert_eq!(get_reflog(Some("refs/heads/other")).unwrap().len(), 1);
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_reflog completed successfully");
+}
+
+/// Clock that only moves when told to
+#[derive(Clone)]
+struct FakeClock {
+    start: std::time::Instant,
+    elapsed: std::sync::Arc<std::sync::Mutex<Duration>>,
+}
+
+impl FakeClock {
+    fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+            elapsed: Default::default(),
+        }
+    }
+
+    fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl autocommit::Clock for FakeClock {
+    fn now(&self) -> std::time::Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
+
+#[test]
+fn test_auto_commit_scheduler() {
+    let clock = FakeClock::new();
+    let mut scheduler = autocommit::Scheduler::new(clock.clone(), Duration::from_secs(2));
+
+    assert_eq!(scheduler.deadline(), None);
+    assert_eq!(scheduler.take_due(), None);
+
+    scheduler.notify("todo.md");
+    clock.advance(Duration::from_secs(1));
+    scheduler.notify("notes/a.md");
+    scheduler.notify("todo.md");
+
+    // Each change restarts the quiet period
+    clock.advance(Duration::from_millis(1500));
+    assert_eq!(scheduler.take_due(), None);
+    assert_eq!(
+        scheduler.deadline(),
+        Some(clock.start + Duration::from_secs(3))
+    );
+
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(
+        scheduler.take_due(),
+        Some(vec!["notes/a.md".to_string(), "todo.md".to_string()])
+    );
+    assert_eq!(scheduler.deadline(), None);
+    assert_eq!(scheduler.take_due(), None);
+
+    scheduler.notify("todo.md");
+    assert_eq!(scheduler.take_all(), vec!["todo.md".to_string()]);
+
+    // A failed batch is due again after another quiet period
+    scheduler.requeue(&["todo.md".to_string()]);
+    assert_eq!(scheduler.take_due(), None);
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(scheduler.take_due(), Some(vec!["todo.md".to_string()]));
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(scheduler.take_due(), None);
+}
+
+#[test]
+#[serial]
+fn test_auto_commit() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_auto_commit");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    fs::create_dir_all(test_dir).expect("Failed to create test dir");
+
+    run_git_command(test_dir, &["init", "-b", "master"]);
+    run_git_command(test_dir, &["config", "user.name", "Test User"]);
+    run_git_command(test_dir, &["config", "user.email", "test@example.com"]);
+    commit_file_at(test_dir, "todo.md", "# Todo\n", 1000);
+    let initial = git_output(test_dir, &["rev-parse", "HEAD"]);
+
+    open_repo(&test_dir.to_string_lossy()).expect("Failed to open repository");
+
+    let mut settings = AutoCommitSettings {
+        quiet_period: Duration::from_secs(60),
+        amend: true,
+        name: "Auto".to_string(),
+        email: "auto@example.com".to_string(),
+    };
+    let count = || git_output(test_dir, &["rev-list", "--count", "HEAD"]);
+    let todo = ["todo.md".to_string()];
+
+    // Nothing to commit
+    auto_commit(&settings, &["todo.md".to_string()]).expect("Failed to auto-commit");
+    assert_eq!(count(), "1");
+
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] buy milk\n").unwrap();
+    fs::write(test_dir.join("notes.md"), "# Notes\n").unwrap();
+    auto_commit(&settings, &["notes.md".to_string(), "todo.md".to_string()])
+        .expect("Failed to auto-commit");
+    assert_eq!(count(), "2");
+    assert_eq!(
+        git_output(test_dir, &["log", "-1", "--format=%B"]),
+        "Auto-commit\n\nnotes.md\ntodo.md"
+    );
+    assert_eq!(
+        git_output(test_dir, &["log", "-1", "--format=%an <%ae>"]),
+        "Auto <auto@example.com>"
+    );
+
+    // The unpushed auto-commit is amended
+    fs::remove_file(test_dir.join("notes.md")).unwrap();
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] buy milk\n").unwrap();
+    auto_commit(&settings, &["notes.md".to_string(), "todo.md".to_string()])
+        .expect("Failed to auto-commit");
+    assert_eq!(count(), "2");
+    assert_eq!(git_output(test_dir, &["rev-parse", "HEAD~1"]), initial);
+    assert_eq!(
+        git_output(test_dir, &["log", "-1", "--format=%B"]),
+        "Auto-commit\n\ntodo.md"
+    );
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+
+    // Once pushed, a new commit is stacked
+    let pushed = git_output(test_dir, &["rev-parse", "HEAD"]);
+    run_git_command(
+        test_dir,
+        &["update-ref", "refs/remotes/origin/master", &pushed],
+    );
+    fs::write(test_dir.join("todo.md"), "# Todo\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    assert_eq!(count(), "3");
+
+    // Undoing the changes of an unpushed auto-commit drops it
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] buy milk\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    assert_eq!(git_output(test_dir, &["rev-parse", "HEAD"]), pushed);
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+
+    // Without amend every batch is a commit
+    settings.amend = false;
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] bread\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] bread\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    assert_eq!(count(), "4");
+
+    // A manual commit is never amended
+    settings.amend = true;
+    fs::write(test_dir.join("todo.md"), "# Todo\n").unwrap();
+    commit_all("Test User", "test@example.com", "Manual").expect("Failed to commit");
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] eggs\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    assert_eq!(count(), "6");
+
+    // Notifications are batched until the quiet period passed or they are flushed
+    start_auto_commit(settings.clone());
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [x] eggs\n").unwrap();
+    notify_file_changed("todo.md");
+    std::thread::sleep(Duration::from_millis(100));
+    assert_ne!(git_output(test_dir, &["status", "--porcelain"]), "");
+    flush_auto_commit().expect("Failed to flush");
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+    assert_eq!(count(), "6");
+
+    start_auto_commit(AutoCommitSettings {
+        quiet_period: Duration::from_millis(50),
+        ..settings.clone()
+    });
+    fs::write(test_dir.join("todo.md"), "# Todo\n").unwrap();
+    notify_file_changed("todo.md");
+    let mut waited = 0;
+    while !git_output(test_dir, &["status", "--porcelain"]).is_empty() && waited < 50 {
+        std::thread::sleep(Duration::from_millis(100));
+        waited += 1;
+    }
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+
+    // Only the notified files are committed
+    fs::write(test_dir.join("draft.md"), "# Draft\n").unwrap();
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] coffee\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    assert_eq!(
+        git_output(test_dir, &["status", "--porcelain"]),
+        "?? draft.md"
+    );
+    assert_eq!(
+        git_output(test_dir, &["log", "-1", "--format=%B"]),
+        "Auto-commit\n\ntodo.md"
+    );
+    fs::write(test_dir.join("todo.md"), "# Todo\n").unwrap();
+    auto_commit(&settings, &todo).expect("Failed to auto-commit");
+    fs::remove_file(test_dir.join("draft.md")).unwrap();
+
+    // A moved folder is notified as a whole
+    fs::create_dir_all(test_dir.join("projects")).unwrap();
+    fs::write(test_dir.join("projects/a.md"), "# A\n").unwrap();
+    fs::write(test_dir.join("projects/b.md"), "# B\n").unwrap();
+    auto_commit(&settings, &["projects".to_string()]).expect("Failed to auto-commit");
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+    fs::create_dir_all(test_dir.join("archive")).unwrap();
+    fs::rename(test_dir.join("projects"), test_dir.join("archive/projects")).unwrap();
+    auto_commit(
+        &settings,
+        &["projects".to_string(), "archive/projects".to_string()],
+    )
+    .expect("Failed to auto-commit");
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+    assert_eq!(
+        git_output(test_dir, &["ls-tree", "-r", "--name-only", "HEAD"]),
+        "archive/projects/a.md\narchive/projects/b.md\ntodo.md"
+    );
+
+    // Failed commits stay pending
+    start_auto_commit(settings.clone());
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] juice\n").unwrap();
+    notify_file_changed("todo.md");
+    fs::write(test_dir.join(".git/index.lock"), "").unwrap();
+    assert!(flush_auto_commit().is_err());
+    fs::remove_file(test_dir.join(".git/index.lock")).unwrap();
+    flush_auto_commit().expect("Failed to flush");
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+
+    // Stopping commits what is pending, later notifications are ignored
+    start_auto_commit(settings.clone());
+    fs::write(test_dir.join("todo.md"), "# Todo\n\n- [ ] tea\n").unwrap();
+    notify_file_changed("todo.md");
+    stop_auto_commit().expect("Failed to stop");
+    assert_eq!(git_output(test_dir, &["status", "--porcelain"]), "");
+    fs::write(test_dir.join("todo.md"), "# Todo\n").unwrap();
+    notify_file_changed("todo.md");
+    flush_auto_commit().expect("Failed to flush");
+    assert_ne!(git_output(test_dir, &["status", "--porcelain"]), "");
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_auto_commit completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_squash_before_push() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_squash");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    let remote_repo = test_dir.join("remote");
+    let local_repo = test_dir.join("local");
+    fs::create_dir_all(&remote_repo).expect("Failed to create remote repo dir");
+
+    run_git_command(&remote_repo, &["init", "--bare", "-b", "master"]);
+    let remote_url = format!("file://{}", remote_repo.canonicalize().unwrap().display());
+    run_git_command(test_dir, &["clone", &remote_url, "local"]);
+    run_git_command(&local_repo, &["config", "user.name", "Test User"]);
+    run_git_command(&local_repo, &["config", "user.email", "test@example.com"]);
+    run_git_command(&local_repo, &["checkout", "-b", "master"]);
+
+    commit_file_at(&local_repo, "todo.md", "# Todo\n", 100);
+    run_git_command(&local_repo, &["push", "-u", "origin", "master"]);
+    let pushed = git_output(&local_repo, &["rev-parse", "HEAD"]);
+
+    let update = |content: &str, seconds: i64, author: &str, message: &str| {
+        fs::write(local_repo.join("todo.md"), content).unwrap();
+        run_git_command(&local_repo, &["add", "todo.md"]);
+        commit_at(&local_repo, message, seconds, author);
+    };
+    update("# Todo\n\n- [ ] a\n", 1000, "Test User", "update");
+    update("# Todo\n\n- [ ] b\n", 1100, "Test User", "update");
+    update(
+        "# Todo\n\n- [ ] c\n",
+        1500,
+        "Test User",
+        "update\n\ntodo.md",
+    );
+    update("# Todo\n\n- [ ] d\n", 1600, "Other User", "update");
+    update("# Todo\n\n- [ ] e\n", 1700, "Other User", "fix typo");
+    update("# Todo\n\n- [ ] f\n", 5000, "Test User", "update");
+    let tree = git_output(&local_repo, &["rev-parse", "HEAD^{tree}"]);
+
+    open_repo(&local_repo.to_string_lossy()).expect("Failed to open repository");
+
+    // Disabled by default
+    push(None, &mut NoProgress).expect("Failed to push");
+    assert_eq!(
+        git_output(&local_repo, &["rev-list", "--count", "HEAD"]),
+        "7"
+    );
+
+    // Nothing is unpushed any more
+    let repo = REPO.lock().unwrap();
+    let removed = squash::squash(repo.as_ref().unwrap(), Duration::from_secs(600));
+    assert_eq!(removed.unwrap(), 0);
+    drop(repo);
+
+    run_git_command(&local_repo, &["reset", "--soft", &pushed]);
+    run_git_command(&local_repo, &["push", "--force", "origin", "master"]);
+    run_git_command(&local_repo, &["reset", "--soft", "HEAD@{1}"]);
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD^{tree}"]), tree);
+
+    set_squash_window(Some(Duration::from_secs(600)));
+
+    // Nothing is squashed when the push is rejected, the remote moved on meanwhile
+    let head = git_output(&local_repo, &["rev-parse", "HEAD"]);
+    run_git_command(&remote_repo, &["config", "user.name", "Other User"]);
+    run_git_command(&remote_repo, &["config", "user.email", "other@example.com"]);
+    let remote_commit = git_output(
+        &remote_repo,
+        &[
+            "commit-tree",
+            &format!("{pushed}^{{tree}}"),
+            "-p",
+            &pushed,
+            "-m",
+            "remote",
+        ],
+    );
+    run_git_command(
+        &remote_repo,
+        &["update-ref", "refs/heads/master", &remote_commit],
+    );
+    let err = push(None, &mut NoProgress).expect_err("Push should be rejected");
+    assert!(err.to_string().contains("push needs sync"), "{err}");
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), head);
+    assert_eq!(
+        git_output(&local_repo, &["rev-parse", "origin/master"]),
+        remote_commit
+    );
+    assert!(list_snapshots().unwrap().is_empty());
+
+    run_git_command(&remote_repo, &["update-ref", "refs/heads/master", &pushed]);
+    push(None, &mut NoProgress).expect("Failed to push");
+    set_squash_window(None);
+
+    let log = git_output(
+        &local_repo,
+        &["log", "--format=%an|%at|%B", &format!("{pushed}..HEAD")],
+    );
+    let log: Vec<&str> = log.split('\n').filter(|line| !line.is_empty()).collect();
+    assert_eq!(
+        log,
+        [
+            "Test User|5000|update",
+            "Other User|1700|update",
+            "fix typo",
+            "Test User|1500|update",
+            "todo.md",
+        ]
+    );
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD^{tree}"]), tree);
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD~3"]), pushed);
+    assert_eq!(
+        git_output(&remote_repo, &["rev-parse", "master"]),
+        git_output(&local_repo, &["rev-parse", "HEAD"])
+    );
+    assert_eq!(git_output(&local_repo, &["status", "--porcelain"]), "");
+
+    // The squashed commits are kept in a snapshot
+    let snapshot = &list_snapshots().unwrap()[0];
+    assert_eq!(snapshot.reason, "squash");
+    assert!(snapshot.files.is_empty());
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_squash_before_push completed successfully");
+}
+
+#[test]
+#[serial]
+fn test_journal_replay() {
+    init_lib("/tmp".to_string());
+
+    let test_dir = Path::new("test_repos_journal");
+    if test_dir.exists() {
+        fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+    }
+    let remote_repo = test_dir.join("remote");
+    let local_repo = test_dir.join("local");
+    let other_repo = test_dir.join("other");
+    fs::create_dir_all(&remote_repo).expect("Failed to create remote repo dir");
+
+    run_git_command(&remote_repo, &["init", "--bare", "-b", "master"]);
+    let remote_url = format!("file://{}", remote_repo.canonicalize().unwrap().display());
+    for repo in ["local", "other"] {
+        run_git_command(test_dir, &["clone", &remote_url, repo]);
+        let repo = test_dir.join(repo);
+        run_git_command(&repo, &["config", "user.name", "Test User"]);
+        run_git_command(&repo, &["config", "user.email", "test@example.com"]);
+        run_git_command(&repo, &["checkout", "-b", "master"]);
+    }
+
+    commit_file_at(&local_repo, "todo.md", "# Todo\n", 1000);
+    run_git_command(&local_repo, &["push", "-u", "origin", "master"]);
+    let before = git_output(&local_repo, &["rev-parse", "HEAD"]);
+    run_git_command(&other_repo, &["pull", "origin", "master"]);
+    commit_file_at(&other_repo, "remote.md", "# Remote\n", 2000);
+    run_git_command(&other_repo, &["push", "origin", "master"]);
+    run_git_command(&local_repo, &["fetch"]);
+
+    let journal_path = local_repo.join(".git/gittasks/journal");
+    let edit = "# Todo\n\n- [ ] local\n";
+    fs::write(local_repo.join("todo.md"), edit).unwrap();
+
+    let remote_head = git_output(&local_repo, &["rev-parse", "origin/master"]);
+    let target = git2::Oid::from_str(&remote_head).unwrap();
+    let reopen = || {
+        close();
+        open_repo(&local_repo.to_string_lossy()).expect("Failed to open repository");
+    };
+
+    open_repo(&local_repo.to_string_lossy()).expect("Failed to open repository");
+    assert_eq!(pending_journal().unwrap(), None);
+
+    // Operations that return remove their journal
+    commit_all("Test User", "test@example.com", "Local").expect("Failed to commit");
+    pull(None, "Test User", "test@example.com", &mut NoProgress).expect("Failed to pull");
+    assert!(!journal_path.exists());
+    run_git_command(&local_repo, &["reset", "--hard", &before]);
+    fs::write(local_repo.join("todo.md"), edit).unwrap();
+
+    // Dies during the fetch of a pull, with uncommitted changes
+    {
+        let repo = REPO.lock().unwrap();
+        let mut journal = journal::Journal::begin(repo.as_ref().unwrap(), JournalOp::Pull).unwrap();
+        journal.step(journal::Step::Fetched);
+        std::mem::forget(journal);
+    }
+    reopen();
+    let discarded = Replay {
+        operation: JournalOp::Pull,
+        outcome: ReplayOutcome::Discarded,
+    };
+    assert_eq!(pending_journal().unwrap(), Some(discarded));
+    assert!(journal_path.exists());
+    assert_eq!(replay_journal().unwrap(), Some(discarded));
+    assert!(!journal_path.exists());
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), before);
+    assert_eq!(
+        fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+        edit
+    );
+
+    // Dies in the middle of the merge of a pull
+    let die_merging = || {
+        {
+            let repo = REPO.lock().unwrap();
+            let repo = repo.as_ref().unwrap();
+            let mut journal = journal::Journal::begin(repo, JournalOp::Pull).unwrap();
+            journal.step(journal::Step::Fetched);
+            journal.updating(target, snapshot::save(repo, "pull", false).unwrap());
+            std::mem::forget(journal);
+        }
+        run_git_command(&local_repo, &["reset", "--hard", "origin/master"]);
+        fs::write(local_repo.join(".git/MERGE_HEAD"), format!("{before}\n")).unwrap();
+        reopen();
+    };
+
+    die_merging();
+    let rolled_back = Replay {
+        operation: JournalOp::Pull,
+        outcome: ReplayOutcome::RolledBack,
+    };
+    // Nothing happens before the caller asks for it
+    assert_eq!(pending_journal().unwrap(), Some(rolled_back));
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), remote_head);
+    assert_eq!(replay_journal().unwrap(), Some(rolled_back));
+    assert!(!journal_path.exists());
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), before);
+    assert_eq!(repo_state().unwrap().operation, Operation::None);
+    assert_eq!(
+        fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+        edit
+    );
+    assert_eq!(
+        git_output(&local_repo, &["symbolic-ref", "HEAD"]),
+        "refs/heads/master"
+    );
+
+    // The branch moved on after the crash: not rolled back, unless discarded
+    die_merging();
+    fs::remove_file(local_repo.join(".git/MERGE_HEAD")).unwrap();
+    commit_file_at(&local_repo, "later.md", "# Later\n", 3000);
+    let later = git_output(&local_repo, &["rev-parse", "HEAD"]);
+    assert!(replay_journal().is_err());
+    assert!(journal_path.exists());
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), later);
+    discard_journal().unwrap();
+    assert!(!journal_path.exists());
+    assert_eq!(pending_journal().unwrap(), None);
+    run_git_command(&local_repo, &["reset", "--hard", &before]);
+    fs::write(local_repo.join("todo.md"), edit).unwrap();
+
+    // Dies after a sync stashed the changes
+    let sync_until = |step: journal::Step| {
+        {
+            let mut repo = REPO.lock().unwrap();
+            let repo = repo.as_mut().unwrap();
+            let mut journal = journal::Journal::begin(repo, JournalOp::Sync).unwrap();
+            let signature = repo.signature().unwrap();
+            journal.stash(repo.stash_save(&signature, "sync", None).unwrap());
+            match step {
+                journal::Step::Started => {}
+                journal::Step::Updating => journal.updating(target, None),
+                step => journal.step(step),
+            }
+            std::mem::forget(journal);
+        }
+        if matches!(step, journal::Step::Updating | journal::Step::Updated) {
+            run_git_command(&local_repo, &["reset", "--hard", "origin/master"]);
+        }
+        reopen();
+        replay_journal().unwrap().map(|replay| replay.outcome)
+    };
+
+    // After the reset the stashed changes are applied
+    assert_eq!(
+        sync_until(journal::Step::Updated),
+        Some(ReplayOutcome::Resumed)
+    );
+    assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), remote_head);
+    assert_eq!(
+        fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+        edit
+    );
+    assert!(list_stashes().unwrap().is_empty());
+
+    // Before or during the reset: the stash goes back on the old head
+    for step in [journal::Step::Fetched, journal::Step::Updating] {
+        run_git_command(&local_repo, &["reset", "--hard", &before]);
+        fs::write(local_repo.join("todo.md"), edit).unwrap();
+        assert_eq!(sync_until(step), Some(ReplayOutcome::RolledBack));
+        assert_eq!(git_output(&local_repo, &["rev-parse", "HEAD"]), before);
+        assert_eq!(
+            fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+            edit
+        );
+        assert!(list_stashes().unwrap().is_empty());
+    }
+
+    // Nothing is left to do for a commit, an unreadable journal is dropped
+    {
+        let repo = REPO.lock().unwrap();
+        std::mem::forget(journal::Journal::begin(
+            repo.as_ref().unwrap(),
+            JournalOp::Commit,
+        ));
+    }
+    reopen();
+    assert_eq!(
+        replay_journal().unwrap(),
+        Some(Replay {
+            operation: JournalOp::Commit,
+            outcome: ReplayOutcome::Discarded,
+        })
+    );
+    assert_eq!(
+        fs::read_to_string(local_repo.join("todo.md")).unwrap(),
+        edit
+    );
+
+    fs::write(&journal_path, "operation=rebase\n").unwrap();
+    reopen();
+    assert_eq!(pending_journal().unwrap(), None);
+    assert!(journal_path.exists());
+    assert_eq!(replay_journal().unwrap(), None);
+    assert!(!journal_path.exists());
+
+    close();
+    fs::remove_dir_all(test_dir).expect("Failed to clean up test directories");
+
+    println!("✓ test_journal_replay completed successfully");
+}
@@ -0,0 +1,380 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{Commit, Delta, DiffFindOptions, Oid, Repository, Sort};
+
+use crate::{Error, mime_types::is_extension_supported};
+
+/// Cache file below `.git`
+const CACHE_FILE: &str = "gittasks/timestamps";
+
+/// Bumped whenever the meaning of the cached values changes, older caches are rebuilt
+const CACHE_VERSION: u32 = 3;
+
+/// Renames of whole folders touch many files at once, look beyond libgit2's default of 200
+const RENAME_LIMIT: usize = 10_000;
+
+/// When a file was created and last modified, in milliseconds, and by whom
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileHistory {
+    /// Time of the first commit that added the file, renames are followed
+    pub created: i64,
+    /// Time of the last commit that modified the file, see `count_renames` of [`file_history`]
+    pub modified: i64,
+    /// Author of the last commit that modified the file
+    pub last_author: String,
+    pub last_author_email: String,
+}
+
+/// History of every path seen up to `head`, persisted between runs so that
+/// only the commits made since then have to be walked.
+///
+/// Deleted paths are kept, a file that comes back keeps its creation time.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Cache {
+    head: Option<Oid>,
+    count_renames: bool,
+    files: HashMap<String, FileHistory>,
+}
+
+impl Cache {
+    fn path(repo: &Repository) -> PathBuf {
+        repo.path().join(CACHE_FILE)
+    }
+
+    /// Missing or unreadable caches are treated as empty
+    fn load(repo: &Repository) -> Cache {
+        let path = Cache::path(repo);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => Cache::parse(&content).unwrap_or_else(|| {
+                warn!("ignoring invalid timestamp cache {}", path.display());
+                Cache::default()
+            }),
+            Err(_) => Cache::default(),
+        }
+    }
+
+    /// ```text
+    /// version <CACHE_VERSION>
+    /// head <oid>
+    /// renames <count_renames>
+    /// <created>\t<modified>\t<author>\t<email>\t<path>
+    /// ```
+    fn parse(content: &str) -> Option<Cache> {
+        let mut lines = content.lines();
+
+        let version = lines.next()?.strip_prefix("version ")?;
+        if version.parse::<u32>().ok()? != CACHE_VERSION {
+            return None;
+        }
+
+        let head = Oid::from_str(lines.next()?.strip_prefix("head ")?).ok()?;
+        let count_renames = lines.next()?.strip_prefix("renames ")?.parse().ok()?;
+
+        let mut files = HashMap::new();
+        for line in lines {
+            let mut fields = line.splitn(5, '\t');
+            let history = FileHistory {
+                created: fields.next()?.parse().ok()?,
+                modified: fields.next()?.parse().ok()?,
+                last_author: fields.next()?.to_string(),
+                last_author_email: fields.next()?.to_string(),
+            };
+            files.insert(fields.next()?.to_string(), history);
+        }
+
+        Some(Cache {
+            head: Some(head),
+            count_renames,
+            files,
+        })
+    }
+
+    fn serialize(&self) -> Option<String> {
+        let head = self.head?;
+        let mut content = format!(
+            "version {CACHE_VERSION}\nhead {head}\nrenames {}\n",
+            self.count_renames
+        );
+
+        for (path, history) in &self.files {
+            // Cannot be represented, such files are looked up again next time
+            if path.contains('\n') {
+                continue;
+            }
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{path}\n",
+                history.created, history.modified, history.last_author, history.last_author_email
+            ));
+        }
+
+        Some(content)
+    }
+
+    /// A cache that cannot be written only costs time on the next call
+    fn save(&self, repo: &Repository) {
+        let Some(content) = self.serialize() else {
+            return;
+        };
+
+        let path = Cache::path(repo);
+        let tmp = path.with_extension("tmp");
+
+        let res = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, content))
+            .and_then(|_| fs::rename(&tmp, &path));
+
+        if let Err(e) = res {
+            warn!("failed to write timestamp cache {}: {e}", path.display());
+        }
+    }
+}
+
+/// Time of the last commit that modified each supported file at HEAD, in milliseconds
+pub fn get_timestamps(
+    repo: &Repository,
+    count_renames: bool,
+) -> Result<HashMap<String, i64>, Error> {
+    Ok(file_history(repo, count_renames)?
+        .into_iter()
+        .map(|(path, history)| (path, history.modified))
+        .collect())
+}
+
+/// [`FileHistory`] of each supported file at HEAD
+///
+/// Renamed and moved files keep their history. With `count_renames` unset a
+/// rename that does not change the content leaves the modification time and
+/// last author alone, so moving a folder does not make its notes look edited.
+pub fn file_history(
+    repo: &Repository,
+    count_renames: bool,
+) -> Result<HashMap<String, FileHistory>, Error> {
+    let start = std::time::Instant::now();
+
+    // Empty repositories have no commits to timestamp
+    let Ok(head) = repo.head() else {
+        debug!("Repository has no commits yet, returning empty timestamps");
+        return Ok(HashMap::new());
+    };
+    let head = head.peel_to_commit()?.id();
+
+    // First, get the list of all supported files in the repo at HEAD
+    let mut supported_files = HashSet::new();
+
+    super::walk_head_blobs(repo, |path, _entry| {
+        if let Some(extension) = Path::new(&path).extension()
+            && let Some(extension) = extension.to_str()
+            && is_extension_supported(extension)
+        {
+            supported_files.insert(path);
+        }
+    })?;
+
+    debug!("Found {} supported files to process", supported_files.len());
+
+    if supported_files.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut cache = Cache::load(repo);
+
+    if cache.head.is_some() && cache.count_renames != count_renames {
+        debug!("Rename handling changed, rebuilding timestamp cache");
+        cache = Cache::default();
+    }
+
+    let commits_processed = match cache.head {
+        Some(cached) if cached == head => 0,
+        // Only the commits since the cached HEAD can have touched a file
+        Some(cached) if repo.graph_descendant_of(head, cached)? => {
+            walk(repo, head, Some(cached), count_renames, &mut cache.files)?
+        }
+        cached => {
+            if cached.is_some() {
+                debug!("History was rewritten, rebuilding timestamp cache");
+            }
+            cache.files.clear();
+            walk(repo, head, None, count_renames, &mut cache.files)?
+        }
+    };
+
+    if commits_processed > 0 {
+        cache.head = Some(head);
+        cache.count_renames = count_renames;
+        cache.save(repo);
+    }
+
+    let mut files: HashMap<String, FileHistory> = cache
+        .files
+        .into_iter()
+        .filter(|(path, _)| supported_files.contains(path))
+        .collect();
+
+    // Check if we have timestamps for all files
+    let missing_files: Vec<&String> = supported_files
+        .iter()
+        .filter(|file| !files.contains_key(*file))
+        .collect();
+
+    if !missing_files.is_empty() {
+        // Use current time for missing files and log an error
+        let current_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+
+        error!(
+            "Some files were never committed, using current time for {} files: {:?}",
+            missing_files.len(),
+            missing_files
+        );
+
+        for file in missing_files {
+            files.insert(
+                file.clone(),
+                FileHistory {
+                    created: current_time,
+                    modified: current_time,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    debug!(
+        "get_timestamps completed in {}ms, processed {} commits, found timestamps for {} files",
+        start.elapsed().as_millis(),
+        commits_processed,
+        files.len()
+    );
+
+    Ok(files)
+}
+
+/// Replay the commits reachable from `head` but not from `hide`, oldest first,
+/// on top of `files`. Returns the number of commits processed.
+fn walk(
+    repo: &Repository,
+    head: Oid,
+    hide: Option<Oid>,
+    count_renames: bool,
+    files: &mut HashMap<String, FileHistory>,
+) -> Result<usize, Error> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(head)?;
+    if let Some(hide) = hide {
+        revwalk.hide(hide)?;
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME | Sort::REVERSE)?;
+
+    let mut commits_processed = 0;
+
+    for oid in revwalk {
+        commits_processed += 1;
+        apply(repo, &repo.find_commit(oid?)?, count_renames, files)?;
+    }
+
+    Ok(commits_processed)
+}
+
+/// Update `files` with the changes `commit` made compared to its first parent
+fn apply(
+    repo: &Repository,
+    commit: &Commit,
+    count_renames: bool,
+    files: &mut HashMap<String, FileHistory>,
+) -> Result<(), Error> {
+    let time = commit.time().seconds() * 1000;
+    let author = commit.author();
+    // Tabs and newlines would break the cache format
+    let clean = |s: Option<&str>| s.unwrap_or_default().replace(['\t', '\n'], " ");
+    let last_author = clean(author.name());
+    let last_author_email = clean(author.email());
+
+    // The initial commit adds everything
+    let parent_tree = match commit.parents().next() {
+        Some(parent) => Some(parent.tree()?),
+        None => None,
+    };
+
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(
+        DiffFindOptions::new()
+            .renames(true)
+            .rename_limit(RENAME_LIMIT),
+    ))?;
+
+    for delta in diff.deltas() {
+        let path = |file: git2::DiffFile| file.path().and_then(|p| p.to_str()).map(str::to_string);
+        let Some(new_path) = path(delta.new_file()) else {
+            continue;
+        };
+
+        let previous = match delta.status() {
+            Delta::Deleted => continue,
+            Delta::Renamed => path(delta.old_file()).and_then(|old_path| files.remove(&old_path)),
+            _ => files.remove(&new_path),
+        };
+
+        let pure_rename =
+            delta.status() == Delta::Renamed && delta.old_file().id() == delta.new_file().id();
+
+        let history = match previous {
+            // Moved without changing the content
+            Some(previous) if pure_rename && !count_renames => previous,
+            previous => FileHistory {
+                created: previous.map_or(time, |history| history.created),
+                modified: time,
+                last_author: last_author.clone(),
+                last_author_email: last_author_email.clone(),
+            },
+        };
+
+        files.insert(new_path, history);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_roundtrip() {
+        let cache = Cache {
+            head: Some(Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap()),
+            count_renames: true,
+            files: HashMap::from([
+                (
+                    "notes/a b.md".to_string(),
+                    FileHistory {
+                        created: 1_600_000_000_000,
+                        modified: 1_700_000_000_000,
+                        last_author: "Jane Doe".to_string(),
+                        last_author_email: "jane@example.com".to_string(),
+                    },
+                ),
+                ("todo\t.txt".to_string(), FileHistory::default()),
+            ]),
+        };
+
+        let content = cache.serialize().unwrap();
+        assert_eq!(Cache::parse(&content), Some(cache));
+
+        // Caches of other versions or garbage are ignored
+        assert_eq!(
+            Cache::parse(&content.replace("version 3", "version 2")),
+            None
+        );
+        assert_eq!(Cache::parse("head 0123\n"), None);
+        assert_eq!(Cache::parse(""), None);
+    }
+}
@@ -0,0 +1,616 @@
+//! Full text search over the notes in the working tree
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use git2::{Oid, Repository, Status, StatusOptions};
+
+use crate::{Error, mime_types::is_extension_supported};
+
+/// Index file below `.git`
+const INDEX_FILE: &str = "gittasks/search";
+
+/// Bumped whenever the tokenisation or the file format changes, older indexes are rebuilt
+const INDEX_VERSION: u32 = 1;
+
+/// Characters shown before and after the match in a snippet
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub path: String,
+    /// Higher is better, only meaningful compared to the other results
+    pub score: f64,
+    /// Line of the first match, starting at 1
+    pub line: usize,
+    /// Text around the first match on a single line
+    pub snippet: String,
+}
+
+/// Inverted index of the supported files in the working tree
+#[derive(Debug, Default, PartialEq)]
+pub struct Index {
+    /// Commit the files were compared against when they were indexed
+    head: Option<Oid>,
+    /// Files that differed from `head`, they are looked at again on the next refresh
+    dirty: BTreeSet<String>,
+    /// Tokens of each file, in order
+    files: BTreeMap<String, Vec<String>>,
+    /// Token -> file -> positions of the token in the file
+    postings: BTreeMap<String, HashMap<String, Vec<u32>>>,
+}
+
+struct Token {
+    text: String,
+    /// Byte range in the source text
+    start: usize,
+    end: usize,
+}
+
+/// Lowercase words made of letters and digits, everything else separates them
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push(Token {
+                    text: text[s..i].to_lowercase(),
+                    start: s,
+                    end: i,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    tokens
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Clause {
+    Term(String),
+    /// `word*`
+    Prefix(String),
+    /// `"some words"`, or a word that splits into several tokens like `e-mail`
+    Phrase(Vec<String>),
+}
+
+/// All clauses have to match
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+
+    for (i, part) in query.split('"').enumerate() {
+        // Odd parts are between quotes
+        if i % 2 == 1 {
+            let tokens: Vec<String> = tokenize(part).into_iter().map(|t| t.text).collect();
+            match tokens.len() {
+                0 => {}
+                1 => clauses.extend(tokens.into_iter().map(Clause::Term)),
+                _ => clauses.push(Clause::Phrase(tokens)),
+            }
+            continue;
+        }
+
+        for word in part.split_whitespace() {
+            let prefix = word.ends_with('*');
+            let mut tokens: Vec<String> = tokenize(word).into_iter().map(|t| t.text).collect();
+
+            match tokens.len() {
+                0 => {}
+                1 if prefix => clauses.push(Clause::Prefix(tokens.remove(0))),
+                1 => clauses.push(Clause::Term(tokens.remove(0))),
+                _ => clauses.push(Clause::Phrase(tokens)),
+            }
+        }
+    }
+
+    clauses
+}
+
+/// How often a clause matches in a file and where it matches first
+#[derive(Debug, Clone, Copy)]
+struct Hit {
+    count: usize,
+    first: u32,
+}
+
+impl Index {
+    fn path(repo: &Repository) -> PathBuf {
+        repo.path().join(INDEX_FILE)
+    }
+
+    /// Missing or unreadable indexes are treated as empty, the next refresh rebuilds them
+    pub fn load(repo: &Repository) -> Index {
+        let path = Index::path(repo);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => Index::parse(&content).unwrap_or_else(|| {
+                warn!("ignoring invalid search index {}", path.display());
+                Index::default()
+            }),
+            Err(_) => Index::default(),
+        }
+    }
+
+    /// ```text
+    /// version <INDEX_VERSION>
+    /// head <oid>
+    /// dirty <path>
+    /// file <path>
+    /// <tokens separated by spaces>
+    /// ```
+    fn parse(content: &str) -> Option<Index> {
+        let mut lines = content.lines();
+
+        let version = lines.next()?.strip_prefix("version ")?;
+        if version.parse::<u32>().ok()? != INDEX_VERSION {
+            return None;
+        }
+
+        let mut index = Index {
+            head: Some(Oid::from_str(lines.next()?.strip_prefix("head ")?).ok()?),
+            ..Default::default()
+        };
+
+        while let Some(line) = lines.next() {
+            if let Some(path) = line.strip_prefix("dirty ") {
+                index.dirty.insert(path.to_string());
+            } else if let Some(path) = line.strip_prefix("file ") {
+                let tokens = lines.next()?.split(' ').filter(|t| !t.is_empty());
+                index.insert(path, tokens.map(str::to_string).collect());
+            } else {
+                return None;
+            }
+        }
+
+        Some(index)
+    }
+
+    fn serialize(&self) -> Option<String> {
+        let head = self.head?;
+        let mut content = format!("version {INDEX_VERSION}\nhead {head}\n");
+
+        // Paths with line breaks cannot be represented, such files are not found until they change
+        for path in self.dirty.iter().filter(|path| !path.contains('\n')) {
+            content.push_str(&format!("dirty {path}\n"));
+        }
+        for (path, tokens) in self.files.iter().filter(|(path, _)| !path.contains('\n')) {
+            content.push_str(&format!("file {path}\n{}\n", tokens.join(" ")));
+        }
+
+        Some(content)
+    }
+
+    /// An index that cannot be written is rebuilt after the next start
+    fn save(&self, repo: &Repository) {
+        let Some(content) = self.serialize() else {
+            return;
+        };
+
+        let path = Index::path(repo);
+        let tmp = path.with_extension("tmp");
+
+        let res = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&tmp, content))
+            .and_then(|_| fs::rename(&tmp, &path));
+
+        if let Err(e) = res {
+            warn!("failed to write search index {}: {e}", path.display());
+        }
+    }
+
+    fn insert(&mut self, path: &str, tokens: Vec<String>) {
+        self.remove(path);
+
+        for (position, token) in tokens.iter().enumerate() {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .entry(path.to_string())
+                .or_default()
+                .push(position as u32);
+        }
+
+        self.files.insert(path.to_string(), tokens);
+    }
+
+    fn remove(&mut self, path: &str) {
+        let Some(tokens) = self.files.remove(path) else {
+            return;
+        };
+
+        for token in tokens.iter().collect::<BTreeSet<_>>() {
+            if let Some(files) = self.postings.get_mut(token) {
+                files.remove(path);
+                if files.is_empty() {
+                    self.postings.remove(token);
+                }
+            }
+        }
+    }
+
+    /// Index `content` as the current version of `path`
+    pub fn add(&mut self, path: &str, content: &str) {
+        let tokens = tokenize(content).into_iter().map(|t| t.text).collect();
+        self.insert(path, tokens);
+    }
+
+    /// Re-index the files that changed since the last refresh: the files
+    /// touched by the commits since then, and everything `git status` reports.
+    /// The whole working tree is indexed if the previous commit is unknown.
+    ///
+    /// Returns the number of files looked at.
+    pub fn refresh(&mut self, repo: &Repository) -> Result<usize, Error> {
+        let Some(workdir) = repo.workdir() else {
+            return Ok(0);
+        };
+
+        let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let head_tree = match &head {
+            Some(head) => Some(head.tree()?),
+            None => None,
+        };
+
+        let old_tree = self
+            .head
+            .and_then(|oid| repo.find_commit(oid).ok())
+            .and_then(|commit| commit.tree().ok());
+
+        let mut changed = std::mem::take(&mut self.dirty);
+        let mut status_options = StatusOptions::new();
+        status_options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true);
+
+        match old_tree {
+            Some(old_tree) => {
+                let diff = repo.diff_tree_to_tree(Some(&old_tree), head_tree.as_ref(), None)?;
+                for delta in diff.deltas() {
+                    for file in [delta.old_file(), delta.new_file()] {
+                        if let Some(path) = file.path().and_then(|path| path.to_str()) {
+                            changed.insert(path.to_string());
+                        }
+                    }
+                }
+            }
+            None => {
+                debug!("Building the search index from scratch");
+                self.files.clear();
+                self.postings.clear();
+                status_options.include_unmodified(true);
+            }
+        }
+
+        let mut dirty = BTreeSet::new();
+        for entry in repo.statuses(Some(&mut status_options))?.iter() {
+            if let Some(path) = entry.path() {
+                if entry.status() != Status::CURRENT {
+                    dirty.insert(path.to_string());
+                }
+                changed.insert(path.to_string());
+            }
+        }
+
+        for path in &changed {
+            match read_note(workdir, path) {
+                Some(content) => self.add(path, &content),
+                None => self.remove(path),
+            }
+        }
+
+        let head = head.map(|head| head.id());
+        let modified = !changed.is_empty() || self.head != head || self.dirty != dirty;
+        self.head = head;
+        self.dirty = dirty;
+
+        if modified {
+            self.save(repo);
+        }
+
+        debug!(
+            "Search index refreshed: {} files looked at, {} indexed",
+            changed.len(),
+            self.files.len()
+        );
+
+        Ok(changed.len())
+    }
+
+    /// Files matching all parts of `query`, best first.
+    ///
+    /// Words match whole words, `word*` matches words starting with `word`,
+    /// `"some words"` matches the words next to each other. `folder` limits
+    /// the search to the files below it.
+    pub fn search(
+        &self,
+        workdir: &Path,
+        query: &str,
+        folder: Option<&str>,
+        limit: usize,
+    ) -> Vec<SearchResult> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() {
+            return Vec::new();
+        }
+
+        let folder = folder
+            .map(|folder| folder.trim_matches('/'))
+            .filter(|folder| !folder.is_empty())
+            .map(|folder| format!("{folder}/"));
+        let in_folder = |path: &str| folder.as_ref().is_none_or(|f| path.starts_with(f));
+
+        let total = self.files.len().max(1) as f64;
+        // File -> (score, first match)
+        let mut matches: Option<HashMap<&str, (f64, u32)>> = None;
+
+        for clause in &clauses {
+            let hits = self.hits(clause);
+            let idf = (1.0 + total / hits.len().max(1) as f64).ln();
+
+            let mut next = HashMap::new();
+            for (path, hit) in hits {
+                if !in_folder(path) {
+                    continue;
+                }
+
+                let (score, first) = match &matches {
+                    // Only files that matched the previous clauses
+                    Some(matches) => match matches.get(path) {
+                        Some(&(score, first)) => (score, first.min(hit.first)),
+                        None => continue,
+                    },
+                    None => (0.0, hit.first),
+                };
+
+                let weight = 1.0 + (hit.count as f64).ln();
+                next.insert(path, (score + weight * idf, first));
+            }
+            matches = Some(next);
+        }
+
+        let mut results: Vec<(&str, f64, u32)> = matches
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(path, (score, first))| (path, score, first))
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        results.truncate(limit);
+
+        results
+            .into_iter()
+            .map(|(path, score, first)| {
+                let (line, snippet) = snippet(workdir, path, first).unwrap_or_default();
+                SearchResult {
+                    path: path.to_string(),
+                    score,
+                    line,
+                    snippet,
+                }
+            })
+            .collect()
+    }
+
+    fn hits(&self, clause: &Clause) -> HashMap<&str, Hit> {
+        let mut hits: HashMap<&str, Hit> = HashMap::new();
+
+        match clause {
+            Clause::Term(term) => {
+                for (path, positions) in self.postings.get(term).into_iter().flatten() {
+                    hits.insert(
+                        path,
+                        Hit {
+                            count: positions.len(),
+                            first: positions[0],
+                        },
+                    );
+                }
+            }
+            Clause::Prefix(prefix) => {
+                let terms = self
+                    .postings
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()));
+
+                for (_, files) in terms {
+                    for (path, positions) in files {
+                        let hit = hits.entry(path).or_insert(Hit {
+                            count: 0,
+                            first: u32::MAX,
+                        });
+                        hit.count += positions.len();
+                        hit.first = hit.first.min(positions[0]);
+                    }
+                }
+            }
+            Clause::Phrase(terms) => {
+                let Some(postings) = terms
+                    .iter()
+                    .map(|term| self.postings.get(term))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    return hits;
+                };
+
+                for (path, starts) in postings[0] {
+                    let found: Vec<u32> = starts
+                        .iter()
+                        .copied()
+                        .filter(|&start| {
+                            postings[1..].iter().enumerate().all(|(i, files)| {
+                                files.get(path).is_some_and(|positions| {
+                                    positions.binary_search(&(start + i as u32 + 1)).is_ok()
+                                })
+                            })
+                        })
+                        .collect();
+
+                    if let Some(&first) = found.first() {
+                        hits.insert(
+                            path,
+                            Hit {
+                                count: found.len(),
+                                first,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        hits
+    }
+}
+
+/// Content of a supported file in the working tree
+fn read_note(workdir: &Path, path: &str) -> Option<String> {
+    let extension = Path::new(path).extension()?.to_str()?;
+    if !is_extension_supported(extension) {
+        return None;
+    }
+
+    let bytes = fs::read(workdir.join(path)).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Line and surrounding text of the token at `position`
+fn snippet(workdir: &Path, path: &str, position: u32) -> Option<(usize, String)> {
+    let content = read_note(workdir, path)?;
+    let tokens = tokenize(&content);
+    // The file may have changed since it was indexed
+    let token = tokens.get(position as usize).or(tokens.first())?;
+
+    let line = content[..token.start].matches('\n').count() + 1;
+
+    let before: Vec<char> = content[..token.start]
+        .chars()
+        .rev()
+        .take(SNIPPET_CONTEXT + 1)
+        .collect();
+    let after: Vec<char> = content[token.end..]
+        .chars()
+        .take(SNIPPET_CONTEXT + 1)
+        .collect();
+
+    let mut snippet = String::new();
+    if before.len() > SNIPPET_CONTEXT {
+        snippet.push('…');
+    }
+    snippet.extend(before.iter().take(SNIPPET_CONTEXT).rev());
+    snippet.push_str(&content[token.start..token.end]);
+    snippet.extend(after.iter().take(SNIPPET_CONTEXT));
+    if after.len() > SNIPPET_CONTEXT {
+        snippet.push('…');
+    }
+
+    Some((
+        line,
+        snippet.split_whitespace().collect::<Vec<_>>().join(" "),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index(files: &[(&str, &str)]) -> Index {
+        let mut index = Index {
+            head: Some(Oid::zero()),
+            ..Default::default()
+        };
+        for (path, content) in files {
+            index.add(path, content);
+        }
+        index
+    }
+
+    fn paths(index: &Index, query: &str, folder: Option<&str>) -> Vec<String> {
+        index
+            .search(Path::new("/nonexistent"), query, folder, 10)
+            .into_iter()
+            .map(|result| result.path)
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_words() {
+        let tokens: Vec<String> = tokenize("Buy **Milk**, e-mail Ärger 42x!")
+            .into_iter()
+            .map(|t| t.text)
+            .collect();
+        assert_eq!(tokens, ["buy", "milk", "e", "mail", "ärger", "42x"]);
+    }
+
+    #[test]
+    fn query_syntax() {
+        assert_eq!(
+            parse_query(r#"Milk shop* "green tea" e-mail """#),
+            [
+                Clause::Term("milk".to_string()),
+                Clause::Prefix("shop".to_string()),
+                Clause::Phrase(vec!["green".to_string(), "tea".to_string()]),
+                Clause::Phrase(vec!["e".to_string(), "mail".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn search_ranked() {
+        let index = index(&[
+            ("todo.md", "- [ ] buy milk\n- [ ] call mom"),
+            (
+                "notes/shopping.md",
+                "milk, milk and more milk. Tea: green tea",
+            ),
+            ("notes/tea.md", "Tea is green, the tea is hot"),
+        ]);
+
+        // More occurrences rank higher
+        assert_eq!(
+            paths(&index, "milk", None),
+            ["notes/shopping.md", "todo.md"]
+        );
+        // All words have to match
+        assert_eq!(paths(&index, "milk tea", None), ["notes/shopping.md"]);
+        assert_eq!(paths(&index, "mor*", None), ["notes/shopping.md"]);
+        // Words have to be next to each other in a phrase
+        assert_eq!(paths(&index, "\"green tea\"", None), ["notes/shopping.md"]);
+        assert_eq!(paths(&index, "tea", Some("notes/")).len(), 2);
+        assert_eq!(paths(&index, "milk", Some("todo")), Vec::<String>::new());
+        assert!(paths(&index, "coffee", None).is_empty());
+    }
+
+    #[test]
+    fn remove_file() {
+        let mut index = index(&[("a.md", "apple banana"), ("b.md", "banana")]);
+        index.add("a.md", "cherry");
+        index.remove("b.md");
+
+        assert!(paths(&index, "banana", None).is_empty());
+        assert_eq!(paths(&index, "cherry", None), ["a.md"]);
+        assert_eq!(index.postings.len(), 1);
+    }
+
+    #[test]
+    fn index_roundtrip() {
+        let mut index = index(&[("a.md", "apple banana"), ("notes/b c.md", "")]);
+        index.dirty.insert("a.md".to_string());
+
+        let content = index.serialize().unwrap();
+        assert_eq!(Index::parse(&content), Some(index));
+
+        assert_eq!(
+            Index::parse(&content.replace("version 1", "version 0")),
+            None
+        );
+        assert_eq!(Index::parse(""), None);
+    }
+}
@@ -24,9 +24,13 @@ pub fn install_panic_hook() {
 macro_rules! unwrap_or_log {
     ($res:expr, $name:expr) => {
         match $res {
-            Ok(v) => v,
+            Ok(v) => {
+                $crate::error::clear_last_error();
+                v
+            }
             Err(e) => {
                 error!("{}: {}", $name, e);
+                $crate::error::set_last_error(&e);
                 return e.into();
             }
         }
@@ -34,9 +38,13 @@ macro_rules! unwrap_or_log {
 
     ($res:expr, $name:expr, $ret:expr) => {
         match $res {
-            Ok(v) => v,
+            Ok(v) => {
+                $crate::error::clear_last_error();
+                v
+            }
             Err(e) => {
                 error!("{}: {}", $name, e);
+                $crate::error::set_last_error(&e);
                 return $ret;
             }
         }